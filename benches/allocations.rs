@@ -0,0 +1,78 @@
+//! Count the allocations made while parsing, which the time benchmarks do not
+//! show.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use libyaml_safer::{EventData, Parser};
+
+/// The number of allocations and reallocations made so far.
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// The system allocator, counting the allocations in [`ALLOCATIONS`].
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Count the allocations made while parsing every event of the input.
+fn parse_allocations(input: &str) -> u64 {
+    let start = ALLOCATIONS.load(Ordering::Relaxed);
+    let mut parser = Parser::from_str(input);
+    black_box(parser.count_documents().unwrap());
+    drop(parser);
+    ALLOCATIONS.load(Ordering::Relaxed) - start
+}
+
+fn main() {
+    const DOCUMENTS: u64 = 1000;
+    let document = "--- !!map\nname: !e!name a\ntags: !!seq [!!str x, !e!y y]\nsize: !!int 1\n";
+    let one_directive = format!("%TAG !e! tag:example.com,2000:\n{document}");
+    let two_directives = format!("%TAG !f! tag:example.com,2001:\n{one_directive}");
+    let one = parse_allocations(&one_directive.repeat(DOCUMENTS as usize));
+    let two = parse_allocations(&two_directives.repeat(DOCUMENTS as usize));
+    println!(
+        "parse tagged documents: {} allocations per document",
+        one / DOCUMENTS
+    );
+    // The scanner allocates the handle and the prefix of the extra directive,
+    // growing the prefix as it is scanned, while the parser reuses its copy
+    // of the directive from the previous document.
+    let per_directive = (two - one) / DOCUMENTS;
+    println!("parse %TAG directive: {per_directive} allocations");
+    assert!(per_directive <= 5, "%TAG directives are copied");
+
+    let scalars = "- name: the quick brown fox\n  id: item-0001-abcdef\n  url: https://example.com/path/to/resource\n".repeat(2000);
+    println!(
+        "parse plain scalars: {} allocations",
+        parse_allocations(&scalars)
+    );
+    let start = ALLOCATIONS.load(Ordering::Relaxed);
+    let mut parser = Parser::from_str(&scalars);
+    while !matches!(
+        parser.parse_borrowed().unwrap().0.data,
+        EventData::StreamEnd
+    ) {}
+    drop(parser);
+    println!(
+        "parse plain scalars borrowed: {} allocations",
+        ALLOCATIONS.load(Ordering::Relaxed) - start
+    );
+}
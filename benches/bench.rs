@@ -1,7 +1,9 @@
 use std::mem::MaybeUninit;
 
 use criterion::{criterion_group, criterion_main, Criterion};
-use libyaml_safer::{Document, Emitter, Parser};
+use libyaml_safer::{
+    Document, Emitter, Event, EventData, LoadArena, Parser, ScalarStyle, SequenceStyle,
+};
 use unsafe_libyaml::*;
 
 static VERY_LARGE_YAML: &[u8] = include_bytes!("very_large.yml");
@@ -39,6 +41,60 @@ pub fn parser(c: &mut Criterion) {
         })
     });
 
+    c.bench_function("libyaml-safer load small documents", |b| {
+        let input = "---\nname: a\ntags: [x, y]\nsize: 1\n".repeat(1000);
+        b.iter(|| {
+            let mut parser = Parser::from_str(&input);
+            while !Document::load(&mut parser).unwrap().nodes.is_empty() {}
+        })
+    });
+
+    c.bench_function("libyaml-safer load small documents with arena", |b| {
+        let input = "---\nname: a\ntags: [x, y]\nsize: 1\n".repeat(1000);
+        let mut arena = LoadArena::new();
+        b.iter(|| {
+            let mut parser = Parser::from_str(&input);
+            loop {
+                let doc = Document::load_with_arena(&mut parser, &mut arena).unwrap();
+                if doc.nodes.is_empty() {
+                    break;
+                }
+                arena.recycle(doc);
+            }
+        })
+    });
+
+    c.bench_function("libyaml-safer parse tagged documents", |b| {
+        let input = "%TAG !e! tag:example.com,2000:\n--- !!map\nname: !e!name a\ntags: !!seq [!!str x, !e!y y]\nsize: !!int 1\n".repeat(1000);
+        b.iter(|| {
+            let mut parser = Parser::from_str(&input);
+            parser.count_documents().unwrap()
+        })
+    });
+
+    c.bench_function("libyaml-safer parse plain scalars", |b| {
+        let input = "- name: the quick brown fox\n  id: item-0001-abcdef\n  url: https://example.com/path/to/resource\n".repeat(2000);
+        b.iter(|| {
+            let mut parser = Parser::from_str(&input);
+            parser.count_documents().unwrap()
+        })
+    });
+
+    c.bench_function("libyaml-safer parse plain scalars borrowed", |b| {
+        let input = "- name: the quick brown fox\n  id: item-0001-abcdef\n  url: https://example.com/path/to/resource\n".repeat(2000);
+        b.iter(|| {
+            let mut parser = Parser::from_str(&input);
+            let mut len = 0;
+            loop {
+                let (event, value) = parser.parse_borrowed().unwrap();
+                if let EventData::StreamEnd = event.data {
+                    break len;
+                }
+                len += value.len();
+            }
+        })
+    });
+
     c.bench_function("libyaml-safer emit large", |b| {
         // output shouldn't be much larger than the input, but just to be safe...
         let mut buffer = Vec::with_capacity(VERY_LARGE_YAML.len());
@@ -64,6 +120,48 @@ pub fn parser(c: &mut Criterion) {
         });
     });
 
+    c.bench_function("libyaml-safer emit long strings", |b| {
+        let value = "the quick brown fox jumps over the lazy dog ".repeat(20);
+        let value = value.trim_end();
+        let mut buffer = Vec::with_capacity(1000 * (value.len() + 3) + 64);
+
+        b.iter(|| {
+            buffer.clear();
+            let mut emitter = Emitter::new();
+            emitter.set_output(&mut buffer);
+            emitter.set_width(-1);
+            emitter
+                .emit(Event::stream_start(Default::default()))
+                .unwrap();
+            emitter
+                .emit(Event::document_start(None, &[], true))
+                .unwrap();
+            emitter
+                .emit(Event::sequence_start(
+                    None,
+                    None,
+                    true,
+                    SequenceStyle::Block,
+                ))
+                .unwrap();
+            for _ in 0..1000 {
+                emitter
+                    .emit(Event::scalar(
+                        None,
+                        None,
+                        value,
+                        true,
+                        true,
+                        ScalarStyle::Any,
+                    ))
+                    .unwrap();
+            }
+            emitter.emit(Event::sequence_end()).unwrap();
+            emitter.emit(Event::document_end(true)).unwrap();
+            emitter.emit(Event::stream_end()).unwrap();
+        });
+    });
+
     c.bench_function("unsafe-libyaml emit large", |b| {
         // output shouldn't be much larger than the input, but just to be safe...
         let mut buffer = vec![0; VERY_LARGE_YAML.len() * 2];
@@ -0,0 +1,107 @@
+//! Character classes of the YAML specification, as used by the scanner and
+//! the emitter.
+//!
+//! These are the definitions this crate uses, so tools built on top of it can
+//! classify characters the same way. Production names refer to the YAML 1.2
+//! specification.
+
+use crate::macros;
+
+/// Check if the character is printable and can be written as is in any
+/// scalar style.
+///
+/// This is the `c-printable` production, excluding the tab, the carriage
+/// return, the next line character (U+0085) and the byte order mark (U+FEFF),
+/// which the emitter always escapes.
+pub fn is_printable(ch: char) -> bool {
+    macros::is_printable(ch)
+}
+
+/// Check if the character is a line break.
+///
+/// This is the `b-char` production, extended with the next line (U+0085),
+/// line separator (U+2028) and paragraph separator (U+2029) characters, which
+/// YAML 1.1 treats as line breaks.
+pub fn is_break(ch: char) -> bool {
+    macros::is_break(ch)
+}
+
+/// Check if the character is a space or a tab, the `s-white` production.
+pub fn is_blank(ch: char) -> bool {
+    macros::is_blank(ch)
+}
+
+/// Check if the character is a space, a tab or a line break.
+pub fn is_blank_or_break(ch: char) -> bool {
+    macros::is_blankz(ch)
+}
+
+/// Check if the character is an ASCII letter or digit, `_` or `-`.
+///
+/// This is the `ns-word-char` production extended with `_`, which is the set
+/// of characters the scanner accepts in anchor names, tag handles and
+/// directive names.
+pub fn is_word_char(ch: char) -> bool {
+    macros::is_alpha(ch)
+}
+
+/// Check if the character is a flow collection indicator, the
+/// `c-flow-indicator` production: `,`, `[`, `]`, `{` or `}`.
+pub fn is_flow_indicator(ch: char) -> bool {
+    matches!(ch, ',' | '[' | ']' | '{' | '}')
+}
+
+/// Check if the character is an indicator, the `c-indicator` production.
+///
+/// A plain scalar cannot start with an indicator, except for `-`, `?` and `:`
+/// followed by a non-space character.
+pub fn is_indicator(ch: char) -> bool {
+    matches!(
+        ch,
+        '-' | '?'
+            | ':'
+            | ','
+            | '['
+            | ']'
+            | '{'
+            | '}'
+            | '#'
+            | '&'
+            | '*'
+            | '!'
+            | '|'
+            | '>'
+            | '\''
+            | '"'
+            | '%'
+            | '@'
+            | '`'
+    )
+}
+
+/// Check if the character may appear in an anchor or alias name.
+///
+/// The `ns-anchor-char` production allows any non-space character except the
+/// flow indicators, but this crate only accepts anchors made of
+/// [word characters](is_word_char).
+pub fn is_anchor_char(ch: char) -> bool {
+    is_word_char(ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes() {
+        assert!(is_printable('a') && is_printable('\u{e9}') && is_printable('\n'));
+        assert!(!is_printable('\t') && !is_printable('\u{feff}') && !is_printable('\x07'));
+        assert!(is_break('\r') && is_break('\u{2028}') && !is_break(' '));
+        assert!(is_blank('\t') && !is_blank('\n'));
+        assert!(is_blank_or_break('\n') && !is_blank_or_break('a'));
+        assert!(is_word_char('_') && is_word_char('-') && !is_word_char('.'));
+        assert!(is_flow_indicator('{') && !is_flow_indicator(':'));
+        assert!(is_indicator(':') && is_indicator('`') && !is_indicator('.'));
+        assert!(is_anchor_char('a') && !is_anchor_char('['));
+    }
+}
@@ -1,10 +1,26 @@
+use alloc::{
+    collections::BTreeSet,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::cmp::Ordering;
+
+use crate::chars::is_anchor_char;
+use crate::event::EventSource;
+use crate::resolver;
 use crate::{
-    AliasData, Anchors, Emitter, Error, Event, EventData, MappingStyle, Mark, Parser, Result,
-    ScalarStyle, SequenceStyle, TagDirective, VersionDirective, DEFAULT_MAPPING_TAG,
-    DEFAULT_SCALAR_TAG, DEFAULT_SEQUENCE_TAG,
+    AliasData, DuplicateAnchorPolicy, Emitter, Encoding, Error, Event, EventData, MappingStyle,
+    Mark, NodeKind, Parser, Result, ScalarStyle, SequenceStyle, TagDirective, VersionDirective,
+    BOOL_TAG, DEFAULT_MAPPING_TAG, DEFAULT_SCALAR_TAG, DEFAULT_SEQUENCE_TAG, FLOAT_TAG, INT_TAG,
+    NULL_TAG, SET_TAG,
 };
 
 /// The document structure.
+///
+/// A document owns all of its nodes, tags and directives, and holds no
+/// reference to the parser it was loaded with, so it is [`Send`] and
+/// [`Sync`]: a document can be loaded on one thread and processed on another.
 #[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct Document {
@@ -22,6 +38,12 @@ pub struct Document {
     pub start_mark: Mark,
     /// The end of the document.
     pub end_mark: Mark,
+    /// The anchors defined in the document and the indices of the nodes they
+    /// are attached to, in the order they were defined.
+    ///
+    /// Nodes keep these anchors when the document is dumped, see
+    /// [`Document::set_anchor()`].
+    pub anchors: Vec<(String, i32)>,
 }
 
 /// The node structure.
@@ -32,6 +54,9 @@ pub struct Node {
     pub data: NodeData,
     /// The node tag.
     pub tag: Option<String>,
+    /// Is the node a plain scalar without an explicit tag, whose value is
+    /// resolved with the core schema? See [`Node::as_str()`].
+    pub plain_implicit: bool,
     /// The beginning of the node.
     pub start_mark: Mark,
     /// The end of the node.
@@ -80,6 +105,553 @@ pub struct NodePair {
     pub value: i32,
 }
 
+/// The pairs of nodes compared by [`Document::semantic_eq()`].
+#[derive(Default)]
+struct Comparison {
+    /// The pairs of collections being compared.
+    in_progress: Vec<(i32, i32)>,
+    /// The pairs of collections found equal, in the order they were found.
+    equal: Vec<(i32, i32)>,
+    /// The pairs of `equal`, for lookups.
+    equal_set: BTreeSet<(i32, i32)>,
+    /// The length of `equal` when each comparison in progress started.
+    starts: Vec<usize>,
+}
+
+impl Comparison {
+    fn start(&mut self, pair: (i32, i32)) {
+        self.in_progress.push(pair);
+        self.starts.push(self.equal.len());
+    }
+
+    fn finish(&mut self, pair: (i32, i32), equal: bool) {
+        self.in_progress.pop();
+        let start = self.starts.pop().unwrap_or_default();
+        if equal {
+            self.equal.push(pair);
+            self.equal_set.insert(pair);
+        } else {
+            // The pairs found equal since the start may only be equal if
+            // this pair is, as a recursive alias assumes.
+            for pair in self.equal.drain(start..) {
+                self.equal_set.remove(&pair);
+            }
+        }
+    }
+}
+
+/// Options for [`Document::semantic_eq()`].
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct SemanticEqOptions {
+    /// Compare mappings regardless of the order of their pairs.
+    pub ignore_key_order: bool,
+}
+
+/// Options for [`Document::merge_defaults()`].
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct MergeOptions {
+    /// Append the items of a default sequence to the sequence replacing it,
+    /// instead of dropping them.
+    pub concat_sequences: bool,
+}
+
+/// A step in the path to a node, used by [`Document::load_path()`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PathSegment<'a> {
+    /// The value of the mapping key with this scalar value.
+    Key(&'a str),
+    /// The sequence item at this index.
+    Index(usize),
+}
+
+/// The outcome of [`Document::load_path()`].
+#[derive(Clone, Debug)]
+pub enum PathLookup {
+    /// The document had a node at the path, loaded as the root of this
+    /// document.
+    Found(Document),
+    /// The document had no node at the path, and was skipped.
+    NotFound,
+    /// The stream has no more documents.
+    EndOfStream,
+}
+
+/// The value of a scalar resolved using the core schema.
+enum Resolved<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(&'a str),
+}
+
+impl<'a> Resolved<'a> {
+    /// Check if two resolved values are equal, treating NaN as equal to
+    /// itself.
+    #[allow(clippy::float_cmp)]
+    fn same(&self, other: &Resolved<'_>) -> bool {
+        match (self, other) {
+            (Resolved::Null, Resolved::Null) => true,
+            (Resolved::Bool(a), Resolved::Bool(b)) => a == b,
+            (Resolved::Int(a), Resolved::Int(b)) => a == b,
+            (Resolved::Float(a), Resolved::Float(b)) => a == b || a.is_nan() && b.is_nan(),
+            (Resolved::Str(a), Resolved::Str(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    fn from_plain(value: &'a str) -> Self {
+        if resolver::is_null(value) {
+            Resolved::Null
+        } else if let Some(value) = resolver::parse_bool(value) {
+            Resolved::Bool(value)
+        } else if let Some(value) = resolver::parse_int(value) {
+            Resolved::Int(value)
+        } else if let Some(value) = resolver::parse_float(value) {
+            Resolved::Float(value)
+        } else {
+            Resolved::Str(value)
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Anchors {
+    /// The number of references.
+    references: i32,
+    /// The anchor, if the node has one.
+    anchor: Option<String>,
+    /// If the node has been emitted?
+    serialized: bool,
+}
+
+/// A borrowed view of a scalar node, usable as a key in hashed or ordered
+/// collections.
+///
+/// Two keys are equal only if both their values and their resolved tags are
+/// equal. A plain scalar without an explicit tag has the tag the core schema
+/// resolves it to, see [`Node::as_str()`], so `1` and `!!int 1` are the same
+/// key, while `'1'` and `!!str 1` are strings and distinct from both. The
+/// scalar style and the node marks do not otherwise participate. Keys are
+/// ordered by value first and by tag second.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub struct ScalarKey<'a> {
+    /// The scalar value.
+    pub value: &'a str,
+    /// The resolved tag of the node.
+    pub tag: &'a str,
+}
+
+/// A scalar node, see [`Node::as_scalar()`].
+///
+/// It dereferences to the value of the scalar, so it can be used wherever a
+/// `&str` is expected. The value is the one in the input, whatever the tag.
+#[derive(Copy, Clone, Debug)]
+#[non_exhaustive]
+pub struct ScalarNode<'a> {
+    /// The scalar value.
+    pub value: &'a str,
+    /// The scalar style.
+    pub style: ScalarStyle,
+    /// The node tag.
+    pub tag: Option<&'a str>,
+}
+
+impl core::ops::Deref for ScalarNode<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.value
+    }
+}
+
+impl AsRef<str> for ScalarNode<'_> {
+    fn as_ref(&self) -> &str {
+        self.value
+    }
+}
+
+/// Allocations recycled between loaded documents.
+///
+/// Loading a document allocates the node list, the item and pair lists of
+/// every collection, a tag string for every node without an explicit tag,
+/// the list of anchors, and the stack of collections being loaded. When
+/// loading many small documents, pass the same arena to
+/// [`Document::load_with_arena()`] and hand each document back with
+/// [`LoadArena::recycle()`] once it is no longer needed, so that these
+/// allocations are reused instead of being made again.
+#[derive(Default)]
+pub struct LoadArena {
+    nodes: Vec<Node>,
+    strings: Vec<String>,
+    items: Vec<Vec<NodeItem>>,
+    pairs: Vec<Vec<NodePair>>,
+    anchors: Vec<(String, i32)>,
+    ctx: Vec<i32>,
+}
+
+impl LoadArena {
+    /// Create an empty arena.
+    pub fn new() -> LoadArena {
+        LoadArena::default()
+    }
+
+    /// Return the allocations of a document to the arena.
+    pub fn recycle(&mut self, mut document: Document) {
+        // Every loaded node takes at most one tag string, so keeping more than
+        // that would only grow the arena.
+        let max_strings = document.nodes.len();
+        for node in document.nodes.drain(..) {
+            if let Some(mut tag) = node.tag {
+                tag.clear();
+                self.strings.push(tag);
+            }
+            match node.data {
+                NodeData::NoNode | NodeData::Scalar { .. } => {}
+                NodeData::Sequence { mut items, .. } => {
+                    items.clear();
+                    self.items.push(items);
+                }
+                NodeData::Mapping { mut pairs, .. } => {
+                    pairs.clear();
+                    self.pairs.push(pairs);
+                }
+            }
+        }
+        self.strings.truncate(max_strings);
+        if document.nodes.capacity() > self.nodes.capacity() {
+            self.nodes = document.nodes;
+        }
+        if document.anchors.capacity() > self.anchors.capacity() {
+            document.anchors.clear();
+            self.anchors = document.anchors;
+        }
+    }
+
+    fn take_nodes(&mut self) -> Vec<Node> {
+        let mut nodes = core::mem::take(&mut self.nodes);
+        nodes.reserve(16);
+        nodes
+    }
+
+    fn tag(&mut self, tag: &str) -> String {
+        let mut string = self.strings.pop().unwrap_or_default();
+        string.push_str(tag);
+        string
+    }
+
+    fn items(&mut self) -> Vec<NodeItem> {
+        self.items.pop().unwrap_or_else(|| Vec::with_capacity(16))
+    }
+
+    fn pairs(&mut self) -> Vec<NodePair> {
+        self.pairs.pop().unwrap_or_else(|| Vec::with_capacity(16))
+    }
+}
+
+impl Node {
+    /// Get a hashable and ordered key for a scalar node.
+    ///
+    /// Returns `None` if the node is not a scalar. Collection nodes only hold
+    /// the ids of their children, so they cannot be compared without the
+    /// document that owns them.
+    pub fn scalar_key(&self) -> Option<ScalarKey<'_>> {
+        let NodeData::Scalar { value, style } = &self.data else {
+            return None;
+        };
+        let plain = self.plain_implicit && matches!(style, ScalarStyle::Any | ScalarStyle::Plain);
+        let tag = match self.tag.as_deref() {
+            None | Some(DEFAULT_SCALAR_TAG) if plain => {
+                resolver::plain_tag(value).unwrap_or(DEFAULT_SCALAR_TAG)
+            }
+            None => DEFAULT_SCALAR_TAG,
+            Some(tag) => tag,
+        };
+        Some(ScalarKey { value, tag })
+    }
+
+    /// Get a scalar node as a [`ScalarNode`], which dereferences to its value.
+    ///
+    /// Unlike [`Node::as_str()`], the value is not resolved, so this also
+    /// returns the scalars `123` and `!!int 123`. Returns `None` if the node
+    /// is not a scalar.
+    pub fn as_scalar(&self) -> Option<ScalarNode<'_>> {
+        let NodeData::Scalar { value, style } = &self.data else {
+            return None;
+        };
+        Some(ScalarNode {
+            value,
+            style: *style,
+            tag: self.tag.as_deref(),
+        })
+    }
+
+    /// Get the value of a scalar node that resolves to a boolean.
+    ///
+    /// See [`Node::as_str()`] for how scalars are resolved.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.resolve()? {
+            Resolved::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value of a scalar node that resolves to an integer.
+    ///
+    /// Hexadecimal (`0x1F`) and octal (`0o17`) integers, and integers with `_`
+    /// separators (`1_000`), are accepted. Returns `None` if the integer does
+    /// not fit in an `i64`.
+    ///
+    /// See [`Node::as_str()`] for how scalars are resolved.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self.resolve()? {
+            Resolved::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value of a scalar node that resolves to a floating-point number
+    /// or an integer.
+    ///
+    /// See [`Node::as_str()`] for how scalars are resolved.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.resolve()? {
+            #[allow(clippy::cast_precision_loss)]
+            Resolved::Int(value) => Some(value as f64),
+            Resolved::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value of a scalar node that resolves to a string.
+    ///
+    /// Scalars are resolved using the YAML 1.2 core schema. A plain scalar
+    /// without an explicit tag is resolved from its value, so `123` is an
+    /// integer and `true` is a boolean, while quoted and block scalars, and
+    /// scalars explicitly tagged `!!str`, are always strings. A scalar with an
+    /// explicit `!!null`, `!!bool`, `!!int` or `!!float` tag must have a
+    /// value matching that type. Scalars with other tags, and nodes that are
+    /// not scalars, do not resolve to any of these types.
+    pub fn as_str(&self) -> Option<&str> {
+        match self.resolve()? {
+            Resolved::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn resolve(&self) -> Option<Resolved<'_>> {
+        let NodeData::Scalar { value, style } = &self.data else {
+            return None;
+        };
+        let plain = self.plain_implicit && matches!(style, ScalarStyle::Any | ScalarStyle::Plain);
+        match self.tag.as_deref() {
+            None | Some(DEFAULT_SCALAR_TAG) if plain => Some(Resolved::from_plain(value)),
+            None | Some(DEFAULT_SCALAR_TAG) => Some(Resolved::Str(value)),
+            Some(NULL_TAG) => resolver::is_null(value).then_some(Resolved::Null),
+            Some(BOOL_TAG) => resolver::parse_bool(value).map(Resolved::Bool),
+            Some(INT_TAG) => resolver::parse_int(value).map(Resolved::Int),
+            Some(FLOAT_TAG) => resolver::parse_float(value).map(Resolved::Float),
+            Some(_) => None,
+        }
+    }
+
+    fn conversion_error(&self, problem: &'static str) -> Error {
+        Error::composer("", Mark::default(), problem, self.start_mark)
+    }
+}
+
+/// Get the problem of a failed conversion, naming the kind of node found.
+///
+/// The `collection` form is used when a collection was expected, so that any
+/// scalar found is of another kind rather than of another type.
+macro_rules! expected {
+    ($expected:literal, $node:expr) => {
+        expected!($expected, $node, "a scalar of another type")
+    };
+    (collection $expected:literal, $node:expr) => {
+        expected!($expected, $node, "a scalar")
+    };
+    ($expected:literal, $node:expr, $scalar:literal) => {
+        match $node.data {
+            NodeData::NoNode => concat!("expected ", $expected, ", found an empty node"),
+            NodeData::Scalar { .. } => concat!("expected ", $expected, ", found ", $scalar),
+            NodeData::Sequence { .. } => concat!("expected ", $expected, ", found a sequence"),
+            NodeData::Mapping { .. } => concat!("expected ", $expected, ", found a mapping"),
+        }
+    };
+}
+
+/// Converts a scalar node that resolves to a string, see [`Node::as_str()`].
+impl TryFrom<&Node> for String {
+    type Error = Error;
+
+    fn try_from(node: &Node) -> Result<Self> {
+        node.as_str()
+            .map(String::from)
+            .ok_or_else(|| node.conversion_error(expected!("a string", node)))
+    }
+}
+
+/// Converts a scalar node that resolves to an integer, see [`Node::as_i64()`].
+impl TryFrom<&Node> for i64 {
+    type Error = Error;
+
+    fn try_from(node: &Node) -> Result<Self> {
+        node.as_i64()
+            .ok_or_else(|| node.conversion_error(expected!("an integer", node)))
+    }
+}
+
+/// Converts a scalar node that resolves to a number, see [`Node::as_f64()`].
+impl TryFrom<&Node> for f64 {
+    type Error = Error;
+
+    fn try_from(node: &Node) -> Result<Self> {
+        node.as_f64()
+            .ok_or_else(|| node.conversion_error(expected!("a number", node)))
+    }
+}
+
+/// Converts a scalar node that resolves to a boolean, see [`Node::as_bool()`].
+impl TryFrom<&Node> for bool {
+    type Error = Error;
+
+    fn try_from(node: &Node) -> Result<Self> {
+        node.as_bool()
+            .ok_or_else(|| node.conversion_error(expected!("a boolean", node)))
+    }
+}
+
+/// A node together with the document that owns it.
+///
+/// Collection nodes only hold the ids of their children, so converting them
+/// needs the document, for example to convert a sequence into a [`Vec`].
+#[derive(Copy, Clone, Debug)]
+#[non_exhaustive]
+pub struct NodeRef<'a> {
+    /// The document that owns the node.
+    pub document: &'a Document,
+    /// The node.
+    pub node: &'a Node,
+}
+
+impl TryFrom<NodeRef<'_>> for String {
+    type Error = Error;
+
+    fn try_from(node: NodeRef<'_>) -> Result<Self> {
+        String::try_from(node.node)
+    }
+}
+
+impl TryFrom<NodeRef<'_>> for i64 {
+    type Error = Error;
+
+    fn try_from(node: NodeRef<'_>) -> Result<Self> {
+        i64::try_from(node.node)
+    }
+}
+
+impl TryFrom<NodeRef<'_>> for f64 {
+    type Error = Error;
+
+    fn try_from(node: NodeRef<'_>) -> Result<Self> {
+        f64::try_from(node.node)
+    }
+}
+
+impl TryFrom<NodeRef<'_>> for bool {
+    type Error = Error;
+
+    fn try_from(node: NodeRef<'_>) -> Result<Self> {
+        bool::try_from(node.node)
+    }
+}
+
+impl<'a> NodeRef<'a> {
+    /// Get the value of the first pair of a mapping node whose key resolves
+    /// to the string `key`, see [`Node::as_str()`].
+    ///
+    /// Keys that resolve to other types never match, so `get("1")` does not
+    /// find the value of `1: a`, but finds the value of `'1': a`. Returns
+    /// `None` if the node is not a mapping or has no such key.
+    pub fn get(&self, key: &str) -> Option<NodeRef<'a>> {
+        let NodeData::Mapping { pairs, .. } = &self.node.data else {
+            return None;
+        };
+        let document = self.document;
+        let pair = pairs.iter().find(|pair| {
+            document
+                .get_node(pair.key)
+                .is_some_and(|node| node.as_str() == Some(key))
+        })?;
+        document.node_ref(pair.value)
+    }
+
+    /// Check if a mapping node has a key that resolves to the string `key`,
+    /// see [`NodeRef::get()`].
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Get the items of a set, the keys of a mapping node whose values are
+    /// all null, such as a mapping tagged `!!set`.
+    ///
+    /// Returns an error if the node is not a mapping, or if any of its values
+    /// does not resolve to null.
+    pub fn set_items(&self) -> Result<Vec<NodeRef<'a>>> {
+        let NodeData::Mapping { pairs, .. } = &self.node.data else {
+            return Err(self
+                .node
+                .conversion_error(expected!(collection "a set", self.node)));
+        };
+        let node_ref = |index: i32| {
+            self.document.node_ref(index).ok_or_else(|| {
+                self.node
+                    .conversion_error("found a mapping pair that is not in the document")
+            })
+        };
+        pairs
+            .iter()
+            .map(|pair| {
+                let value = node_ref(pair.value)?.node;
+                if matches!(value.resolve(), Some(Resolved::Null)) {
+                    node_ref(pair.key)
+                } else {
+                    Err(value.conversion_error(expected!("a null set value", value)))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Converts each item of a sequence node.
+impl<'a, T> TryFrom<NodeRef<'a>> for Vec<T>
+where
+    T: TryFrom<NodeRef<'a>, Error = Error>,
+{
+    type Error = Error;
+
+    fn try_from(node: NodeRef<'a>) -> Result<Self> {
+        let NodeData::Sequence { items, .. } = &node.node.data else {
+            return Err(node
+                .node
+                .conversion_error(expected!(collection "a sequence", node.node)));
+        };
+        items
+            .iter()
+            .map(|item| match node.document.node_ref(*item) {
+                Some(item) => T::try_from(item),
+                None => Err(node
+                    .node
+                    .conversion_error("found a sequence item that is not in the document")),
+            })
+            .collect()
+    }
+}
+
 impl Document {
     /// Create a YAML document.
     pub fn new(
@@ -99,6 +671,7 @@ impl Document {
             end_implicit,
             start_mark: Mark::default(),
             end_mark: Mark::default(),
+            anchors: Vec::new(),
         }
     }
 
@@ -116,6 +689,17 @@ impl Document {
         self.nodes.get(index as usize - 1)
     }
 
+    /// Get a node of a YAML document together with the document, for
+    /// converting it with [`TryFrom`].
+    ///
+    /// Returns `None` if `index` is out of range.
+    pub fn node_ref(&self, index: i32) -> Option<NodeRef<'_>> {
+        Some(NodeRef {
+            document: self,
+            node: self.get_node(index)?,
+        })
+    }
+
     /// Get the root of a YAML document node.
     ///
     /// The root object is the first object added to the document.
@@ -127,9 +711,41 @@ impl Document {
         self.nodes.get_mut(0)
     }
 
+    /// Iterate over the items of the root node.
+    ///
+    /// Returns `None` if the document is empty or the root node is not a
+    /// sequence.
+    pub fn root_sequence(&self) -> Option<impl Iterator<Item = &Node> + '_> {
+        let NodeData::Sequence { items, .. } = &self.nodes.first()?.data else {
+            return None;
+        };
+        Some(
+            items
+                .iter()
+                .map(move |item| &self.nodes[*item as usize - 1]),
+        )
+    }
+
+    /// Iterate over the key/value pairs of the root node.
+    ///
+    /// Returns `None` if the document is empty or the root node is not a
+    /// mapping.
+    pub fn root_mapping(&self) -> Option<impl Iterator<Item = (&Node, &Node)> + '_> {
+        let NodeData::Mapping { pairs, .. } = &self.nodes.first()?.data else {
+            return None;
+        };
+        Some(pairs.iter().map(move |pair| {
+            (
+                &self.nodes[pair.key as usize - 1],
+                &self.nodes[pair.value as usize - 1],
+            )
+        }))
+    }
+
     /// Create a SCALAR node and attach it to the document.
     ///
-    /// The `style` argument may be ignored by the emitter.
+    /// The `style` argument may be ignored by the emitter. A plain scalar
+    /// without a tag is resolved from its value, see [`Node::as_str()`].
     ///
     /// Returns the node id or 0 on error.
     #[must_use]
@@ -139,6 +755,7 @@ impl Document {
             line: 0_u64,
             column: 0_u64,
         };
+        let plain_implicit = tag.is_none();
         let tag = tag.unwrap_or(DEFAULT_SCALAR_TAG);
         let tag_copy = String::from(tag);
         let value_copy = String::from(value);
@@ -148,6 +765,7 @@ impl Document {
                 style,
             },
             tag: Some(tag_copy),
+            plain_implicit,
             start_mark: mark,
             end_mark: mark,
         };
@@ -155,6 +773,48 @@ impl Document {
         self.nodes.len() as i32
     }
 
+    /// Create a `!!float` SCALAR node and attach it to the document.
+    ///
+    /// Infinities and NaN are written as `.inf`, `-.inf` and `.nan`, the
+    /// spellings of the core schema, rather than as Rust formats them.
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    #[must_use]
+    pub fn add_float(&mut self, value: f64) -> i32 {
+        let value = resolver::format_float(value);
+        self.add_scalar(Some(FLOAT_TAG), &value, ScalarStyle::Plain)
+    }
+
+    /// Create an `!!int` SCALAR node written in the given radix and attach it
+    /// to the document.
+    ///
+    /// Radix 16 is written as `0x1F` and radix 8 as `0o755`, the spellings of
+    /// the core schema, so [`Node::as_i64()`] reads the value back. The core
+    /// schema has neither binary integers nor signed hexadecimal or octal
+    /// ones, so radix 2 and negative values outside radix 10 are rejected.
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    pub fn yaml_document_add_int_radix(&mut self, value: i64, radix: u32) -> Result<i32> {
+        let spelling = match radix {
+            10 => value.to_string(),
+            8 | 16 if value < 0 => {
+                return Err(Error::emitter(
+                    "cannot write a negative integer in radix 8 or 16",
+                ));
+            }
+            8 => alloc::format!("0o{value:o}"),
+            16 => alloc::format!("0x{value:X}"),
+            2 => {
+                return Err(Error::emitter(
+                    "cannot write an integer in radix 2, the core schema has no binary integers",
+                ));
+            }
+            _ => return Err(Error::emitter("radix must be 2, 8, 10 or 16")),
+        };
+        debug_assert_eq!(resolver::parse_int(&spelling), Some(value));
+        Ok(self.add_scalar(Some(INT_TAG), &spelling, ScalarStyle::Plain))
+    }
+
     /// Create a SEQUENCE node and attach it to the document.
     ///
     /// The `style` argument may be ignored by the emitter.
@@ -174,6 +834,7 @@ impl Document {
         let node = Node {
             data: NodeData::Sequence { items, style },
             tag: Some(tag_copy),
+            plain_implicit: false,
             start_mark: mark,
             end_mark: mark,
         };
@@ -200,6 +861,7 @@ impl Document {
         let node = Node {
             data: NodeData::Mapping { pairs, style },
             tag: Some(tag_copy),
+            plain_implicit: false,
             start_mark: mark,
             end_mark: mark,
         };
@@ -208,6 +870,74 @@ impl Document {
         self.nodes.len() as i32
     }
 
+    /// Create a MAPPING node with the given pairs of key and value node ids,
+    /// in order, and attach it to the document.
+    ///
+    /// The `style` argument may be ignored by the emitter.
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    #[must_use]
+    pub fn add_mapping_from<I>(&mut self, tag: Option<&str>, style: MappingStyle, pairs: I) -> i32
+    where
+        I: IntoIterator<Item = (i32, i32)>,
+    {
+        let mapping = self.add_mapping(tag, style);
+        for (key, value) in pairs {
+            self.yaml_document_append_mapping_pair(mapping, key, value);
+        }
+        mapping
+    }
+
+    /// Create a MAPPING node with a scalar key and a scalar value for each
+    /// pair, in order, and attach it to the document.
+    ///
+    /// The `style` argument may be ignored by the emitter. The scalars are
+    /// tagged `!!str` and written in the style chosen by the emitter.
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    #[must_use]
+    pub fn add_string_mapping<I, K, V>(
+        &mut self,
+        tag: Option<&str>,
+        style: MappingStyle,
+        pairs: I,
+    ) -> i32
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mapping = self.add_mapping(tag, style);
+        for (key, value) in pairs {
+            let key = self.add_scalar(None, key.as_ref(), ScalarStyle::Any);
+            let value = self.add_scalar(None, value.as_ref(), ScalarStyle::Any);
+            self.yaml_document_append_mapping_pair(mapping, key, value);
+        }
+        mapping
+    }
+
+    /// Create a `!!set` MAPPING node with a scalar key for each item and null
+    /// values, and attach it to the document.
+    ///
+    /// The `style` argument may be ignored by the emitter. Use
+    /// [`NodeRef::set_items()`] to get the items of a set.
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    #[must_use]
+    pub fn add_set<I>(&mut self, items: I, style: MappingStyle) -> i32
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let set = self.add_mapping(Some(SET_TAG), style);
+        for item in items {
+            let key = self.add_scalar(None, item.as_ref(), ScalarStyle::Any);
+            let value = self.add_scalar(None, "", ScalarStyle::Plain);
+            self.yaml_document_append_mapping_pair(set, key, value);
+        }
+        set
+    }
+
     /// Add an item to a SEQUENCE node.
     pub fn append_sequence_item(&mut self, sequence: i32, item: i32) {
         assert!(sequence > 0 && sequence as usize - 1 < self.nodes.len());
@@ -239,6 +969,37 @@ impl Document {
         }
     }
 
+    /// Set the anchor of a node, which is used instead of a generated anchor
+    /// when the document is dumped.
+    ///
+    /// Nodes with an anchor are written with it even if they are referenced
+    /// only once. The anchor must be made of
+    /// [anchor characters](crate::chars::is_anchor_char) and must not be the
+    /// anchor of another node. Setting the anchor of a node that already has
+    /// one replaces it.
+    pub fn set_anchor(&mut self, node: i32, anchor: &str) -> Result<()> {
+        assert!(node > 0 && node as usize - 1 < self.nodes.len());
+        if anchor.is_empty() {
+            return Err(Error::emitter("anchor value must not be empty"));
+        }
+        if !anchor.chars().all(is_anchor_char) {
+            return Err(Error::emitter_value(
+                "anchor value must contain alphanumerical characters only",
+                anchor,
+            ));
+        }
+        if self
+            .anchors
+            .iter()
+            .any(|(name, index)| name == anchor && *index != node)
+        {
+            return Err(Error::emitter_value("found duplicate anchor", anchor));
+        }
+        self.anchors.retain(|(_, index)| *index != node);
+        self.anchors.push((anchor.to_string(), node));
+        Ok(())
+    }
+
     /// Parse the input stream and produce the next YAML document.
     ///
     /// Call this function subsequently to produce a sequence of documents
@@ -250,15 +1011,59 @@ impl Document {
     /// An application must not alternate the calls of [`Document::load()`] with
     /// the calls of [`Parser::parse()`]. Doing this will break the parser.
     pub fn load(parser: &mut Parser) -> Result<Document> {
-        let mut document = Document::new(None, &[], false, false);
-        document.nodes.reserve(16);
+        Self::load_with_arena(parser, &mut LoadArena::new())
+    }
+
+    /// Parse the input stream and produce the next YAML document, reusing the
+    /// allocations held by `arena`.
+    ///
+    /// This behaves exactly like [`Document::load()`]. See [`LoadArena`] for
+    /// how to make the allocations available for reuse.
+    pub fn load_with_arena(parser: &mut Parser, arena: &mut LoadArena) -> Result<Document> {
+        Self::load_observed(parser, arena, &mut |_| {})
+    }
+
+    /// Parse the input stream and produce the next YAML document, passing
+    /// every event the loader consumes to `observer`.
+    ///
+    /// This behaves exactly like [`Document::load()`], and lets the events be
+    /// inspected, for example to log them or to record the marks of the
+    /// scalars, without parsing the input twice. The observer sees the
+    /// STREAM-START event before the first document, the events of the
+    /// document from DOCUMENT-START to DOCUMENT-END, and the STREAM-END event
+    /// once there are no more documents.
+    pub fn load_with_observer(
+        parser: &mut Parser,
+        observer: &mut dyn FnMut(&Event),
+    ) -> Result<Document> {
+        Self::load_observed(parser, &mut LoadArena::new(), observer)
+    }
+
+    fn load_observed(
+        parser: &mut Parser,
+        arena: &mut LoadArena,
+        observer: &mut dyn FnMut(&Event),
+    ) -> Result<Document> {
+        parser.error_context_chain.clear();
+        let mut document = Document {
+            nodes: arena.take_nodes(),
+            version_directive: None,
+            tag_directives: Vec::new(),
+            start_implicit: false,
+            end_implicit: false,
+            start_mark: Mark::default(),
+            end_mark: Mark::default(),
+            anchors: Vec::new(),
+        };
 
         if !parser.scanner.stream_start_produced {
             match parser.parse() {
-                Ok(Event {
-                    data: EventData::StreamStart { .. },
-                    ..
-                }) => (),
+                Ok(
+                    event @ Event {
+                        data: EventData::StreamStart { .. },
+                        ..
+                    },
+                ) => observer(&event),
                 Ok(_) => panic!("expected stream start"),
                 Err(err) => {
                     parser.delete_aliases();
@@ -272,13 +1077,14 @@ impl Document {
         let err: Error;
         match parser.parse() {
             Ok(event) => {
+                observer(&event);
                 if let EventData::StreamEnd = &event.data {
                     return Ok(document);
                 }
                 parser.aliases.reserve(16);
-                match document.load_document(parser, event) {
+                match document.load_document(parser, arena, event, observer) {
                     Ok(()) => {
-                        parser.delete_aliases();
+                        document.anchors = parser.take_anchors(core::mem::take(&mut arena.anchors));
                         return Ok(document);
                     }
                     Err(e) => err = e,
@@ -290,70 +1096,252 @@ impl Document {
         Err(err)
     }
 
-    fn load_document(&mut self, parser: &mut Parser, event: Event) -> Result<()> {
-        let mut ctx = vec![];
-        if let EventData::DocumentStart {
+    /// Parse the next YAML document in the input stream, but only load the
+    /// node found at `path`.
+    ///
+    /// Each path segment selects either the value of a mapping key, compared
+    /// with the values of scalar keys, or the item at an index of a sequence.
+    /// The document is parsed only as far as needed to find the node, then
+    /// the rest of it is skipped. Skipped nodes are still parsed, so errors in
+    /// them are reported, but they are never built, so memory use only
+    /// depends on the size of the selected node. The returned document has
+    /// the selected node as its root.
+    ///
+    /// Aliases are not followed: an alias on the way to the node, or as the
+    /// selected node itself, is reported as an error. Aliases in the selected
+    /// node can only refer to anchors defined within the selected node.
+    pub fn load_path(parser: &mut Parser, path: &[PathSegment]) -> Result<PathLookup> {
+        let mut result = Self::load_path_inner(parser, path);
+        if let Ok(PathLookup::Found(document)) = &mut result {
+            document.anchors = parser.take_anchors(Vec::new());
+        }
+        parser.delete_aliases();
+        result
+    }
+
+    fn load_path_inner(parser: &mut Parser, path: &[PathSegment]) -> Result<PathLookup> {
+        parser.error_context_chain.clear();
+        if !parser.scanner.stream_start_produced {
+            let event = parser.parse()?;
+            assert!(
+                matches!(event.data, EventData::StreamStart { .. }),
+                "expected stream start"
+            );
+        }
+        if parser.scanner.stream_end_produced {
+            return Ok(PathLookup::EndOfStream);
+        }
+        let event = parser.parse()?;
+        let EventData::DocumentStart {
             version_directive,
             tag_directives,
             implicit,
         } = event.data
-        {
-            self.version_directive = version_directive;
-            self.tag_directives = tag_directives;
-            self.start_implicit = implicit;
-            self.start_mark = event.start_mark;
-            ctx.reserve(16);
-            if let Err(err) = self.load_nodes(parser, &mut ctx) {
-                ctx.clear();
+        else {
+            return Ok(PathLookup::EndOfStream);
+        };
+        let mut document = Document::new(version_directive, &tag_directives, implicit, false);
+        document.start_mark = event.start_mark;
+
+        let mut node = Some(parser.parse()?);
+        for segment in path {
+            let Some(event) = node else {
+                break;
+            };
+            node = Self::find_child(parser, event, *segment)?;
+        }
+        if let Some(event) = &node {
+            if let EventData::Alias { .. } = event.data {
+                return Err(Self::alias_in_path(event));
+            }
+        }
+        let found = node.is_some();
+        if let Some(mut event) = node {
+            let mut ctx = Vec::with_capacity(16);
+            let mut arena = LoadArena::new();
+            let result = loop {
+                if let Err(err) = document.load_node(parser, &mut arena, event, &mut ctx) {
+                    break Err(err);
+                }
+                if ctx.is_empty() {
+                    break Ok(());
+                }
+                match parser.parse() {
+                    Ok(next) => event = next,
+                    Err(err) => break Err(err),
+                }
+            };
+            if let Err(err) = result {
+                parser.error_context_chain = document.error_context_chain(&ctx);
                 return Err(err);
             }
+        }
+
+        // Skip the rest of the document.
+        loop {
+            let event = parser.parse()?;
+            if let EventData::DocumentEnd { implicit } = event.data {
+                document.end_implicit = implicit;
+                document.end_mark = event.end_mark;
+                break;
+            }
+        }
+        Ok(if found {
+            PathLookup::Found(document)
+        } else {
+            PathLookup::NotFound
+        })
+    }
+
+    fn alias_in_path(event: &Event) -> Error {
+        Error::composer(
+            "",
+            Mark::default(),
+            "found an alias in the path, which cannot be followed",
+            event.start_mark,
+        )
+    }
+
+    /// Find the child of the node starting with `event` selected by `segment`,
+    /// and return its first event. If there is no such child, the whole node
+    /// is skipped. An alias is an error, since its anchored node has already
+    /// been skipped.
+    fn find_child(
+        parser: &mut Parser,
+        event: Event,
+        segment: PathSegment,
+    ) -> Result<Option<Event>> {
+        match (&event.data, segment) {
+            (EventData::MappingStart { .. }, PathSegment::Key(key)) => loop {
+                let event = parser.parse()?;
+                let matches = match &event.data {
+                    EventData::MappingEnd => return Ok(None),
+                    EventData::Scalar { value, .. } => value == key,
+                    _ => false,
+                };
+                Self::skip_node(parser, event)?;
+                let value = parser.parse()?;
+                if matches {
+                    return Ok(Some(value));
+                }
+                Self::skip_node(parser, value)?;
+            },
+            (EventData::SequenceStart { .. }, PathSegment::Index(index)) => {
+                let mut current = 0;
+                loop {
+                    let event = parser.parse()?;
+                    if let EventData::SequenceEnd = event.data {
+                        return Ok(None);
+                    }
+                    if current == index {
+                        return Ok(Some(event));
+                    }
+                    Self::skip_node(parser, event)?;
+                    current += 1;
+                }
+            }
+            (EventData::Alias { .. }, _) => Err(Self::alias_in_path(&event)),
+            _ => {
+                Self::skip_node(parser, event)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Skip the rest of the node starting with `event`.
+    fn skip_node(parser: &mut Parser, event: Event) -> Result<()> {
+        let mut depth = 0_usize;
+        let mut event = event;
+        loop {
+            match event.data {
+                EventData::SequenceStart { .. } | EventData::MappingStart { .. } => depth += 1,
+                EventData::SequenceEnd | EventData::MappingEnd => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return Ok(());
+            }
+            event = parser.parse()?;
+        }
+    }
+
+    fn load_document(
+        &mut self,
+        parser: &mut Parser,
+        arena: &mut LoadArena,
+        event: Event,
+        observer: &mut dyn FnMut(&Event),
+    ) -> Result<()> {
+        if let EventData::DocumentStart {
+            version_directive,
+            tag_directives,
+            implicit,
+        } = event.data
+        {
+            self.version_directive = version_directive;
+            self.tag_directives = tag_directives;
+            self.start_implicit = implicit;
+            self.start_mark = event.start_mark;
+            let mut ctx = core::mem::take(&mut arena.ctx);
+            ctx.reserve(16);
+            let result = self.load_nodes(parser, arena, &mut ctx, observer);
+            if result.is_err() {
+                parser.error_context_chain = self.error_context_chain(&ctx);
+            }
             ctx.clear();
-            Ok(())
+            arena.ctx = ctx;
+            result
         } else {
             panic!("Expected YAML_DOCUMENT_START_EVENT")
         }
     }
 
-    fn load_nodes(&mut self, parser: &mut Parser, ctx: &mut Vec<i32>) -> Result<()> {
+    fn load_nodes(
+        &mut self,
+        parser: &mut Parser,
+        arena: &mut LoadArena,
+        ctx: &mut Vec<i32>,
+        observer: &mut dyn FnMut(&Event),
+    ) -> Result<()> {
         let end_implicit;
         let end_mark;
 
         loop {
             let event = parser.parse()?;
-            match event.data {
-                EventData::StreamStart { .. } => panic!("unexpected stream start event"),
-                EventData::StreamEnd => panic!("unexpected stream end event"),
-                EventData::DocumentStart { .. } => panic!("unexpected document start event"),
-                EventData::DocumentEnd { implicit } => {
-                    end_implicit = implicit;
-                    end_mark = event.end_mark;
-                    break;
-                }
-                EventData::Alias { .. } => {
-                    self.load_alias(parser, event, ctx)?;
-                }
-                EventData::Scalar { .. } => {
-                    self.load_scalar(parser, event, ctx)?;
-                }
-                EventData::SequenceStart { .. } => {
-                    self.load_sequence(parser, event, ctx)?;
-                }
-                EventData::SequenceEnd => {
-                    self.load_sequence_end(event, ctx)?;
-                }
-                EventData::MappingStart { .. } => {
-                    self.load_mapping(parser, event, ctx)?;
-                }
-                EventData::MappingEnd => {
-                    self.load_mapping_end(event, ctx)?;
-                }
+            observer(&event);
+            if let EventData::DocumentEnd { implicit } = event.data {
+                end_implicit = implicit;
+                end_mark = event.end_mark;
+                break;
             }
+            self.load_node(parser, arena, event, ctx)?;
         }
         self.end_implicit = end_implicit;
         self.end_mark = end_mark;
         Ok(())
     }
 
+    fn load_node(
+        &mut self,
+        parser: &mut Parser,
+        arena: &mut LoadArena,
+        event: Event,
+        ctx: &mut Vec<i32>,
+    ) -> Result<()> {
+        match event.data {
+            EventData::StreamStart { .. } => panic!("unexpected stream start event"),
+            EventData::StreamEnd => panic!("unexpected stream end event"),
+            EventData::DocumentStart { .. } => panic!("unexpected document start event"),
+            EventData::DocumentEnd { .. } => panic!("unexpected document end event"),
+            EventData::Alias { .. } => self.load_alias(parser, event, ctx),
+            EventData::Scalar { .. } => self.load_scalar(parser, arena, event, ctx),
+            EventData::SequenceStart { .. } => self.load_sequence(parser, arena, event, ctx),
+            EventData::SequenceEnd => self.load_sequence_end(event, ctx),
+            EventData::MappingStart { .. } => self.load_mapping(parser, arena, event, ctx),
+            EventData::MappingEnd => self.load_mapping_end(event, ctx),
+        }
+    }
+
     fn register_anchor(
         &mut self,
         parser: &mut Parser,
@@ -368,20 +1356,69 @@ impl Document {
             index,
             mark: self.nodes[index as usize - 1].start_mark,
         };
-        for alias_data in &parser.aliases {
+        let policy = parser.duplicate_anchor_policy;
+        for alias_data in &mut parser.aliases {
             if alias_data.anchor == data.anchor {
-                return Err(Error::composer(
-                    "found duplicate anchor; first occurrence",
-                    alias_data.mark,
-                    "second occurrence",
-                    data.mark,
-                ));
+                match policy {
+                    DuplicateAnchorPolicy::Error => {
+                        return Err(Error::composer(
+                            "found duplicate anchor; first occurrence",
+                            alias_data.mark,
+                            "second occurrence",
+                            data.mark,
+                        ));
+                    }
+                    DuplicateAnchorPolicy::LastWins => *alias_data = data,
+                    DuplicateAnchorPolicy::FirstWins => {}
+                }
+                return Ok(());
             }
         }
         parser.aliases.push(data);
         Ok(())
     }
 
+    /// Describe the collections being loaded when an error occurred, from
+    /// the root node to the innermost one, see
+    /// [`Parser::error_context_chain()`].
+    fn error_context_chain(&self, ctx: &[i32]) -> Vec<(String, Mark)> {
+        let mut chain = Vec::with_capacity(ctx.len());
+        for (depth, index) in ctx.iter().enumerate() {
+            let node = &self.nodes[*index as usize - 1];
+            let child = ctx.get(depth + 1).copied();
+            match &node.data {
+                NodeData::Sequence { items, .. } => {
+                    let item = if child.is_some() {
+                        items.len() - 1
+                    } else {
+                        items.len()
+                    };
+                    chain.push((
+                        alloc::format!("while composing sequence item {item}"),
+                        node.start_mark,
+                    ));
+                }
+                NodeData::Mapping { pairs, .. } => match pairs.last() {
+                    // Either the value of the last pair is being loaded, or
+                    // its key is loaded and its value is next.
+                    Some(pair) if child.map_or(pair.value == 0, |child| child == pair.value) => {
+                        let key = &self.nodes[pair.key as usize - 1];
+                        let context = match &key.data {
+                            NodeData::Scalar { value, .. } => {
+                                alloc::format!("while composing mapping value for key '{value}'")
+                            }
+                            _ => String::from("while composing mapping value"),
+                        };
+                        chain.push((context, key.start_mark));
+                    }
+                    _ => chain.push((String::from("while composing mapping key"), node.start_mark)),
+                },
+                NodeData::NoNode | NodeData::Scalar { .. } => {}
+            }
+        }
+        chain
+    }
+
     fn load_node_add(&mut self, ctx: &[i32], index: i32) -> Result<()> {
         let Some(parent_index) = ctx.last() else {
             return Ok(());
@@ -429,7 +1466,13 @@ impl Document {
         ))
     }
 
-    fn load_scalar(&mut self, parser: &mut Parser, event: Event, ctx: &[i32]) -> Result<()> {
+    fn load_scalar(
+        &mut self,
+        parser: &mut Parser,
+        arena: &mut LoadArena,
+        event: Event,
+        ctx: &[i32],
+    ) -> Result<()> {
         let EventData::Scalar {
             mut tag,
             value,
@@ -440,13 +1483,25 @@ impl Document {
         else {
             unreachable!()
         };
+        tag = parser.check_tag(tag, event.start_mark)?;
+        let plain_implicit = tag.is_none() && style == ScalarStyle::Plain;
 
         if tag.is_none() || tag.as_deref() == Some("!") {
-            tag = Some(String::from(DEFAULT_SCALAR_TAG));
+            let kind = if style == ScalarStyle::Plain {
+                NodeKind::PlainScalar
+            } else {
+                NodeKind::QuotedScalar
+            };
+            tag = Some(
+                parser
+                    .resolve_tag(&value, tag.as_deref(), kind)
+                    .unwrap_or_else(|| arena.tag(DEFAULT_SCALAR_TAG)),
+            );
         }
         let node = Node {
             data: NodeData::Scalar { value, style },
             tag,
+            plain_implicit,
             start_mark: event.start_mark,
             end_mark: event.end_mark,
         };
@@ -459,6 +1514,7 @@ impl Document {
     fn load_sequence(
         &mut self,
         parser: &mut Parser,
+        arena: &mut LoadArena,
         event: Event,
         ctx: &mut Vec<i32>,
     ) -> Result<()> {
@@ -471,11 +1527,16 @@ impl Document {
         else {
             unreachable!()
         };
+        tag = parser.check_tag(tag, event.start_mark)?;
 
-        let mut items = Vec::with_capacity(16);
+        let mut items = arena.items();
 
         if tag.is_none() || tag.as_deref() == Some("!") {
-            tag = Some(String::from(DEFAULT_SEQUENCE_TAG));
+            tag = Some(
+                parser
+                    .resolve_tag("", tag.as_deref(), NodeKind::Sequence)
+                    .unwrap_or_else(|| arena.tag(DEFAULT_SEQUENCE_TAG)),
+            );
         }
 
         let node = Node {
@@ -484,6 +1545,7 @@ impl Document {
                 style,
             },
             tag,
+            plain_implicit: false,
             start_mark: event.start_mark,
             end_mark: event.end_mark,
         };
@@ -512,6 +1574,7 @@ impl Document {
     fn load_mapping(
         &mut self,
         parser: &mut Parser,
+        arena: &mut LoadArena,
         event: Event,
         ctx: &mut Vec<i32>,
     ) -> Result<()> {
@@ -524,11 +1587,16 @@ impl Document {
         else {
             unreachable!()
         };
+        tag = parser.check_tag(tag, event.start_mark)?;
 
-        let mut pairs = Vec::with_capacity(16);
+        let mut pairs = arena.pairs();
 
         if tag.is_none() || tag.as_deref() == Some("!") {
-            tag = Some(String::from(DEFAULT_MAPPING_TAG));
+            tag = Some(
+                parser
+                    .resolve_tag("", tag.as_deref(), NodeKind::Mapping)
+                    .unwrap_or_else(|| arena.tag(DEFAULT_MAPPING_TAG)),
+            );
         }
         let node = Node {
             data: NodeData::Mapping {
@@ -536,6 +1604,7 @@ impl Document {
                 style,
             },
             tag,
+            plain_implicit: false,
             start_mark: event.start_mark,
             end_mark: event.end_mark,
         };
@@ -566,10 +1635,7 @@ impl Document {
     /// function or the [`Document::new()`] function.
     pub fn dump(mut self, emitter: &mut Emitter) -> Result<()> {
         if !emitter.opened {
-            if let Err(err) = emitter.open() {
-                emitter.reset_anchors();
-                return Err(err);
-            }
+            emitter.open()?;
         }
         if self.nodes.is_empty() {
             // TODO: Do we really want to close the emitter just because the
@@ -578,142 +1644,1687 @@ impl Document {
             emitter.close()?;
         } else {
             assert!(emitter.opened);
-            emitter.anchors = vec![Anchors::default(); self.nodes.len()];
-            let event = Event::new(EventData::DocumentStart {
-                version_directive: self.version_directive,
-                tag_directives: core::mem::take(&mut self.tag_directives),
-                implicit: self.start_implicit,
-            });
-            emitter.emit(event)?;
-            self.anchor_node(emitter, 1);
-            self.dump_node(emitter, 1)?;
-            let event = Event::document_end(self.end_implicit);
+            if let Some(compare) = emitter.sequence_sort {
+                self.sort_scalar_sequences(compare);
+            }
+            for event in self.into_events() {
+                emitter.emit(event)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sort the items of the sequences whose items are all scalars, see
+    /// [`Emitter::set_sequence_sort()`].
+    pub(crate) fn sort_scalar_sequences(&mut self, compare: fn(&str, &str) -> Ordering) {
+        for index in 0..self.nodes.len() {
+            let NodeData::Sequence { items, .. } = &self.nodes[index].data else {
+                continue;
+            };
+            let Some(mut sorted) = items
+                .iter()
+                .map(|item| match self.get_node(*item).map(|node| &node.data) {
+                    Some(NodeData::Scalar { value, .. }) => Some((*item, value.as_str())),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            sorted.sort_by(|a, b| compare(a.1, b.1));
+            let sorted = sorted.into_iter().map(|(item, _)| item).collect();
+            if let NodeData::Sequence { items, .. } = &mut self.nodes[index].data {
+                *items = sorted;
+            }
+        }
+    }
+
+    /// Check that a YAML document can be emitted, without writing anything.
+    ///
+    /// This runs the same checks as [`Document::dump()`] with a default
+    /// emitter, such as the validity of the directives and tags, so that a
+    /// document can be checked before any output is written. The first problem
+    /// found is returned, with the id of the offending node, see
+    /// [`Error::node_id()`].
+    pub fn validate(&self) -> Result<()> {
+        let mut sink = Sink;
+        let mut emitter = Emitter::new();
+        emitter.set_output_fmt(&mut sink);
+        emitter.emit(Event::stream_start(Encoding::Utf8))?;
+        for event in self.clone().into_events() {
             emitter.emit(event)?;
         }
+        emitter.emit(Event::stream_end())
+    }
+
+    /// Compare two documents by content, ignoring how they are formatted.
+    ///
+    /// Scalars are equal if they resolve to the same value in the core schema,
+    /// as described in [`Node::as_str()`], so `1`, `0x1` and `!!int "1"` are
+    /// equal while `1` and `"1"` are not. Scalars with other tags are equal if
+    /// both their tags and their values are equal. Sequences are compared
+    /// item by item, mappings pair by pair, and collections must also have
+    /// equal tags. Styles, marks, directives and anchor names are ignored, and
+    /// aliases are compared by the node they refer to.
+    pub fn semantic_eq(&self, other: &Document, options: SemanticEqOptions) -> bool {
+        match (self.nodes.is_empty(), other.nodes.is_empty()) {
+            (true, true) => true,
+            (false, false) => {
+                self.node_semantic_eq(1, other, 1, options, &mut Comparison::default())
+            }
+            _ => false,
+        }
+    }
+
+    fn node_semantic_eq(
+        &self,
+        index: i32,
+        other: &Document,
+        other_index: i32,
+        options: SemanticEqOptions,
+        comparison: &mut Comparison,
+    ) -> bool {
+        let (Some(node), Some(other_node)) = (self.get_node(index), other.get_node(other_index))
+        else {
+            return false;
+        };
+        // A recursive alias leads back to a pair of nodes being compared,
+        // which is equal unless the comparison in progress finds otherwise.
+        // Shared aliases lead to pairs that were already found equal.
+        let pair = (index, other_index);
+        if comparison.in_progress.contains(&pair) || comparison.equal_set.contains(&pair) {
+            return true;
+        }
+        match (&node.data, &other_node.data) {
+            (NodeData::NoNode, NodeData::NoNode) => true,
+            (
+                NodeData::Scalar { value, .. },
+                NodeData::Scalar {
+                    value: other_value, ..
+                },
+            ) => match (node.resolve(), other_node.resolve()) {
+                (Some(resolved), Some(other_resolved)) => resolved.same(&other_resolved),
+                (None, None) => node.tag == other_node.tag && value == other_value,
+                _ => false,
+            },
+            (
+                NodeData::Sequence { items, .. },
+                NodeData::Sequence {
+                    items: other_items, ..
+                },
+            ) => {
+                if node.tag != other_node.tag || items.len() != other_items.len() {
+                    return false;
+                }
+                comparison.start(pair);
+                let equal = items.iter().zip(other_items).all(|(item, other_item)| {
+                    self.node_semantic_eq(*item, other, *other_item, options, comparison)
+                });
+                comparison.finish(pair, equal);
+                equal
+            }
+            (
+                NodeData::Mapping { pairs, .. },
+                NodeData::Mapping {
+                    pairs: other_pairs, ..
+                },
+            ) => {
+                if node.tag != other_node.tag || pairs.len() != other_pairs.len() {
+                    return false;
+                }
+                comparison.start(pair);
+                let mut pair_eq = |pair: &NodePair, other_pair: &NodePair| {
+                    self.node_semantic_eq(pair.key, other, other_pair.key, options, comparison)
+                        && self.node_semantic_eq(
+                            pair.value,
+                            other,
+                            other_pair.value,
+                            options,
+                            comparison,
+                        )
+                };
+                let equal = if options.ignore_key_order {
+                    let mut matched = vec![false; other_pairs.len()];
+                    pairs.iter().all(|pair| {
+                        let found = other_pairs
+                            .iter()
+                            .enumerate()
+                            .position(|(i, other_pair)| !matched[i] && pair_eq(pair, other_pair));
+                        found.map(|i| matched[i] = true).is_some()
+                    })
+                } else {
+                    pairs
+                        .iter()
+                        .zip(other_pairs)
+                        .all(|(pair, other_pair)| pair_eq(pair, other_pair))
+                };
+                comparison.finish(pair, equal);
+                equal
+            }
+            _ => false,
+        }
+    }
+
+    /// Fill in the parts of `defaults` missing from this document, and return
+    /// the merged document.
+    ///
+    /// When both root nodes are mappings, each pair of `defaults` whose key is
+    /// not in this document is copied into it, and the values of keys in both
+    /// are merged recursively when both are mappings. Otherwise the node of
+    /// this document is kept as is, unless both are sequences and
+    /// [`MergeOptions::concat_sequences`] is set, in which case the default
+    /// items are appended to it. Keys are compared with
+    /// [`Document::semantic_eq()`] rules. An empty document is replaced by
+    /// `defaults`.
+    #[must_use]
+    pub fn merge_defaults(mut self, defaults: &Document, options: MergeOptions) -> Document {
+        if self.nodes.is_empty() {
+            return defaults.clone();
+        }
+        if !defaults.nodes.is_empty() {
+            let mut copied = vec![0; defaults.nodes.len() + 1];
+            self.merge_node(1, defaults, 1, options, &mut copied, &mut Vec::new());
+        }
+        self
+    }
 
-        emitter.reset_anchors();
+    fn merge_node(
+        &mut self,
+        index: i32,
+        defaults: &Document,
+        default_index: i32,
+        options: MergeOptions,
+        copied: &mut Vec<i32>,
+        in_progress: &mut Vec<(i32, i32)>,
+    ) {
+        if in_progress.contains(&(index, default_index)) {
+            return;
+        }
+        let (Some(node), Some(default_node)) =
+            (self.get_node(index), defaults.get_node(default_index))
+        else {
+            return;
+        };
+        match (&node.data, &default_node.data) {
+            (
+                NodeData::Mapping { pairs, .. },
+                NodeData::Mapping {
+                    pairs: default_pairs,
+                    ..
+                },
+            ) => {
+                let pairs = pairs.clone();
+                in_progress.push((index, default_index));
+                for default_pair in default_pairs {
+                    let existing = pairs.iter().find(|pair| {
+                        self.node_semantic_eq(
+                            pair.key,
+                            defaults,
+                            default_pair.key,
+                            SemanticEqOptions::default(),
+                            &mut Comparison::default(),
+                        )
+                    });
+                    if let Some(pair) = existing {
+                        self.merge_node(
+                            pair.value,
+                            defaults,
+                            default_pair.value,
+                            options,
+                            copied,
+                            in_progress,
+                        );
+                    } else {
+                        let key = self.copy_node(defaults, default_pair.key, copied);
+                        let value = self.copy_node(defaults, default_pair.value, copied);
+                        self.yaml_document_append_mapping_pair(index, key, value);
+                    }
+                }
+                in_progress.pop();
+            }
+            (
+                NodeData::Sequence { .. },
+                NodeData::Sequence {
+                    items: default_items,
+                    ..
+                },
+            ) if options.concat_sequences => {
+                for item in default_items {
+                    let item = self.copy_node(defaults, *item, copied);
+                    self.append_sequence_item(index, item);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Copy a node of `source` and its children into this document, and
+    /// return the index of the copy. Nodes copied before are reused, so that
+    /// aliases keep referring to a single node.
+    fn copy_node(&mut self, source: &Document, index: i32, copied: &mut Vec<i32>) -> i32 {
+        if copied[index as usize] != 0 {
+            return copied[index as usize];
+        }
+        let node = &source.nodes[index as usize - 1];
+        let data = match &node.data {
+            NodeData::NoNode => NodeData::NoNode,
+            NodeData::Scalar { value, style } => NodeData::Scalar {
+                value: value.clone(),
+                style: *style,
+            },
+            NodeData::Sequence { style, .. } => NodeData::Sequence {
+                items: Vec::new(),
+                style: *style,
+            },
+            NodeData::Mapping { style, .. } => NodeData::Mapping {
+                pairs: Vec::new(),
+                style: *style,
+            },
+        };
+        self.nodes.push(Node {
+            data,
+            tag: node.tag.clone(),
+            plain_implicit: node.plain_implicit,
+            start_mark: node.start_mark,
+            end_mark: node.end_mark,
+        });
+        let copy = self.nodes.len() as i32;
+        copied[index as usize] = copy;
+        match &node.data {
+            NodeData::Sequence { items, .. } => {
+                for item in items {
+                    let item = self.copy_node(source, *item, copied);
+                    self.append_sequence_item(copy, item);
+                }
+            }
+            NodeData::Mapping { pairs, .. } => {
+                for pair in pairs {
+                    let key = self.copy_node(source, pair.key, copied);
+                    let value = self.copy_node(source, pair.value, copied);
+                    self.yaml_document_append_mapping_pair(copy, key, value);
+                }
+            }
+            NodeData::NoNode | NodeData::Scalar { .. } => {}
+        }
+        copy
+    }
+
+    /// Expand the aliases of a document, so that every node is referenced
+    /// once and the document is dumped without anchors or aliases.
+    ///
+    /// Each node referenced more than once is replaced by a deep copy at each
+    /// reference after the first, and [`Document::anchors`] is cleared. Nodes
+    /// that are not reachable from the root node are dropped, so the indices
+    /// of the nodes may change. An alias to one of its own ancestors cannot be
+    /// expanded and is an error, as is an expansion to more than `max_nodes`
+    /// nodes, which guards against documents made of nested aliases that
+    /// expand exponentially. On error, the document is left unchanged.
+    pub fn yaml_document_deanchor(&mut self, max_nodes: usize) -> Result<()> {
+        if self.nodes.is_empty() {
+            self.anchors.clear();
+            return Ok(());
+        }
+        let source = core::mem::take(&mut self.nodes);
+        if let Err(err) = self.expand_node(&source, 1, &mut Vec::new(), max_nodes) {
+            self.nodes = source;
+            return Err(err);
+        }
+        self.anchors.clear();
         Ok(())
     }
 
-    fn anchor_node(&self, emitter: &mut Emitter, index: i32) {
+    /// Copy a node of `source` and its children into this document like
+    /// [`Document::copy_node()`], but copying each node again at each of its
+    /// references. `path` holds the nodes being copied.
+    fn expand_node(
+        &mut self,
+        source: &[Node],
+        index: i32,
+        path: &mut Vec<i32>,
+        max_nodes: usize,
+    ) -> Result<i32> {
+        let node = &source[index as usize - 1];
+        if path.contains(&index) {
+            return Err(Error::composer(
+                "",
+                Mark::default(),
+                "found an alias to one of its own ancestors",
+                node.start_mark,
+            ));
+        }
+        if self.nodes.len() >= max_nodes {
+            return Err(Error::composer(
+                "",
+                Mark::default(),
+                "expanding the aliases exceeds the maximum number of nodes",
+                node.start_mark,
+            ));
+        }
+        let data = match &node.data {
+            NodeData::NoNode => NodeData::NoNode,
+            NodeData::Scalar { value, style } => NodeData::Scalar {
+                value: value.clone(),
+                style: *style,
+            },
+            NodeData::Sequence { style, .. } => NodeData::Sequence {
+                items: Vec::new(),
+                style: *style,
+            },
+            NodeData::Mapping { style, .. } => NodeData::Mapping {
+                pairs: Vec::new(),
+                style: *style,
+            },
+        };
+        self.nodes.push(Node {
+            data,
+            tag: node.tag.clone(),
+            plain_implicit: node.plain_implicit,
+            start_mark: node.start_mark,
+            end_mark: node.end_mark,
+        });
+        let copy = self.nodes.len() as i32;
+        path.push(index);
+        match &node.data {
+            NodeData::Sequence { items, .. } => {
+                for item in items {
+                    let item = self.expand_node(source, *item, path, max_nodes)?;
+                    self.append_sequence_item(copy, item);
+                }
+            }
+            NodeData::Mapping { pairs, .. } => {
+                for pair in pairs {
+                    let key = self.expand_node(source, pair.key, path, max_nodes)?;
+                    let value = self.expand_node(source, pair.value, path, max_nodes)?;
+                    self.yaml_document_append_mapping_pair(copy, key, value);
+                }
+            }
+            NodeData::NoNode | NodeData::Scalar { .. } => {}
+        }
+        path.pop();
+        Ok(copy)
+    }
+
+    /// Convert a YAML document into the sequence of events that
+    /// [`Document::dump()`] would emit.
+    ///
+    /// The events start with a DOCUMENT-START event and end with a
+    /// DOCUMENT-END event. Nodes that are referenced more than once are given
+    /// an anchor, unless they have one in [`Document::anchors`], and are
+    /// emitted as aliases after their first occurrence.
+    /// Returns no events if the document is empty.
+    /// The events carry the marks of the nodes they were produced from, and
+    /// the emitter errors raised for them carry the node ids, see
+    /// [`Error::node_id()`].
+    pub fn into_events(mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        if self.nodes.is_empty() {
+            return events;
+        }
+
+        let mut anchors = vec![Anchors::default(); self.nodes.len()];
+        for (anchor, index) in &self.anchors {
+            if let Some(anchors) = anchors.get_mut((*index as usize).wrapping_sub(1)) {
+                anchors.anchor = Some(anchor.clone());
+            }
+        }
+        let mut last_anchor_id = 0;
+        self.anchor_node(&mut anchors, &mut last_anchor_id, 1);
+
+        let mut event = Event::new(EventData::DocumentStart {
+            version_directive: self.version_directive,
+            tag_directives: core::mem::take(&mut self.tag_directives),
+            implicit: self.start_implicit,
+        });
+        event.start_mark = self.start_mark;
+        event.end_mark = self.start_mark;
+        events.push(event);
+        self.dump_node(&mut events, &mut anchors, 1);
+        let mut event = Event::document_end(self.end_implicit);
+        event.start_mark = self.end_mark;
+        event.end_mark = self.end_mark;
+        events.push(event);
+        events
+    }
+
+    fn anchor_node(&self, anchors: &mut [Anchors], last_anchor_id: &mut i32, index: i32) {
         let node = &self.nodes[index as usize - 1];
-        emitter.anchors[index as usize - 1].references += 1;
-        if emitter.anchors[index as usize - 1].references == 1 {
+        anchors[index as usize - 1].references += 1;
+        if anchors[index as usize - 1].references == 1 {
             match &node.data {
                 NodeData::Sequence { items, .. } => {
                     for item in items {
-                        emitter.anchor_node_sub(*item);
+                        self.anchor_node(anchors, last_anchor_id, *item);
                     }
                 }
                 NodeData::Mapping { pairs, .. } => {
                     for pair in pairs {
-                        emitter.anchor_node_sub(pair.key);
-                        emitter.anchor_node_sub(pair.value);
+                        self.anchor_node(anchors, last_anchor_id, pair.key);
+                        self.anchor_node(anchors, last_anchor_id, pair.value);
                     }
                 }
                 _ => {}
             }
-        } else if emitter.anchors[index as usize - 1].references == 2 {
-            emitter.last_anchor_id += 1;
-            emitter.anchors[index as usize - 1].anchor = emitter.last_anchor_id;
+        } else if anchors[index as usize - 1].references == 2
+            && anchors[index as usize - 1].anchor.is_none()
+        {
+            // Skip the generated anchors that are already set on other nodes.
+            let anchor = loop {
+                *last_anchor_id += 1;
+                let anchor = Emitter::generate_anchor(*last_anchor_id);
+                if !self.anchors.iter().any(|(name, _)| *name == anchor) {
+                    break anchor;
+                }
+            };
+            anchors[index as usize - 1].anchor = Some(anchor);
         }
     }
 
-    fn dump_node(&mut self, emitter: &mut Emitter, index: i32) -> Result<()> {
+    fn dump_node(&mut self, events: &mut Vec<Event>, anchors: &mut [Anchors], index: i32) {
         assert!(index > 0);
         let node = &mut self.nodes[index as usize - 1];
-        let anchor_id: i32 = emitter.anchors[index as usize - 1].anchor;
-        let mut anchor: Option<String> = None;
-        if anchor_id != 0 {
-            anchor = Some(Emitter::generate_anchor(anchor_id));
-        }
-        if emitter.anchors[index as usize - 1].serialized {
-            return Self::dump_alias(emitter, anchor.unwrap());
+        let anchor = anchors[index as usize - 1].anchor.clone();
+        if anchors[index as usize - 1].serialized {
+            let mut event = Event::new(EventData::Alias {
+                anchor: anchor.expect("node referenced more than once has an anchor"),
+            });
+            event.source = EventSource::Node(index);
+            events.push(event);
+            return;
         }
-        emitter.anchors[index as usize - 1].serialized = true;
+        anchors[index as usize - 1].serialized = true;
 
         let node = core::mem::take(node);
         match node.data {
-            NodeData::Scalar { .. } => Self::dump_scalar(emitter, node, anchor),
-            NodeData::Sequence { .. } => self.dump_sequence(emitter, node, anchor),
-            NodeData::Mapping { .. } => self.dump_mapping(emitter, node, anchor),
+            NodeData::Scalar { .. } => Self::dump_scalar(events, index, node, anchor),
+            NodeData::Sequence { .. } => self.dump_sequence(events, anchors, index, node, anchor),
+            NodeData::Mapping { .. } => self.dump_mapping(events, anchors, index, node, anchor),
             _ => unreachable!("document node is neither a scalar, sequence, or a mapping"),
         }
     }
 
-    fn dump_alias(emitter: &mut Emitter, anchor: String) -> Result<()> {
-        let event = Event::new(EventData::Alias { anchor });
-        emitter.emit(event)
-    }
-
-    fn dump_scalar(emitter: &mut Emitter, node: Node, anchor: Option<String>) -> Result<()> {
-        let plain_implicit = node.tag.as_deref() == Some(DEFAULT_SCALAR_TAG);
-        let quoted_implicit = node.tag.as_deref() == Some(DEFAULT_SCALAR_TAG); // TODO: Why compare twice?! (even the C code does this)
-
+    fn dump_scalar(events: &mut Vec<Event>, index: i32, node: Node, anchor: Option<String>) {
         let NodeData::Scalar { value, style } = node.data else {
             unreachable!()
         };
-        let event = Event::new(EventData::Scalar {
+        // A plain scalar explicitly tagged `!!str` is only written without
+        // its tag if its value is not read back as another type, and one
+        // loaded without a tag keeps none if its tag is the one the core
+        // schema resolves it to.
+        let plain_implicit = match node.tag.as_deref() {
+            Some(DEFAULT_SCALAR_TAG) => {
+                node.plain_implicit || matches!(Resolved::from_plain(&value), Resolved::Str(_))
+            }
+            tag => {
+                node.plain_implicit
+                    && tag.is_some()
+                    && resolver::core_schema_tag(&value, None, NodeKind::PlainScalar).as_deref()
+                        == tag
+            }
+        };
+        let quoted_implicit = node.tag.as_deref() == Some(DEFAULT_SCALAR_TAG);
+
+        let mut event = Event::new(EventData::Scalar {
             anchor,
             tag: node.tag,
             value,
             plain_implicit,
             quoted_implicit,
             style,
+            force_style: None,
+            anchor_mark: None,
+            tag_mark: None,
+            value_mark: None,
         });
-        emitter.emit(event)
+        event.start_mark = node.start_mark;
+        event.end_mark = node.end_mark;
+        event.source = EventSource::Node(index);
+        events.push(event);
     }
 
     fn dump_sequence(
         &mut self,
-        emitter: &mut Emitter,
+        events: &mut Vec<Event>,
+        anchors: &mut [Anchors],
+        index: i32,
         node: Node,
         anchor: Option<String>,
-    ) -> Result<()> {
+    ) {
         let implicit = node.tag.as_deref() == Some(DEFAULT_SEQUENCE_TAG);
 
         let NodeData::Sequence { items, style } = node.data else {
             unreachable!()
         };
-        let event = Event::new(EventData::SequenceStart {
+        let mut event = Event::new(EventData::SequenceStart {
             anchor,
             tag: node.tag,
             implicit,
             style,
+            anchor_mark: None,
+            tag_mark: None,
+            value_mark: None,
         });
-
-        emitter.emit(event)?;
+        event.start_mark = node.start_mark;
+        event.end_mark = node.start_mark;
+        event.source = EventSource::Node(index);
+        events.push(event);
         for item in items {
-            self.dump_node(emitter, item)?;
+            self.dump_node(events, anchors, item);
         }
-        let event = Event::sequence_end();
-        emitter.emit(event)
+        let mut event = Event::sequence_end();
+        event.start_mark = node.end_mark;
+        event.end_mark = node.end_mark;
+        event.source = EventSource::Node(index);
+        events.push(event);
     }
 
     fn dump_mapping(
         &mut self,
-        emitter: &mut Emitter,
+        events: &mut Vec<Event>,
+        anchors: &mut [Anchors],
+        index: i32,
         node: Node,
         anchor: Option<String>,
-    ) -> Result<()> {
+    ) {
         let implicit = node.tag.as_deref() == Some(DEFAULT_MAPPING_TAG);
 
         let NodeData::Mapping { pairs, style } = node.data else {
             unreachable!()
         };
-        let event = Event::new(EventData::MappingStart {
+        let mut event = Event::new(EventData::MappingStart {
             anchor,
             tag: node.tag,
             implicit,
             style,
+            anchor_mark: None,
+            tag_mark: None,
+            value_mark: None,
         });
-
-        emitter.emit(event)?;
+        event.start_mark = node.start_mark;
+        event.end_mark = node.start_mark;
+        event.source = EventSource::Node(index);
+        events.push(event);
         for pair in pairs {
-            self.dump_node(emitter, pair.key)?;
-            self.dump_node(emitter, pair.value)?;
+            self.dump_node(events, anchors, pair.key);
+            self.dump_node(events, anchors, pair.value);
+        }
+        let mut event = Event::mapping_end();
+        event.start_mark = node.end_mark;
+        event.end_mark = node.end_mark;
+        event.source = EventSource::Node(index);
+        events.push(event);
+    }
+}
+
+/// Load the single document of a YAML file.
+///
+/// The file is read through a buffered reader, and its encoding is detected
+/// from its byte order mark or first bytes like for any input. A file with no
+/// document, or with more than one, is a composer error.
+#[cfg(feature = "std")]
+pub fn yaml_load_file<P: AsRef<std::path::Path>>(path: P) -> Result<Document> {
+    let file = std::fs::File::open(path)?;
+    let mut reader = std::io::BufReader::new(file);
+    let mut parser = Parser::new();
+    parser.set_input(&mut reader);
+    let document = Document::load(&mut parser)?;
+    if document.nodes.is_empty() {
+        return Err(Error::composer(
+            "",
+            Mark::default(),
+            "expected a single document, but found none",
+            parser.scanner.mark,
+        ));
+    }
+    let event = parser.parse()?;
+    if let EventData::DocumentStart { .. } = event.data {
+        return Err(Error::composer(
+            "",
+            Mark::default(),
+            "expected a single document, but found another",
+            event.start_mark,
+        ));
+    }
+    Ok(document)
+}
+
+/// An output handler that discards everything written to it.
+struct Sink;
+
+impl core::fmt::Write for Sink {
+    fn write_str(&mut self, _s: &str) -> core::fmt::Result {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(input: &str) -> Document {
+        let mut parser = Parser::new();
+        let mut read_in = input.as_bytes();
+        parser.set_input_string(&mut read_in);
+        Document::load(&mut parser).unwrap()
+    }
+
+    fn scalar_value(node: &Node) -> &str {
+        let NodeData::Scalar { value, .. } = &node.data else {
+            panic!("expected scalar")
+        };
+        value
+    }
+
+    #[test]
+    fn root_collections() {
+        let doc = load("- a\n- b\n- c\n");
+        let items = doc.root_sequence().unwrap().map(scalar_value);
+        assert_eq!(items.collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert!(doc.root_mapping().is_none());
+
+        let doc = load("a: 1\nb: 2\n");
+        let pairs = doc
+            .root_mapping()
+            .unwrap()
+            .map(|(key, value)| (scalar_value(key), scalar_value(value)));
+        assert_eq!(pairs.collect::<Vec<_>>(), [("a", "1"), ("b", "2")]);
+        assert!(doc.root_sequence().is_none());
+
+        let doc = load("");
+        assert!(doc.root_sequence().is_none());
+        assert!(doc.root_mapping().is_none());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn scalar_keys() {
+        let mut doc = Document::new(None, &[], true, true);
+        let str_one = doc.add_scalar(None, "1", ScalarStyle::Plain);
+        let quoted_one = doc.add_scalar(None, "1", ScalarStyle::DoubleQuoted);
+        let int_one = doc.add_scalar(Some(crate::INT_TAG), "1", ScalarStyle::Plain);
+        let explicit_str_one = doc.add_scalar(Some(DEFAULT_SCALAR_TAG), "1", ScalarStyle::Plain);
+        let seq = doc.add_sequence(None, SequenceStyle::Block);
+        let key = |id| doc.get_node(id).unwrap().scalar_key();
+
+        assert_eq!(key(str_one), key(int_one));
+        assert_eq!(key(quoted_one), key(explicit_str_one));
+        assert_ne!(key(str_one), key(quoted_one));
+        assert_eq!(key(str_one).unwrap().tag, crate::INT_TAG);
+        assert!(key(seq).is_none());
+
+        let mut set = std::collections::HashSet::new();
+        assert!(set.insert(key(str_one).unwrap()));
+        assert!(!set.insert(key(int_one).unwrap()));
+        assert!(set.insert(key(quoted_one).unwrap()));
+        assert!(!set.insert(key(explicit_str_one).unwrap()));
+
+        // Keys loaded without a resolver are resolved the same way.
+        let mut parser = Parser::from_str("[1, !!int 1, '1', !!str 1]");
+        parser.set_tag_resolver(|_, _, _| None);
+        let doc = Document::load(&mut parser).unwrap();
+        let keys: Vec<_> = doc.nodes.iter().filter_map(Node::scalar_key).collect();
+        assert_eq!(keys[0], keys[1]);
+        assert_eq!(keys[2], keys[3]);
+        assert_ne!(keys[0], keys[2]);
+
+        let doc = load("[b, a, c]");
+        let mut keys: Vec<_> = doc
+            .root_sequence()
+            .unwrap()
+            .filter_map(Node::scalar_key)
+            .collect();
+        keys.sort();
+        let values: Vec<_> = keys.iter().map(|key| key.value).collect();
+        assert_eq!(values, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn load_with_arena() {
+        let input = "a: &x [1, 2]\n---\n- !x b\n- {c: &y d}\n";
+        let mut parser = Parser::new();
+        let mut read_in = input.as_bytes();
+        parser.set_input_string(&mut read_in);
+
+        let mut arena = LoadArena::new();
+        let doc = Document::load_with_arena(&mut parser, &mut arena).unwrap();
+        assert_eq!(doc.nodes.len(), 5);
+        assert_eq!(doc.anchors, [(String::from("x"), 3)]);
+        arena.recycle(doc);
+
+        let doc = Document::load_with_arena(&mut parser, &mut arena).unwrap();
+        let tags: Vec<_> = doc.nodes.iter().map(|node| node.tag.as_deref()).collect();
+        assert_eq!(
+            tags,
+            [
+                Some(crate::SEQ_TAG),
+                Some("!x"),
+                Some(crate::MAP_TAG),
+                Some(crate::STR_TAG),
+                Some(crate::STR_TAG),
+            ]
+        );
+        let NodeData::Mapping { pairs, .. } = &doc.nodes[2].data else {
+            panic!("expected mapping")
+        };
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(doc.anchors, [(String::from("y"), 5)]);
+        arena.recycle(doc);
+
+        let doc = Document::load_with_arena(&mut parser, &mut arena).unwrap();
+        assert!(doc.nodes.is_empty());
+    }
+
+    #[test]
+    fn into_events() {
+        let mut doc = Document::new(None, &[], true, true);
+        let root = doc.add_sequence(None, SequenceStyle::Flow);
+        let shared = doc.add_mapping(None, MappingStyle::Flow);
+        let key = doc.add_scalar(None, "a", ScalarStyle::Plain);
+        let inner = doc.add_scalar(None, "b", ScalarStyle::Plain);
+        doc.yaml_document_append_mapping_pair(shared, key, inner);
+        doc.append_sequence_item(root, shared);
+        doc.append_sequence_item(root, shared);
+        doc.append_sequence_item(root, inner);
+
+        let events: Vec<_> = doc.into_events().into_iter().map(|e| e.data).collect();
+        assert_eq!(events.len(), 10);
+        assert!(matches!(events[0], EventData::DocumentStart { .. }));
+        assert!(matches!(
+            &events[2],
+            EventData::MappingStart { anchor: Some(anchor), .. } if anchor == "id001"
+        ));
+        assert!(matches!(
+            &events[4],
+            EventData::Scalar { anchor: Some(anchor), value, .. } if anchor == "id002" && value == "b"
+        ));
+        assert!(matches!(&events[6], EventData::Alias { anchor } if anchor == "id001"));
+        assert!(matches!(&events[7], EventData::Alias { anchor } if anchor == "id002"));
+        assert!(matches!(events[8], EventData::SequenceEnd));
+        assert!(matches!(events[9], EventData::DocumentEnd { .. }));
+
+        assert!(Document::new(None, &[], true, true)
+            .into_events()
+            .is_empty());
+    }
+
+    #[test]
+    fn load_path() {
+        let input = "\
+config:
+  skip: &a [1, [2, 3], {x: y}]
+  servers:
+  - {name: one}
+  - name: two
+    ports: [80, 443]
+---
+other: document
+";
+        let mut parser = Parser::new();
+        let mut read_in = input.as_bytes();
+        parser.set_input_string(&mut read_in);
+
+        let path = [
+            PathSegment::Key("config"),
+            PathSegment::Key("servers"),
+            PathSegment::Index(1),
+            PathSegment::Key("ports"),
+        ];
+        let PathLookup::Found(doc) = Document::load_path(&mut parser, &path).unwrap() else {
+            panic!("path not found");
+        };
+        let ports = doc.root_sequence().unwrap().map(scalar_value);
+        assert_eq!(ports.collect::<Vec<_>>(), ["80", "443"]);
+        assert_eq!(doc.nodes.len(), 3);
+
+        let path = [PathSegment::Key("missing")];
+        assert!(matches!(
+            Document::load_path(&mut parser, &path).unwrap(),
+            PathLookup::NotFound
+        ));
+        assert!(matches!(
+            Document::load_path(&mut parser, &[]).unwrap(),
+            PathLookup::EndOfStream
+        ));
+
+        let mut parser = Parser::new();
+        let mut read_in = &b"a: &x {b: 1}\nc: *x\n"[..];
+        parser.set_input_string(&mut read_in);
+        let path = [PathSegment::Key("c"), PathSegment::Key("b")];
+        let err = Document::load_path(&mut parser, &path).unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "found an alias in the path, which cannot be followed"
+        );
+        assert_eq!(err.problem_mark().unwrap().line, 1);
+    }
+
+    #[test]
+    fn resolve_scalars() {
+        let doc = load(
+            "\
+- true
+- FALSE
+- 'true'
+- 0x1F
+- 0o17
+- -1_000
+- 99999999999999999999
+- 1.5e3
+- -.inf
+- ~
+- text
+- !!int '42'
+- !!bool maybe
+- !custom 1
+- [1]
+- !!str true
+- !!str 12
+",
+        );
+        let nodes: Vec<&Node> = doc.root_sequence().unwrap().collect();
+        assert_eq!(nodes[0].as_bool(), Some(true));
+        assert_eq!(nodes[1].as_bool(), Some(false));
+        assert_eq!(nodes[2].as_bool(), None);
+        assert_eq!(nodes[2].as_str(), Some("true"));
+        assert_eq!(nodes[3].as_i64(), Some(31));
+        assert_eq!(nodes[4].as_i64(), Some(15));
+        assert_eq!(nodes[5].as_i64(), Some(-1000));
+        assert_eq!(nodes[5].as_f64(), Some(-1000.0));
+        assert_eq!(nodes[6].as_i64(), None);
+        assert_eq!(nodes[6].as_f64(), Some(1e20));
+        assert_eq!(nodes[7].as_f64(), Some(1500.0));
+        assert_eq!(nodes[7].as_i64(), None);
+        assert_eq!(nodes[8].as_f64(), Some(f64::NEG_INFINITY));
+        assert_eq!(nodes[9].as_str(), None);
+        assert_eq!(nodes[10].as_str(), Some("text"));
+        assert_eq!(nodes[10].as_i64(), None);
+        assert_eq!(nodes[11].as_i64(), Some(42));
+        assert_eq!(nodes[12].as_bool(), None);
+        assert_eq!(nodes[13].as_i64(), None);
+        assert_eq!(nodes[14].as_str(), None);
+        assert_eq!(nodes[15].as_bool(), None);
+        assert_eq!(nodes[15].as_str(), Some("true"));
+        assert_eq!(nodes[16].as_i64(), None);
+        assert_eq!(nodes[16].as_str(), Some("12"));
+
+        let mut doc = Document::new(None, &[], true, true);
+        let implicit = doc.add_scalar(None, "12", ScalarStyle::Plain);
+        let explicit = doc.add_scalar(Some(DEFAULT_SCALAR_TAG), "12", ScalarStyle::Plain);
+        assert_eq!(doc.get_node(implicit).unwrap().as_i64(), Some(12));
+        assert_eq!(doc.get_node(explicit).unwrap().as_str(), Some("12"));
+    }
+
+    #[test]
+    fn semantic_eq() {
+        let eq = |a: &str, b: &str, ignore_key_order: bool| {
+            let options = SemanticEqOptions { ignore_key_order };
+            load(a).semantic_eq(&load(b), options)
+        };
+        assert!(eq("a: 1\nb: [x, 'y']\n", "{a: 0x1, b: [\"x\", y]}", false));
+        assert!(eq("- 1\n- .NaN\n- ~\n", "[!!int '1', .nan, null]", false));
+        assert!(!eq("- 1\n", "- '1'\n", false));
+        assert!(!eq("- !t 1\n", "- !u 1\n", false));
+        assert!(!eq("- a\n", "- a\n- b\n", false));
+        assert!(!eq("a: 1\nb: 2\n", "b: 2\na: 1\n", false));
+        assert!(eq("a: 1\nb: 2\n", "b: 2\na: 1\n", true));
+        assert!(!eq("a: 1\nb: 2\n", "b: 1\na: 2\n", true));
+        assert!(eq("a: &x [1]\nb: *x\n", "a: [1]\nb: [1]\n", false));
+        assert!(eq("&a [*a]", "&b [*b]", false));
+        assert!(eq("", "", false));
+        assert!(!eq("a", "", false));
+        assert!(!eq("- !!str 1\n", "- 1\n", false));
+        assert!(eq("- !!str 1\n", "- '1'\n", false));
+    }
+
+    #[test]
+    fn dump_explicit_str() {
+        let doc = load("[5, !!str 5, !!str x, '6']");
+        let mut emitter = Emitter::new();
+        doc.clone().dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(output, "[5, '5', x, '6']\n");
+        assert!(load(&output).semantic_eq(&doc, SemanticEqOptions::default()));
+    }
+
+    #[test]
+    fn semantic_eq_shared_aliases() {
+        use core::fmt::Write as _;
+
+        // Each level refers twice to the previous one, so the documents have
+        // 2^40 paths to their leaves.
+        let mut input = String::from("- &a0 [x]\n");
+        for level in 1..40 {
+            let previous = level - 1;
+            writeln!(input, "- &a{level} [*a{previous}, *a{previous}]").unwrap();
+        }
+        let doc = load(&input);
+        for ignore_key_order in [false, true] {
+            let options = SemanticEqOptions { ignore_key_order };
+            assert!(doc.semantic_eq(&doc.clone(), options));
+            let other = load(&input.replacen("[x]", "[y]", 1));
+            assert!(!doc.semantic_eq(&other, options));
+        }
+    }
+
+    #[test]
+    fn merge_defaults() {
+        const DEFAULTS: &str = "port: 80\nlog: {level: info, file: out.log}\nhosts: [a]\n";
+        let merge = |target: &str, defaults: &str, concat_sequences: bool| {
+            let options = MergeOptions { concat_sequences };
+            load(target).merge_defaults(&load(defaults), options)
+        };
+        let expect = |document: &Document, expected: &str| {
+            assert!(
+                document.semantic_eq(&load(expected), SemanticEqOptions::default()),
+                "{document:?}"
+            );
+        };
+
+        let merged = merge("log: {level: debug}\nhosts: [b]\n", DEFAULTS, false);
+        expect(
+            &merged,
+            "log: {level: debug, file: out.log}\nhosts: [b]\nport: 80\n",
+        );
+        let merged = merge("hosts: [b]\n", DEFAULTS, true);
+        expect(
+            &merged,
+            "hosts: [b, a]\nport: 80\nlog: {level: info, file: out.log}\n",
+        );
+        expect(&merge("", DEFAULTS, false), DEFAULTS);
+        expect(&merge("[x]", DEFAULTS, false), "[x]");
+
+        let merged = merge("a: 1\n", "b: &x [1]\nc: *x\n", false);
+        expect(&merged, "a: 1\nb: [1]\nc: [1]\n");
+        let root = merged.get_node(1).unwrap();
+        let NodeData::Mapping { pairs, .. } = &root.data else {
+            panic!()
+        };
+        assert_eq!(pairs[1].value, pairs[2].value);
+    }
+
+    #[test]
+    fn validate() {
+        let doc = load("a: [b, c]\n");
+        doc.validate().unwrap();
+
+        let mut doc = load("a: [b, c]\n");
+        doc.nodes[3].tag = Some(String::new());
+        let err = doc.validate().unwrap_err();
+        assert_eq!(err.problem(), "tag value must not be empty");
+        assert_eq!(err.node_id(), Some(4));
+        assert_eq!(err.problem_mark(), None);
+
+        let mut doc = load("a\n");
+        doc.tag_directives.push(TagDirective {
+            handle: String::from("x"),
+            prefix: String::from("tag:example.com,2000:"),
+        });
+        let err = doc.validate().unwrap_err();
+        assert_eq!(err.problem(), "tag handle must start with '!'");
+        assert_eq!(err.node_id(), None);
+    }
+
+    #[test]
+    fn tag_resolver() {
+        let mut parser = Parser::new();
+        let mut input = &b"- 1\n- '1'\n- ! 1\n- !!str 1\n- [x, null]\n- {}\n"[..];
+        parser.set_input_string(&mut input);
+        parser.set_tag_resolver(|value, tag, kind| match kind {
+            NodeKind::Mapping => Some(String::from("tag:example.com,2000:map")),
+            _ => crate::core_schema_tag(value, tag, kind),
+        });
+        let doc = Document::load(&mut parser).unwrap();
+        let tags: Vec<_> = doc.nodes.iter().map(|node| node.tag.as_deref()).collect();
+        assert_eq!(
+            tags,
+            [
+                Some(DEFAULT_SEQUENCE_TAG),
+                Some(INT_TAG),
+                Some(DEFAULT_SCALAR_TAG),
+                Some(DEFAULT_SCALAR_TAG),
+                Some(DEFAULT_SCALAR_TAG),
+                Some(DEFAULT_SEQUENCE_TAG),
+                Some(DEFAULT_SCALAR_TAG),
+                Some(NULL_TAG),
+                Some("tag:example.com,2000:map"),
+            ]
+        );
+
+        // Plain scalars are resolved with the core schema by default, and
+        // are written back without their resolved tags, unless they cannot
+        // be written plain.
+        let doc = load("[1, true, null, x]\n");
+        let tags: Vec<_> = doc.nodes.iter().map(|node| node.tag.as_deref()).collect();
+        assert_eq!(
+            tags,
+            [
+                Some(DEFAULT_SEQUENCE_TAG),
+                Some(INT_TAG),
+                Some(BOOL_TAG),
+                Some(NULL_TAG),
+                Some(DEFAULT_SCALAR_TAG),
+            ]
+        );
+        let mut emitter = Emitter::new();
+        doc.dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(output, "[1, true, null, x]\n");
+        let doc = load("[1, {e: }]\n");
+        let mut emitter = Emitter::new();
+        doc.dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(output, "[1, {e: !!null ''}]\n");
+
+        let mut parser = Parser::new();
+        let mut input = &b"[1, true]\n"[..];
+        parser.set_input_string(&mut input);
+        parser.set_tag_resolver(|_, _, _| None);
+        let doc = Document::load(&mut parser).unwrap();
+        assert_eq!(doc.nodes[1].tag.as_deref(), Some(DEFAULT_SCALAR_TAG));
+        assert_eq!(doc.nodes[2].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn anchors() {
+        let doc = load("a: &x [&y 1, 2]\nb: &z {}\nc: *y\n");
+        let anchors: Vec<_> = doc
+            .anchors
+            .iter()
+            .map(|(anchor, index)| (anchor.as_str(), doc.get_node(*index).unwrap().start_mark))
+            .collect();
+        assert_eq!(
+            anchors,
+            [
+                ("x", doc.nodes[2].start_mark),
+                ("y", doc.nodes[3].start_mark),
+                ("z", doc.nodes[6].start_mark),
+            ]
+        );
+        assert!(load("a\n").anchors.is_empty());
+    }
+
+    #[test]
+    fn set_anchor() {
+        let dump = |doc: Document| {
+            let mut emitter = Emitter::new();
+            doc.dump(&mut emitter).unwrap();
+            emitter.emit(Event::stream_end()).unwrap();
+            String::from_utf8(emitter.into_vec().unwrap()).unwrap()
+        };
+
+        let mut doc = Document::new(None, &[], true, true);
+        let root = doc.add_sequence(None, SequenceStyle::Block);
+        let defaults = doc.add_scalar(None, "a", ScalarStyle::Plain);
+        let shared = doc.add_scalar(None, "b", ScalarStyle::Plain);
+        let single = doc.add_scalar(None, "c", ScalarStyle::Plain);
+        for item in [defaults, defaults, shared, shared, single] {
+            doc.append_sequence_item(root, item);
+        }
+        doc.set_anchor(defaults, "id001").unwrap();
+        doc.set_anchor(single, "single").unwrap();
+        assert_eq!(
+            doc.set_anchor(shared, "id001").unwrap_err().problem(),
+            "found duplicate anchor"
+        );
+        assert!(doc.set_anchor(shared, "a b").is_err());
+        assert!(doc.set_anchor(shared, "").is_err());
+        assert_eq!(
+            dump(doc),
+            "- &id001 a\n- *id001\n- &id002 b\n- *id002\n- &single c\n"
+        );
+
+        let mut doc = load("a: &x 1\nb: *x\nc: &y 2\n");
+        doc.set_anchor(3, "one").unwrap();
+        assert_eq!(dump(doc), "a: &one 1\nb: *one\nc: &y 2\n");
+    }
+
+    #[test]
+    fn mappings_from_pairs() {
+        let mut doc = Document::new(None, &[], true, true);
+        let root = doc.add_mapping(None, MappingStyle::Block);
+        let inner = doc.add_string_mapping(
+            None,
+            MappingStyle::Block,
+            [("z", "1"), ("a", "it's"), ("m", "two words")],
+        );
+        let key = doc.add_scalar(None, "inner", ScalarStyle::Any);
+        doc.yaml_document_append_mapping_pair(root, key, inner);
+        let pairs = [("b", "[2]"), ("a", "x")].map(|(key, value)| {
+            (
+                doc.add_scalar(None, key, ScalarStyle::Any),
+                doc.add_scalar(None, value, ScalarStyle::Any),
+            )
+        });
+        let flow = doc.add_mapping_from(Some("!pairs"), MappingStyle::Flow, pairs);
+        let key = doc.add_scalar(None, "flow", ScalarStyle::Any);
+        doc.yaml_document_append_mapping_pair(root, key, flow);
+
+        let mut emitter = Emitter::new();
+        doc.dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            "inner:\n  z: 1\n  a: it's\n  m: two words\nflow: !pairs {b: '[2]', a: x}\n"
+        );
+    }
+
+    #[test]
+    fn deanchor() {
+        let mut document = load("a: &x [1, {b: 2}]\nc: *x\nd: *x\n");
+        document.yaml_document_deanchor(100).unwrap();
+        assert!(document.anchors.is_empty());
+        assert_eq!(document.nodes.len(), 19);
+        let mut emitter = Emitter::new();
+        document.dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(output, "a: [1, {b: 2}]\nc: [1, {b: 2}]\nd: [1, {b: 2}]\n");
+
+        let mut document = load("a: &x [1]\nb: *x\n");
+        let err = document.yaml_document_deanchor(6).unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "expanding the aliases exceeds the maximum number of nodes"
+        );
+        assert_eq!(document.nodes.len(), 5);
+        assert_eq!(document.anchors.len(), 1);
+
+        let laughs = "a: &a [x, x]\nb: &b [*a, *a, *a]\nc: &c [*b, *b, *b]\nd: [*c, *c, *c]\n";
+        let err = load(laughs).yaml_document_deanchor(50).unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "expanding the aliases exceeds the maximum number of nodes"
+        );
+
+        let mut document = Document::new(None, &[], true, true);
+        let sequence = document.add_sequence(None, SequenceStyle::Any);
+        document.append_sequence_item(sequence, sequence);
+        let err = document.yaml_document_deanchor(100).unwrap_err();
+        assert_eq!(err.problem(), "found an alias to one of its own ancestors");
+    }
+
+    #[test]
+    fn sequence_sort() {
+        let dump = |compare: Option<fn(&str, &str) -> Ordering>| {
+            let document = load("deps: [serde, libc, 'anyhow']\nmixed: [b, [z, y], a]\n");
+            let mut emitter = Emitter::new();
+            emitter.set_sequence_sort(compare);
+            document.dump(&mut emitter).unwrap();
+            emitter.emit(Event::stream_end()).unwrap();
+            String::from_utf8(emitter.into_vec().unwrap()).unwrap()
+        };
+        assert_eq!(
+            dump(None),
+            "deps: [serde, libc, 'anyhow']\nmixed: [b, [z, y], a]\n"
+        );
+        assert_eq!(
+            dump(Some(str::cmp)),
+            "deps: ['anyhow', libc, serde]\nmixed: [b, [y, z], a]\n"
+        );
+        assert_eq!(
+            dump(Some(|a, b| b.len().cmp(&a.len()))),
+            "deps: ['anyhow', serde, libc]\nmixed: [b, [z, y], a]\n"
+        );
+    }
+
+    #[test]
+    fn error_context_chain() {
+        let mut parser = Parser::from_str("spec:\n  items:\n  - a\n  - b\n  - {x: *missing}\n");
+        let chain = |parser: &Parser| {
+            parser
+                .error_context_chain()
+                .iter()
+                .map(|(context, mark)| (context.clone(), mark.line, mark.column))
+                .collect::<Vec<_>>()
+        };
+        let err = Document::load(&mut parser).unwrap_err();
+        assert_eq!(err.problem(), "found undefined alias");
+        assert_eq!(
+            chain(&parser),
+            [
+                ("while composing mapping value for key 'spec'".into(), 0, 0),
+                ("while composing mapping value for key 'items'".into(), 1, 2),
+                ("while composing sequence item 2".into(), 2, 2),
+                ("while composing mapping value for key 'x'".into(), 4, 5),
+            ]
+        );
+
+        let mut parser = Parser::from_str("? [*y]\n: 1\n");
+        Document::load(&mut parser).unwrap_err();
+        assert_eq!(
+            chain(&parser),
+            [
+                ("while composing mapping key".into(), 0, 0),
+                ("while composing sequence item 0".into(), 0, 2),
+            ]
+        );
+
+        let mut input = &b"a: b\n"[..];
+        let mut parser = Parser::from_str("a: [b, c\n");
+        Document::load(&mut parser).unwrap_err();
+        assert_eq!(chain(&parser).len(), 2);
+        parser.reset_input();
+        parser.set_input_string(&mut input);
+        Document::load(&mut parser).unwrap();
+        assert!(parser.error_context_chain().is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn yaml_load_file() {
+        let path = std::env::temp_dir().join(format!("yaml_load_file_{}.yaml", std::process::id()));
+        let load_file = |content: &[u8]| {
+            std::fs::write(&path, content).unwrap();
+            let result = super::yaml_load_file(&path);
+            std::fs::remove_file(&path).unwrap();
+            result
+        };
+
+        let document = load_file(b"a: [1, 2]\n").unwrap();
+        assert_eq!(document.nodes.len(), 5);
+        let utf16: Vec<u8> = "\u{feff}a: b\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let document = load_file(&utf16).unwrap();
+        assert_eq!(scalar_value(&document.nodes[2]), "b");
+        let document = load_file(b"--- a\n...\n").unwrap();
+        assert_eq!(document.nodes.len(), 1);
+
+        let err = load_file(b"# nothing\n").unwrap_err();
+        assert_eq!(err.problem(), "expected a single document, but found none");
+        let err = load_file(b"a\n--- b\n").unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "expected a single document, but found another"
+        );
+        assert_eq!(err.problem_mark().unwrap().line, 1);
+        let err = load_file(b"[a\n").unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Parser);
+
+        let err = super::yaml_load_file(&path).unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Io);
+    }
+
+    #[test]
+    fn as_scalar() {
+        let doc = load("[abc, !!int 12, 'x y', [z]]\n");
+        let scalar = doc.nodes[1].as_scalar().unwrap();
+        assert_eq!(scalar.len(), 3);
+        assert!(scalar.starts_with("ab"));
+        assert_eq!(&*scalar, "abc");
+        assert_eq!(scalar.style, ScalarStyle::Plain);
+
+        let scalar = doc.nodes[2].as_scalar().unwrap();
+        assert_eq!(scalar.parse::<i32>().unwrap(), 12);
+        assert_eq!(scalar.tag, Some(INT_TAG));
+        assert_eq!(doc.nodes[2].as_str(), None);
+
+        let words = |value: &dyn AsRef<str>| value.as_ref().split(' ').count();
+        assert_eq!(words(&doc.nodes[3].as_scalar().unwrap()), 2);
+
+        assert!(doc.nodes[0].as_scalar().is_none());
+        assert!(doc.nodes[4].as_scalar().is_none());
+    }
+
+    #[test]
+    fn sets() {
+        let mut doc = Document::new(None, &[], true, true);
+        let set = doc.add_set(["b", "a"], MappingStyle::Block);
+        let items = doc.node_ref(set).unwrap().set_items().unwrap();
+        let items: Vec<_> = items.iter().map(|item| item.node.as_str()).collect();
+        assert_eq!(items, [Some("b"), Some("a")]);
+
+        let mut emitter = Emitter::new();
+        doc.dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(output, "!!set\nb:\na:\n");
+
+        let doc = load("--- !!set\n? x\n? y\n");
+        assert_eq!(doc.get_node(1).unwrap().tag.as_deref(), Some(SET_TAG));
+        assert_eq!(doc.node_ref(1).unwrap().set_items().unwrap().len(), 2);
+
+        let doc = load("{x: ~, y: 1}\n");
+        let err = doc.node_ref(1).unwrap().set_items().unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "expected a null set value, found a scalar of another type"
+        );
+        let doc = load("[x]\n");
+        let err = doc.node_ref(1).unwrap().set_items().unwrap_err();
+        assert_eq!(err.problem(), "expected a set, found a sequence");
+
+        let mut doc = load("? x\n");
+        if let NodeData::Mapping { pairs, .. } = &mut doc.nodes[0].data {
+            pairs.push(NodePair { key: 2, value: 100 });
+        }
+        let err = doc.node_ref(1).unwrap().set_items().unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "found a mapping pair that is not in the document"
+        );
+    }
+
+    #[test]
+    fn float_specials() {
+        let doc =
+            load("[.inf, .Inf, .INF, +.inf, -.inf, -.Inf, -.INF, .nan, .NaN, .NAN, inf, nan]");
+        let values: Vec<_> = doc.root_sequence().unwrap().map(Node::as_f64).collect();
+        assert_eq!(values[..4], [Some(f64::INFINITY); 4]);
+        assert_eq!(values[4..7], [Some(f64::NEG_INFINITY); 3]);
+        assert!(values[7..10].iter().all(|value| value.unwrap().is_nan()));
+        assert_eq!(values[10..], [None, None]);
+
+        let mut doc = Document::new(None, &[], true, true);
+        let root = doc.add_sequence(None, SequenceStyle::Flow);
+        for value in [f64::INFINITY, f64::NEG_INFINITY, f64::NAN, 1.0, -2.5e-8] {
+            let item = doc.add_float(value);
+            doc.append_sequence_item(root, item);
+        }
+        let mut emitter = Emitter::new();
+        doc.dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            "[!!float .inf, !!float -.inf, !!float .nan, !!float 1.0, !!float -2.5e-8]\n"
+        );
+
+        let doc = load(&output);
+        let values: Vec<_> = doc.root_sequence().unwrap().map(Node::as_f64).collect();
+        assert_eq!(values[0], Some(f64::INFINITY));
+        assert_eq!(values[1], Some(f64::NEG_INFINITY));
+        assert!(values[2].unwrap().is_nan());
+        assert_eq!(values[3..], [Some(1.0), Some(-2.5e-8)]);
+    }
+
+    #[test]
+    fn int_radix() {
+        let mut doc = Document::new(None, &[], true, true);
+        let root = doc.add_sequence(None, SequenceStyle::Flow);
+        for (value, radix) in [(31, 16), (0o755, 8), (-42, 10), (i64::MAX, 16), (0, 8)] {
+            let item = doc.yaml_document_add_int_radix(value, radix).unwrap();
+            doc.append_sequence_item(root, item);
+        }
+        for (value, radix) in [(1, 2), (-1, 16), (-1, 8), (1, 3)] {
+            let err = doc.yaml_document_add_int_radix(value, radix).unwrap_err();
+            assert_eq!(err.kind(), crate::ErrorKind::Emitter);
+        }
+        let mut emitter = Emitter::new();
+        doc.dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            "[!!int 0x1F, !!int 0o755, !!int -42, !!int 0x7FFFFFFFFFFFFFFF, !!int 0o0]\n"
+        );
+
+        let doc = load(&output);
+        let values: Vec<_> = doc.root_sequence().unwrap().map(Node::as_i64).collect();
+        assert_eq!(
+            values,
+            [Some(31), Some(0o755), Some(-42), Some(i64::MAX), Some(0)]
+        );
+
+        // The core schema has no binary integers.
+        let doc = load("[!!int 0b101, 0b101]\n");
+        let values: Vec<_> = doc.root_sequence().unwrap().map(Node::as_i64).collect();
+        assert_eq!(values, [None, None]);
+    }
+
+    #[test]
+    fn get() {
+        let doc = load("name: a\n1: one\n'2': two\nlist: [x]\nname: b\n");
+        let root = doc.node_ref(1).unwrap();
+        assert_eq!(root.get("name").unwrap().node.as_str(), Some("a"));
+        assert_eq!(root.get("2").unwrap().node.as_str(), Some("two"));
+        assert!(root.get("1").is_none());
+        assert!(root.contains_key("list"));
+        let doc = load("!!str 1: one\n");
+        assert_eq!(
+            doc.node_ref(1).unwrap().get("1").unwrap().node.as_str(),
+            Some("one")
+        );
+        assert!(!root.contains_key("missing"));
+
+        let list = root.get("list").unwrap();
+        assert!(list.get("x").is_none());
+        assert!(!list.contains_key("x"));
+    }
+
+    #[test]
+    fn load_with_observer() {
+        let mut parser = Parser::from_str("a: [b, c]\n--- d\n");
+        let mut scalars = Vec::new();
+        let mut count = 0;
+        let mut observer = |event: &Event| {
+            count += 1;
+            if let EventData::Scalar { value, .. } = &event.data {
+                scalars.push((value.clone(), event.start_mark.column));
+            }
+        };
+        let doc = Document::load_with_observer(&mut parser, &mut observer).unwrap();
+        assert_eq!(doc.nodes.len(), 5);
+        let doc = Document::load_with_observer(&mut parser, &mut observer).unwrap();
+        assert_eq!(doc.nodes.len(), 1);
+        let doc = Document::load_with_observer(&mut parser, &mut observer).unwrap();
+        assert!(doc.nodes.is_empty());
+        assert_eq!(count, 14);
+        assert_eq!(
+            scalars,
+            [
+                (String::from("a"), 0),
+                (String::from("b"), 4),
+                (String::from("c"), 7),
+                (String::from("d"), 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn complex_keys() {
+        let mut doc = Document::new(None, &[], true, true);
+        let root = doc.add_mapping(None, MappingStyle::Block);
+        let key = doc.add_sequence(None, SequenceStyle::Flow);
+        for item in ["a", "b"] {
+            let item = doc.add_scalar(None, item, ScalarStyle::Plain);
+            doc.append_sequence_item(key, item);
+        }
+        let value = doc.add_scalar(None, "value", ScalarStyle::Plain);
+        doc.yaml_document_append_mapping_pair(root, key, value);
+        let key = doc.add_mapping(None, MappingStyle::Block);
+        let inner = doc.add_scalar(None, "k", ScalarStyle::Plain);
+        let value = doc.add_scalar(None, "v", ScalarStyle::Plain);
+        doc.yaml_document_append_mapping_pair(key, inner, value);
+        let value = doc.add_scalar(None, "other", ScalarStyle::Plain);
+        doc.yaml_document_append_mapping_pair(root, key, value);
+
+        let mut emitter = Emitter::new();
+        doc.dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(output, "? [a, b]\n: value\n? k: v\n: other\n");
+
+        let doc = load(&output);
+        let keys: Vec<_> = doc
+            .root_mapping()
+            .unwrap()
+            .map(|(key, _)| {
+                matches!(
+                    key.data,
+                    NodeData::Sequence { .. } | NodeData::Mapping { .. }
+                )
+            })
+            .collect();
+        assert_eq!(keys, [true, true]);
+    }
+
+    #[test]
+    fn duplicate_anchor_policy() {
+        let input = "[&a 1, *a, &a 2, *a]";
+        let mut parser = Parser::from_str(input);
+        let err = Document::load(&mut parser).unwrap_err();
+        assert_eq!(err.problem(), "second occurrence");
+
+        for (policy, expected) in [
+            (DuplicateAnchorPolicy::LastWins, ["1", "1", "2", "2"]),
+            (DuplicateAnchorPolicy::FirstWins, ["1", "1", "2", "1"]),
+        ] {
+            let mut parser = Parser::from_str(input);
+            parser.set_duplicate_anchor_policy(policy);
+            let doc = Document::load(&mut parser).unwrap();
+            let values: Vec<_> = doc.root_sequence().unwrap().map(scalar_value).collect();
+            assert_eq!(values, expected);
+            assert_eq!(doc.anchors.len(), 1);
+        }
+    }
+
+    #[test]
+    fn tag_policy() {
+        const INPUT: &str = "- !point {x: 1}\n- !!int '3'\n- ! a\n- b\n";
+        let load_with = |policy| {
+            let mut parser = Parser::from_str(INPUT);
+            parser.set_tag_policy(policy, &["!point"]);
+            Document::load(&mut parser)
+        };
+        let tags = |doc: Document| -> Vec<Option<String>> {
+            doc.nodes.into_iter().map(|node| node.tag).collect()
+        };
+
+        let doc = load_with(crate::TagPolicy::PassThrough).unwrap();
+        assert_eq!(doc.nodes[4].tag.as_deref(), Some(INT_TAG));
+
+        let err = load_with(crate::TagPolicy::Error).unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Composer);
+        assert_eq!(err.problem(), "found a tag that is not allowed");
+        let mark = err.problem_mark().unwrap();
+        assert_eq!((mark.line, mark.column), (1, 2));
+
+        let str_tag = Some(String::from(DEFAULT_SCALAR_TAG));
+        assert_eq!(
+            tags(load_with(crate::TagPolicy::Strip).unwrap()),
+            [
+                Some(String::from(DEFAULT_SEQUENCE_TAG)),
+                Some(String::from("!point")),
+                str_tag.clone(),
+                Some(String::from(INT_TAG)),
+                str_tag.clone(),
+                str_tag.clone(),
+                str_tag,
+            ]
+        );
+    }
+
+    #[test]
+    fn non_specific_tag() {
+        const INPUT: &str = "a: ! 1\nb: ! [x]\nc: ! {y: z}\n";
+        let doc = load(INPUT);
+        let tags: Vec<_> = doc.nodes.iter().map(|node| node.tag.as_deref()).collect();
+        assert_eq!(tags[2], Some(DEFAULT_SCALAR_TAG));
+        assert_eq!(tags[4], Some(DEFAULT_SEQUENCE_TAG));
+        assert_eq!(tags[7], Some(DEFAULT_MAPPING_TAG));
+
+        for (input, column) in [(INPUT, 3), ("- ! [x]\n", 2), ("! {y: z}\n", 0)] {
+            let mut parser = Parser::from_str(input);
+            parser.set_allow_non_specific_tag(false);
+            let err = Document::load(&mut parser).unwrap_err();
+            assert_eq!(err.kind(), crate::ErrorKind::Composer);
+            assert_eq!(
+                err.problem(),
+                "found a non-specific tag that is not allowed"
+            );
+            assert_eq!(err.problem_mark().unwrap().column, column, "{input:?}");
+        }
+
+        let mut parser = Parser::from_str("a: !!str 1\n");
+        parser.set_allow_non_specific_tag(false);
+        Document::load(&mut parser).unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Document>();
+        assert_send_sync::<Node>();
+        assert_send_sync::<NodeRef<'_>>();
+        assert_send_sync::<LoadArena>();
+        assert_send_sync::<Event>();
+        assert_send_sync::<Error>();
+
+        let doc = load("a: [1, 2]\n");
+        let sum = std::thread::spawn(move || {
+            let NodeData::Sequence { items, .. } = &doc.get_node(3).unwrap().data else {
+                panic!("expected a sequence")
+            };
+            items
+                .iter()
+                .map(|item| doc.get_node(*item).unwrap().as_i64().unwrap())
+                .sum::<i64>()
+        });
+        assert_eq!(sum.join().unwrap(), 3);
+    }
+
+    #[test]
+    fn directives_round_trip() {
+        const INPUT: &str =
+            "%YAML 1.2\n%TAG !e! tag:example.com,2000:app/\n--- !e!foo\na: !e!bar b\n";
+        let doc = load(INPUT);
+        assert_eq!(
+            doc.version_directive,
+            Some(VersionDirective { major: 1, minor: 2 })
+        );
+        assert_eq!(doc.tag_directives.len(), 1);
+
+        let mut emitter = Emitter::new();
+        doc.dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(output, INPUT);
+    }
+
+    #[test]
+    fn try_from_nodes() {
+        let doc = load("[[1, 0x10], [2.5], a, true, {}]\n");
+        let root = doc.node_ref(1).unwrap();
+        let NodeData::Sequence { items, .. } = &root.node.data else {
+            panic!("expected sequence")
+        };
+        let item = |index: usize| doc.node_ref(items[index]).unwrap();
+
+        assert_eq!(Vec::<i64>::try_from(item(0)).unwrap(), [1, 16]);
+        assert_eq!(Vec::<f64>::try_from(item(0)).unwrap(), [1.0, 16.0]);
+        assert_eq!(Vec::<f64>::try_from(item(1)).unwrap(), [2.5]);
+        assert_eq!(String::try_from(item(2)).unwrap(), "a");
+        assert!(bool::try_from(item(3).node).unwrap());
+
+        let err = Vec::<Vec<i64>>::try_from(root).unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "expected an integer, found a scalar of another type"
+        );
+        assert_eq!(err.problem_mark(), Some(doc.nodes[5].start_mark));
+        let err = i64::try_from(item(4)).unwrap_err();
+        assert_eq!(err.problem(), "expected an integer, found a mapping");
+        let err = Vec::<String>::try_from(item(2)).unwrap_err();
+        assert_eq!(err.problem(), "expected a sequence, found a scalar");
+        assert_eq!(
+            err.to_string(),
+            "Composer error: line 0 column 19: expected a sequence, found a scalar"
+        );
+
+        let mut doc = doc;
+        if let NodeData::Sequence { items, .. } = &mut doc.nodes[1].data {
+            items.push(100);
         }
-        let event = Event::mapping_end();
-        emitter.emit(event)
+        let err = Vec::<i64>::try_from(doc.node_ref(2).unwrap()).unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "found a sequence item that is not in the document"
+        );
     }
 }
@@ -1,11 +1,20 @@
-use std::collections::VecDeque;
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::VecDeque,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cmp::Ordering;
 
 use crate::macros::{
     is_alpha, is_ascii, is_blank, is_blankz, is_bom, is_break, is_breakz, is_printable, is_space,
 };
+use crate::resolver;
 use crate::{
-    Break, Encoding, Error, Event, EventData, MappingStyle, Result, ScalarStyle, SequenceStyle,
-    TagDirective, VersionDirective, OUTPUT_BUFFER_SIZE,
+    Break, Document, Encoding, Error, Event, EventData, MappingStyle, Result, ScalarStyle,
+    SequenceStyle, TagDirective, VersionDirective, BOOL_TAG, DEFAULT_TAG_DIRECTIVES, FLOAT_TAG,
+    INT_TAG, NULL_TAG, OUTPUT_BUFFER_SIZE, STR_TAG,
 };
 
 /// The emitter structure.
@@ -15,7 +24,11 @@ use crate::{
 #[non_exhaustive]
 pub struct Emitter<'w> {
     /// Write handler.
-    pub(crate) write_handler: Option<&'w mut dyn std::io::Write>,
+    pub(crate) write_handler: Option<WriteHandler<'w>>,
+    /// Formatter write handler, used instead of `write_handler`.
+    pub(crate) fmt_write_handler: Option<&'w mut dyn core::fmt::Write>,
+    /// The output, if no write handler is set.
+    pub(crate) owned_output: Vec<u8>,
     /// The working buffer.
     ///
     /// This always contains valid UTF-8.
@@ -33,10 +46,61 @@ pub struct Emitter<'w> {
     pub(crate) best_indent: i32,
     /// The preferred width of the output lines.
     pub(crate) best_width: i32,
+    /// The preferred width of the lines of mapping keys, if not `best_width`.
+    pub(crate) key_width: Option<i32>,
+    /// The preferred width of the lines of mapping values, if not
+    /// `best_width`.
+    pub(crate) value_width: Option<i32>,
+    /// The quoted style used when a scalar cannot be written plain.
+    pub(crate) quote_preference: QuotePreference,
+    /// Always write mapping keys with the `?` indicator?
+    pub(crate) force_explicit_keys: bool,
+    /// Pad the simple keys of block mappings so their values are aligned?
+    pub(crate) align_values: bool,
+    /// Separate the keys of the root block mapping with blank lines?
+    pub(crate) blank_line_between_top_level_keys: bool,
+    /// The order of the items of the scalar sequences of dumped documents, if
+    /// they are sorted.
+    pub(crate) sequence_sort: Option<fn(&str, &str) -> Ordering>,
+    /// The width of the widest simple key of each block mapping being
+    /// emitted, or 0 if the values are not aligned.
+    pub(crate) key_alignments: Vec<i32>,
+    /// The width of the widest simple key of the mapping being started.
+    pub(crate) pending_key_alignment: i32,
+    /// Are the default `!` and `!!` tag directives defined?
+    pub(crate) default_tag_directives: bool,
+    /// Write every tag in the verbatim `!<...>` form?
+    pub(crate) prefer_verbatim_tags: bool,
+    /// Write output that is also valid JSON?
+    pub(crate) json_compatible: bool,
+    /// Write single-pair mappings in flow sequences as `[key: value]`?
+    pub(crate) compact_flow_mapping: bool,
+    /// Is the flow mapping being written a compact single pair?
+    pub(crate) compact_mapping: bool,
+    /// The length above which scalar mapping values start on a new line.
+    pub(crate) value_on_new_line_threshold: Option<usize>,
+    /// The length above which plain scalars are written as folded scalars.
+    pub(crate) auto_fold_threshold: Option<usize>,
+    /// Indent block sequences that are mapping values under their key?
+    pub(crate) sequence_under_key_indent: bool,
+    /// Quote strings that would be resolved to another type if plain?
+    pub(crate) quote_ambiguous_strings: bool,
+    /// Write every scalar mapping key double-quoted?
+    pub(crate) quote_keys: bool,
+    /// Wrap flow collections that exceed the preferred width?
+    pub(crate) flow_wrap: bool,
+    /// The number of spaces prefixed to every output line.
+    pub(crate) base_indent: i32,
+    /// The maximum allowed width of the output lines, if any.
+    pub(crate) hard_line_limit: Option<usize>,
     /// Allow unescaped non-ASCII characters?
     pub(crate) unicode: bool,
+    /// The function deciding which printable characters are escaped, if set.
+    pub(crate) escape_policy: Option<EscapePolicy>,
     /// The preferred line break.
     pub(crate) line_break: Break,
+    /// Does the output end with a line break?
+    pub(crate) trailing_newline: bool,
     /// The stack of states.
     pub(crate) states: Vec<EmitterState>,
     /// The current emitter state.
@@ -45,8 +109,12 @@ pub struct Emitter<'w> {
     pub(crate) events: VecDeque<Event>,
     /// The stack of indentation levels.
     pub(crate) indents: Vec<i32>,
+    /// The part of each mapping being written, innermost last.
+    pub(crate) width_contexts: Vec<WidthContext>,
     /// The list of tag directives.
     pub(crate) tag_directives: Vec<TagDirective>,
+    /// The tag directives written in every document.
+    pub(crate) registered_tag_directives: Vec<TagDirective>,
     /// The current indentation level.
     pub(crate) indent: i32,
     /// The current flow level.
@@ -67,17 +135,16 @@ pub struct Emitter<'w> {
     pub(crate) whitespace: bool,
     /// If the last character was an indentation character (' ', '-', '?', ':')?
     pub(crate) indention: bool,
+    /// If the last line break written is part of a block scalar?
+    pub(crate) block_scalar_break: bool,
     /// If an explicit document end is required?
     pub(crate) open_ended: i32,
+    /// When to write the `...` document end marker for open-ended documents.
+    pub(crate) open_ended_policy: OpenEndedPolicy,
     /// If the stream was already opened?
     pub(crate) opened: bool,
     /// If the stream was already closed?
     pub(crate) closed: bool,
-    /// The information associated with the document nodes.
-    // Note: Same length as `document.nodes`.
-    pub(crate) anchors: Vec<Anchors>,
-    /// The last assigned anchor id.
-    pub(crate) last_anchor_id: i32,
 }
 
 impl<'a> Default for Emitter<'a> {
@@ -129,24 +196,82 @@ pub enum EmitterState {
     End = 17,
 }
 
-#[derive(Copy, Clone, Default)]
-pub(crate) struct Anchors {
-    /// The number of references.
-    pub references: i32,
-    /// The anchor id.
-    pub anchor: i32,
-    /// If the node has been emitted?
-    pub serialized: bool,
+/// Policy for writing the `...` document end marker after documents that do
+/// not request it explicitly.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum OpenEndedPolicy {
+    /// Write the marker only when it is needed to keep the stream
+    /// unambiguous, such as before the directives of the next document, or at
+    /// the end of the stream after a block scalar with "keep" chomping.
+    #[default]
+    Auto,
+    /// Never write the marker automatically.
+    ///
+    /// This can produce streams that do not parse back the same way. For
+    /// example, the directives of a document following a document ending in a
+    /// plain scalar may be read as part of that scalar, and trailing line
+    /// breaks of a "keep" block scalar at the end of the stream may be lost
+    /// when the output is concatenated with other text.
+    Never,
+    /// Write the marker after every document.
+    Always,
+}
+
+/// The quoted style preferred by the emitter when a scalar cannot be written
+/// in the plain style.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum QuotePreference {
+    /// Prefer the single-quoted style.
+    #[default]
+    Single,
+    /// Prefer the double-quoted style.
+    Double,
+}
+
+/// A function deciding which printable characters the emitter escapes, see
+/// [`Emitter::set_escape_policy()`].
+pub type EscapePolicy = Box<dyn Fn(char) -> bool>;
+
+/// The part of a mapping a preferred line width applies to, see
+/// [`Emitter::set_width_for()`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum WidthContext {
+    /// Mapping keys.
+    Key,
+    /// Mapping values.
+    Value,
+    /// Every node without a width of its own.
+    Any,
+}
+
+/// The place a scalar is written in, see [`scalar_needs_quoting()`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum ScalarContext {
+    /// A simple key of a block mapping.
+    BlockKey,
+    /// A value in block context, including block sequence items.
+    BlockValue,
+    /// A key of a flow mapping.
+    FlowKey,
+    /// A value in flow context, including flow sequence items.
+    FlowValue,
 }
 
 #[derive(Default)]
 struct Analysis<'a> {
     pub anchor: Option<AnchorAnalysis<'a>>,
     pub tag: Option<TagAnalysis<'a>>,
+    /// The tag of a plain implicit scalar, written if the scalar cannot be
+    /// written plain.
+    pub implied_tag: Option<TagAnalysis<'a>>,
     pub scalar: Option<ScalarAnalysis<'a>>,
 }
 
-struct AnchorAnalysis<'a> {
+pub(crate) struct AnchorAnalysis<'a> {
     pub anchor: &'a str,
     pub alias: bool,
 }
@@ -156,6 +281,28 @@ struct TagAnalysis<'a> {
     pub suffix: &'a str,
 }
 
+/// The output of an emitter, either borrowed or owned.
+pub(crate) enum WriteHandler<'w> {
+    Buffer(&'w mut Vec<u8>),
+    #[cfg(feature = "std")]
+    Borrowed(&'w mut dyn std::io::Write),
+    #[cfg(feature = "std")]
+    Owned(Box<dyn std::io::Write>),
+}
+
+impl WriteHandler<'_> {
+    fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+        match self {
+            WriteHandler::Buffer(buffer) => buffer.extend_from_slice(bytes),
+            #[cfg(feature = "std")]
+            WriteHandler::Borrowed(writer) => writer.write_all(bytes)?,
+            #[cfg(feature = "std")]
+            WriteHandler::Owned(writer) => writer.write_all(bytes)?,
+        }
+        Ok(())
+    }
+}
+
 struct ScalarAnalysis<'a> {
     /// The scalar value.
     pub value: &'a str,
@@ -178,19 +325,47 @@ impl<'w> Emitter<'w> {
     pub fn new() -> Emitter<'w> {
         Emitter {
             write_handler: None,
+            fmt_write_handler: None,
+            owned_output: Vec::new(),
             buffer: String::with_capacity(OUTPUT_BUFFER_SIZE),
             raw_buffer: Vec::with_capacity(OUTPUT_BUFFER_SIZE),
             encoding: Encoding::Any,
             canonical: false,
             best_indent: 0,
             best_width: 0,
+            key_width: None,
+            value_width: None,
+            base_indent: 0,
+            hard_line_limit: None,
+            flow_wrap: true,
+            quote_ambiguous_strings: false,
+            quote_keys: false,
+            force_explicit_keys: false,
+            align_values: false,
+            blank_line_between_top_level_keys: false,
+            sequence_sort: None,
+            key_alignments: Vec::new(),
+            pending_key_alignment: 0,
+            default_tag_directives: true,
+            prefer_verbatim_tags: false,
+            value_on_new_line_threshold: None,
+            auto_fold_threshold: None,
+            compact_flow_mapping: false,
+            json_compatible: false,
+            compact_mapping: false,
+            sequence_under_key_indent: false,
+            quote_preference: QuotePreference::default(),
             unicode: false,
+            escape_policy: None,
             line_break: Break::default(),
+            trailing_newline: true,
             states: Vec::with_capacity(16),
             state: EmitterState::default(),
             events: VecDeque::with_capacity(16),
             indents: Vec::with_capacity(16),
+            width_contexts: Vec::new(),
             tag_directives: Vec::with_capacity(16),
+            registered_tag_directives: Vec::new(),
             indent: 0,
             flow_level: 0,
             root_context: false,
@@ -201,11 +376,11 @@ impl<'w> Emitter<'w> {
             column: 0,
             whitespace: false,
             indention: false,
+            block_scalar_break: false,
             open_ended: 0,
+            open_ended_policy: OpenEndedPolicy::default(),
             opened: false,
             closed: false,
-            anchors: Vec::new(),
-            last_anchor_id: 0,
         }
     }
 
@@ -214,6 +389,54 @@ impl<'w> Emitter<'w> {
         *self = Self::new();
     }
 
+    /// Reset the emitter state and remove the output, but keep the
+    /// configuration.
+    ///
+    /// The style settings, such as the indentation, the line widths and the
+    /// canonical and JSON-compatible modes, the escape policy and the
+    /// registered tag directives are kept. The encoding is not, so a new
+    /// output and encoding can then be set to emit another stream, for
+    /// example after [`Emitter::close()`]. Output that was not flushed is
+    /// discarded.
+    pub fn reset_output(&mut self) {
+        *self = self.take_config();
+    }
+
+    /// Make a new emitter with the configuration of this one, see
+    /// [`Emitter::reset_output()`].
+    fn take_config<'v>(&mut self) -> Emitter<'v> {
+        let mut emitter = Emitter::new();
+        emitter.canonical = self.canonical;
+        emitter.best_indent = self.best_indent;
+        emitter.best_width = self.best_width;
+        emitter.key_width = self.key_width;
+        emitter.value_width = self.value_width;
+        emitter.quote_preference = self.quote_preference;
+        emitter.force_explicit_keys = self.force_explicit_keys;
+        emitter.align_values = self.align_values;
+        emitter.blank_line_between_top_level_keys = self.blank_line_between_top_level_keys;
+        emitter.sequence_sort = self.sequence_sort;
+        emitter.default_tag_directives = self.default_tag_directives;
+        emitter.prefer_verbatim_tags = self.prefer_verbatim_tags;
+        emitter.json_compatible = self.json_compatible;
+        emitter.compact_flow_mapping = self.compact_flow_mapping;
+        emitter.value_on_new_line_threshold = self.value_on_new_line_threshold;
+        emitter.auto_fold_threshold = self.auto_fold_threshold;
+        emitter.sequence_under_key_indent = self.sequence_under_key_indent;
+        emitter.quote_ambiguous_strings = self.quote_ambiguous_strings;
+        emitter.quote_keys = self.quote_keys;
+        emitter.flow_wrap = self.flow_wrap;
+        emitter.base_indent = self.base_indent;
+        emitter.hard_line_limit = self.hard_line_limit;
+        emitter.unicode = self.unicode;
+        emitter.escape_policy = self.escape_policy.take();
+        emitter.line_break = self.line_break;
+        emitter.trailing_newline = self.trailing_newline;
+        emitter.registered_tag_directives = core::mem::take(&mut self.registered_tag_directives);
+        emitter.open_ended_policy = self.open_ended_policy;
+        emitter
+    }
+
     /// Start a YAML stream.
     ///
     /// This function should be used before
@@ -241,24 +464,127 @@ impl<'w> Emitter<'w> {
         Ok(())
     }
 
+    /// Emit the content of a document as a fragment, for splicing into other
+    /// text.
+    ///
+    /// Unlike [`Document::dump()`](crate::Document::dump), this does not need
+    /// [`Emitter::open()`] or [`Emitter::close()`], and writes no BOM,
+    /// directives, `---` or `...`, only the root node followed by a line
+    /// break. The indentation and widths apply as usual, and
+    /// [`Emitter::set_base_indent()`] sets the indentation of the fragment.
+    /// Since no `%TAG` directive is written, tags are only shortened with the
+    /// default `!` and `!!` handles.
+    ///
+    /// Several fragments can be emitted in a row, or between the documents of
+    /// a stream, but not inside a document. A document without nodes writes
+    /// nothing.
+    pub fn emit_fragment(&mut self, mut document: Document) -> Result<()> {
+        match self.state {
+            EmitterState::StreamStart => {
+                self.start_stream(Encoding::Any);
+                self.state = EmitterState::FirstDocumentStart;
+            }
+            EmitterState::FirstDocumentStart | EmitterState::DocumentStart => {}
+            _ => return Err(Error::emitter("expected no open document for a fragment")),
+        }
+        if !self.events.is_empty() {
+            return Err(Error::emitter(
+                "cannot emit a fragment while events are waiting",
+            ));
+        }
+        if document.nodes.is_empty() {
+            return Ok(());
+        }
+        if let Some(compare) = self.sequence_sort {
+            document.sort_scalar_sequences(compare);
+        }
+        self.append_default_tag_directives()?;
+
+        // Skip the DOCUMENT-START and DOCUMENT-END events, and write the root
+        // node as the content of a document.
+        let state = self.state;
+        let mut events = document.into_events();
+        events.pop();
+        self.state = EmitterState::DocumentContent;
+        for event in events.into_iter().skip(1) {
+            self.emit(event)?;
+        }
+        self.write_indent()?;
+        self.flush()?;
+        self.open_ended = 0;
+        self.tag_directives.clear();
+        self.state = state;
+        Ok(())
+    }
+
     /// Set a string output.
     ///
     /// The emitter will write the output characters to the `output` buffer.
     pub fn set_output_string(&mut self, output: &'w mut Vec<u8>) {
-        assert!(self.write_handler.is_none());
+        assert!(self.write_handler.is_none() && self.fmt_write_handler.is_none());
         if self.encoding == Encoding::Any {
             self.set_encoding(Encoding::Utf8);
         } else if self.encoding != Encoding::Utf8 {
             panic!("cannot output UTF-16 to String")
         }
         output.clear();
-        self.write_handler = Some(output);
+        self.write_handler = Some(WriteHandler::Buffer(output));
     }
 
     /// Set a generic output handler.
+    #[cfg(feature = "std")]
     pub fn set_output(&mut self, handler: &'w mut dyn std::io::Write) {
-        assert!(self.write_handler.is_none());
-        self.write_handler = Some(handler);
+        assert!(self.write_handler.is_none() && self.fmt_write_handler.is_none());
+        self.write_handler = Some(WriteHandler::Borrowed(handler));
+    }
+
+    /// Set an owned output handler.
+    ///
+    /// Unlike [`Emitter::set_output()`], the emitter takes ownership of the
+    /// writer, so the emitter does not borrow anything and can be returned
+    /// from a function or stored in a struct. The writer is dropped with the
+    /// emitter.
+    #[cfg(feature = "std")]
+    pub fn set_output_writer<W: std::io::Write + 'static>(&mut self, writer: W) {
+        assert!(self.write_handler.is_none() && self.fmt_write_handler.is_none());
+        self.write_handler = Some(WriteHandler::Owned(Box::new(writer)));
+    }
+
+    /// Consume the emitter and return its output.
+    ///
+    /// When no output handler is set, the emitter collects its output itself,
+    /// which avoids having to borrow an output buffer for the lifetime of the
+    /// emitter. Any buffered output is flushed first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an output handler was set, since the output was then written
+    /// to that handler.
+    pub fn into_vec(mut self) -> Result<Vec<u8>> {
+        assert!(
+            self.write_handler.is_none() && self.fmt_write_handler.is_none(),
+            "output was written to an output handler"
+        );
+        if !self.buffer.is_empty() {
+            self.flush()?;
+        }
+        Ok(core::mem::take(&mut self.owned_output))
+    }
+
+    /// Set a formatter output handler.
+    ///
+    /// This makes it possible to emit YAML directly into a
+    /// [`core::fmt::Formatter`], for example in a `Display` implementation. The
+    /// output encoding must be UTF-8. A formatter error is reported as an I/O
+    /// error, or as an emitter error without the `std` feature.
+    pub fn set_output_fmt(&mut self, handler: &'w mut dyn core::fmt::Write) {
+        assert!(self.write_handler.is_none() && self.fmt_write_handler.is_none());
+        if self.encoding == Encoding::Any {
+            self.set_encoding(Encoding::Utf8);
+        } else if self.encoding != Encoding::Utf8 {
+            panic!("cannot output UTF-16 to a formatter")
+        }
+        self.fmt_write_handler = Some(handler);
     }
 
     /// Set the output encoding.
@@ -279,8 +605,309 @@ impl<'w> Emitter<'w> {
     }
 
     /// Set the preferred line width. -1 means unlimited.
+    ///
+    /// The width can also be changed between the documents of a stream,
+    /// after a DOCUMENT-END event and before the next DOCUMENT-START event, to
+    /// write each document with its own width. Changing it in the middle of a
+    /// document is not supported.
     pub fn set_width(&mut self, width: i32) {
         self.best_width = if width >= 0 { width } else { -1 };
+        self.update_widths();
+    }
+
+    /// Get the preferred line width, as the emitter uses it.
+    ///
+    /// Unlimited widths are `i32::MAX`, and widths that are 0 or not greater
+    /// than twice the indentation increment are replaced by 80.
+    pub fn width(&self) -> i32 {
+        resolve_width(self.best_width, self.resolved_indent())
+    }
+
+    /// Set the preferred line width of mapping keys or mapping values. -1
+    /// means unlimited.
+    ///
+    /// Keys and values use the width set with [`Emitter::set_width()`] unless
+    /// they are given their own, and [`WidthContext::Any`] sets that width.
+    /// The width applies to everything nested in the key or value, up to the
+    /// keys and values of nested mappings. Simple keys are never wrapped, so
+    /// the key width only applies to keys in the explicit `? key` form.
+    pub fn set_width_for(&mut self, context: WidthContext, width: i32) {
+        let width = if width >= 0 { width } else { -1 };
+        match context {
+            WidthContext::Key => self.key_width = Some(width),
+            WidthContext::Value => self.value_width = Some(width),
+            WidthContext::Any => self.best_width = width,
+        }
+        self.update_widths();
+    }
+
+    /// Set the indentation increment, failing if it is not between 2 and 9.
+    ///
+    /// Unlike [`Emitter::set_indent()`], this does not silently fall back to
+    /// the default for invalid values.
+    pub fn try_set_indent(&mut self, indent: i32) -> Result<()> {
+        if !(2..=9).contains(&indent) {
+            return Err(Error::emitter("indent must be between 2 and 9"));
+        }
+        self.best_indent = indent;
+        Ok(())
+    }
+
+    /// Set the preferred line width, failing if it is not -1 (unlimited) or
+    /// greater than twice the indentation increment.
+    ///
+    /// Unlike [`Emitter::set_width()`], this does not silently fall back to
+    /// another width for invalid values. The indentation increment should be
+    /// set first.
+    pub fn try_set_width(&mut self, width: i32) -> Result<()> {
+        if width != -1 && width <= self.resolved_indent() * 2 {
+            return Err(Error::emitter(
+                "width must be -1 or greater than twice the indent",
+            ));
+        }
+        self.best_width = width;
+        self.update_widths();
+        Ok(())
+    }
+
+    /// The indentation increment, where an unset one is replaced by 2 as when
+    /// the stream starts.
+    fn resolved_indent(&self) -> i32 {
+        if (2..=9).contains(&self.best_indent) {
+            self.best_indent
+        } else {
+            2
+        }
+    }
+
+    /// Resolve the widths again when they are changed after the stream
+    /// started, see [`Emitter::set_width()`].
+    fn update_widths(&mut self) {
+        if self.state != EmitterState::StreamStart {
+            self.resolve_widths();
+        }
+    }
+
+    /// Replace the widths that are unlimited or too small, see
+    /// [`Emitter::width()`].
+    fn resolve_widths(&mut self) {
+        let best_indent = self.best_indent;
+        self.best_width = resolve_width(self.best_width, best_indent);
+        if let Some(width) = self.key_width.as_mut() {
+            *width = resolve_width(*width, best_indent);
+        }
+        if let Some(width) = self.value_width.as_mut() {
+            *width = resolve_width(*width, best_indent);
+        }
+    }
+
+    /// Set if flow collections are wrapped when they exceed the preferred
+    /// width.
+    ///
+    /// When enabled (the default), a line break is inserted after the comma
+    /// following the item that crossed the preferred width, and the next item
+    /// is indented to the level of the collection, e.g. `[1, 2, 3,\n  4, 5]`.
+    /// When disabled, flow collections are written on a single line unless the
+    /// output is canonical.
+    pub fn set_flow_wrap(&mut self, wrap: bool) {
+        self.flow_wrap = wrap;
+    }
+
+    /// Set the number of spaces prefixed to every non-empty output line.
+    ///
+    /// This shifts the whole stream to the right, including directives and
+    /// document markers, which is useful when embedding the output in another
+    /// indented file. The preferred line width does not include this prefix.
+    pub fn set_base_indent(&mut self, indent: i32) {
+        self.base_indent = indent.max(0);
+    }
+
+    /// Set the maximum width of the output lines, including the base
+    /// indentation.
+    ///
+    /// Unlike the preferred width, which is only a hint for where to wrap
+    /// scalars, exceeding this limit is an error. Note that the emitter only
+    /// wraps lines after the preferred width has been exceeded, so the
+    /// preferred width should be set somewhat lower than the hard limit.
+    pub fn set_hard_line_limit(&mut self, limit: Option<usize>) {
+        self.hard_line_limit = limit;
+    }
+
+    /// Set when the `...` document end marker is written for documents that
+    /// do not explicitly request it.
+    ///
+    /// See [`OpenEndedPolicy`] for the implications of each policy.
+    pub fn set_open_ended_policy(&mut self, policy: OpenEndedPolicy) {
+        self.open_ended_policy = policy;
+    }
+
+    /// Set the quoted style used when a plain scalar has to be quoted.
+    ///
+    /// Scalars that are explicitly requested in a quoted style keep that
+    /// style, and the single-quoted style falls back to double-quoted when the
+    /// value cannot be single-quoted.
+    pub fn set_quote_preference(&mut self, preference: QuotePreference) {
+        self.quote_preference = preference;
+    }
+
+    /// Set if mapping keys are always written in the explicit `? key` form,
+    /// even when they could be written as simple keys.
+    pub fn set_force_explicit_keys(&mut self, force: bool) {
+        self.force_explicit_keys = force;
+    }
+
+    /// Set if the values of block mappings are aligned, by padding the simple
+    /// keys of each mapping with spaces up to the width of its widest simple
+    /// key.
+    ///
+    /// Nested mappings are aligned independently, and keys written in the
+    /// explicit `? key` form are not padded. The emitter has to see all the
+    /// events of a mapping before writing it, so the events of a whole
+    /// mapping are kept in memory.
+    pub fn set_align_values(&mut self, align: bool) {
+        self.align_values = align;
+    }
+
+    /// Set if a blank line is written before every key of the root block
+    /// mapping of a document but the first one.
+    ///
+    /// Nested mappings are unaffected. The blank line is left out after a
+    /// literal or folded scalar kept with the `+` chomping indicator, where it
+    /// would become part of the value.
+    pub fn set_blank_line_between_top_level_keys(&mut self, blank_line: bool) {
+        self.blank_line_between_top_level_keys = blank_line;
+    }
+
+    /// Set the order in which [`Document::dump()`](crate::Document::dump)
+    /// writes the items of sequences of scalars, or `None` to keep the order
+    /// of the document, which is the default.
+    ///
+    /// The items are compared by value with `compare`, and items that compare
+    /// equal keep their order. A sequence with an item that is not a scalar,
+    /// such as a nested collection, is not sorted. Events emitted with
+    /// [`Emitter::emit()`] are never reordered.
+    pub fn set_sequence_sort(&mut self, compare: Option<fn(&str, &str) -> Ordering>) {
+        self.sequence_sort = compare;
+    }
+
+    /// Set if the default tag directives, binding `!` to `!` and `!!` to
+    /// `tag:yaml.org,2002:`, are used to shorten tags.
+    ///
+    /// When disabled, only the tag directives of the document are used, and
+    /// other tags are written in the verbatim `!<...>` form.
+    pub fn set_default_tag_directives(&mut self, enabled: bool) {
+        self.default_tag_directives = enabled;
+    }
+
+    /// Set if tags are always written in the verbatim `!<...>` form, rather
+    /// than shortened with a matching tag directive, which is the default.
+    ///
+    /// The parser expands every tag, so whether a tag was written with a
+    /// handle like `!e!thing` or verbatim like `!<tag:example.com,2024:thing>`
+    /// is not known when it is emitted: by default both are shortened if a tag
+    /// directive matches, and only tags without a matching directive are
+    /// written verbatim. When enabled, the tag directives are still written
+    /// but never used, and a tag is only written in another form when the
+    /// emitter needs to add the non-specific `!` tag.
+    pub fn set_prefer_verbatim_tags(&mut self, prefer: bool) {
+        self.prefer_verbatim_tags = prefer;
+    }
+
+    /// Register a tag directive written at the start of every document.
+    ///
+    /// Tags starting with `prefix` are then written with the `handle`
+    /// shorthand. A `%TAG` directive only applies to the document it precedes,
+    /// so it is repeated in each document. The tag directives of a
+    /// DOCUMENT-START event take precedence over registered ones with the same
+    /// handle, and registering a handle again replaces its prefix.
+    pub fn register_tag_directive(&mut self, handle: &str, prefix: &str) -> Result<()> {
+        let value = TagDirective {
+            handle: String::from(handle),
+            prefix: String::from(prefix),
+        };
+        Self::analyze_tag_directive(&value)?;
+        if let Some(existing) = self
+            .registered_tag_directives
+            .iter_mut()
+            .find(|tag_directive| tag_directive.handle == value.handle)
+        {
+            *existing = value;
+        } else {
+            self.registered_tag_directives.push(value);
+        }
+        Ok(())
+    }
+
+    /// Set if the output must also be valid JSON.
+    ///
+    /// In this mode, all collections are written in the flow style, mapping
+    /// keys and strings are double-quoted with JSON escapes, and scalars that
+    /// resolve to nulls, booleans and numbers in the core schema are written
+    /// as the JSON literals `null`, `true`, `false` and numbers. Numbers that
+    /// JSON cannot represent, like `.inf`, are written as strings. Tags,
+    /// comments, directives and document markers are not written, so each
+    /// document is written as one JSON value. Anchors, aliases, collections
+    /// as mapping keys and keys longer than the 1024 bytes an implicit key can
+    /// span are an error.
+    pub fn set_json_compatible(&mut self, json_compatible: bool) {
+        self.json_compatible = json_compatible;
+    }
+
+    /// Set if a mapping with a single pair of scalars in a flow sequence is
+    /// written in the compact `[key: value]` form instead of as
+    /// `[{key: value}]`.
+    ///
+    /// Mappings with an anchor or an explicit tag are always written with
+    /// braces, since the compact form cannot express them.
+    pub fn set_compact_flow_mapping(&mut self, compact: bool) {
+        self.compact_flow_mapping = compact;
+    }
+
+    /// Set the length, in characters, above which a scalar value of a block
+    /// mapping is written on its own line, indented under its key, instead of
+    /// after `key: `.
+    pub fn set_value_on_new_line_threshold(&mut self, threshold: usize) {
+        self.value_on_new_line_threshold = Some(threshold);
+    }
+
+    /// Set the length, in characters, above which a single-line scalar that
+    /// would be written plain is written as a folded (`>`) block scalar
+    /// instead, folded at the preferred line width.
+    ///
+    /// Only scalars in block context that are not simple keys are folded.
+    /// Scalars whose tag is implied by the plain style are only folded if
+    /// their value resolves to a string, so the loaded value does not change.
+    pub fn set_auto_fold_threshold(&mut self, threshold: Option<usize>) {
+        self.auto_fold_threshold = threshold;
+    }
+
+    /// Set if block sequences that are mapping values are indented under
+    /// their key (`key:\n  - item`) instead of aligned with it
+    /// (`key:\n- item`), which is the default.
+    pub fn set_sequence_under_key_indent(&mut self, indent: bool) {
+        self.sequence_under_key_indent = indent;
+    }
+
+    /// Set if strings that look like another type are quoted.
+    ///
+    /// When enabled, a scalar that is tagged `!!str` or is not plain implicit,
+    /// and whose value a reader would resolve to a null, boolean, integer or
+    /// floating-point number if written plain (such as `true`, `123`, `null`,
+    /// or the YAML 1.1 boolean `no`), is written in a quoted style instead.
+    pub fn set_quote_ambiguous_strings(&mut self, quote: bool) {
+        self.quote_ambiguous_strings = quote;
+    }
+
+    /// Set if scalar mapping keys are always written double-quoted, even when
+    /// they could be written plain, which is not the default.
+    ///
+    /// Both simple keys and keys written in the explicit `? key` form are
+    /// quoted, and the keys are written as strings, without the tag that
+    /// would otherwise keep a quoted key such as `1` resolving to an integer.
+    /// Values are unaffected, and a style forced on a key with
+    /// [`Event::with_forced_style()`] takes precedence.
+    pub fn set_quote_keys(&mut self, quote: bool) {
+        self.quote_keys = quote;
     }
 
     /// Set if unescaped non-ASCII characters are allowed.
@@ -288,11 +915,34 @@ impl<'w> Emitter<'w> {
         self.unicode = unicode;
     }
 
+    /// Set the function deciding which printable characters are escaped.
+    ///
+    /// Scalars containing a character for which the policy returns `true` are
+    /// written in the double-quoted style, with the character escaped. This
+    /// replaces the choice made with [`Emitter::set_unicode()`], so for
+    /// example bidirectional control and zero-width characters can be escaped
+    /// while other non-ASCII characters are written as is. Characters that are
+    /// not printable, line breaks and the byte order mark are always escaped.
+    pub fn set_escape_policy(&mut self, policy: impl Fn(char) -> bool + 'static) {
+        self.escape_policy = Some(Box::new(policy));
+    }
+
     /// Set the preferred line break.
     pub fn set_break(&mut self, line_break: Break) {
         self.line_break = line_break;
     }
 
+    /// Set if the output ends with a line break.
+    ///
+    /// By default, the output of a non-empty stream ends with a single line
+    /// break. When disabled, the last line break is removed when the stream
+    /// is closed, unless it is part of the value of a literal or folded
+    /// scalar. Until then, [`Emitter::flush()`] holds back a line break at the
+    /// end of the output, since it may turn out to be the last one.
+    pub fn set_trailing_newline(&mut self, trailing_newline: bool) {
+        self.trailing_newline = trailing_newline;
+    }
+
     /// Emit an event.
     ///
     /// The event object may be generated using the
@@ -304,8 +954,16 @@ impl<'w> Emitter<'w> {
         while let Some(event) = self.needs_mode_events() {
             let tag_directives = core::mem::take(&mut self.tag_directives);
 
-            let mut analysis = self.analyze_event(&event, &tag_directives)?;
-            self.state_machine(&event, &mut analysis)?;
+            let mut analysis = self
+                .analyze_event(&event, &tag_directives)
+                .map_err(|err| err.located_at(&event))?;
+            if self.align_values {
+                if let EventData::MappingStart { .. } = &event.data {
+                    self.pending_key_alignment = self.aligned_key_width(&tag_directives);
+                }
+            }
+            self.state_machine(&event, &mut analysis)
+                .map_err(|err| err.located_at(&event))?;
 
             // The DOCUMENT-START event populates the tag directives, and this
             // happens only once, so don't swap out the tags in that case.
@@ -316,6 +974,85 @@ impl<'w> Emitter<'w> {
         Ok(())
     }
 
+    /// Write pre-formatted YAML text as is, at the current indentation.
+    ///
+    /// This is an escape hatch for splicing an already formatted fragment,
+    /// such as a vendored configuration block, into the output without
+    /// parsing and emitting it again. The text is not analyzed or escaped:
+    /// the caller guarantees that it is valid YAML at the point where it is
+    /// written, so for example between the pairs of a block mapping it must be
+    /// a sequence of complete pairs. The text starts on a new line, and each
+    /// of its lines is prefixed with the current indentation.
+    ///
+    /// The emitter writes an event only once it has seen enough of the events
+    /// following it, so the text can only be written when no event is
+    /// waiting, for example not right after a SEQUENCE-START or MAPPING-START
+    /// event, and not at all while values are aligned with
+    /// [`Emitter::set_align_values()`].
+    pub fn write_verbatim(&mut self, text: &str) -> Result<()> {
+        if matches!(self.state, EmitterState::StreamStart | EmitterState::End) {
+            return Err(Error::emitter("expected an open stream for verbatim text"));
+        }
+        if !self.events.is_empty() {
+            return Err(Error::emitter(
+                "cannot write verbatim text while events are waiting",
+            ));
+        }
+        let mut breaks = true;
+        for ch in text.chars() {
+            if is_break(ch) {
+                self.write_break(ch)?;
+                self.indention = true;
+                self.whitespace = true;
+                breaks = true;
+            } else {
+                if breaks {
+                    self.write_indent()?;
+                }
+                self.write_char(ch)?;
+                self.indention = false;
+                self.whitespace = is_blank(ch);
+                breaks = false;
+            }
+        }
+        self.block_scalar_break = false;
+        Ok(())
+    }
+
+    /// Get the number of flow collections that are open.
+    ///
+    /// Like the other accessors of the emitter position, this describes the
+    /// events that were written, not the events waiting to be written, see
+    /// [`Emitter::write_verbatim()`].
+    pub fn flow_level(&self) -> i32 {
+        self.flow_level
+    }
+
+    /// Get the indentation of the block collection being written, in columns
+    /// and not counting [`Emitter::set_base_indent()`].
+    ///
+    /// This is the indentation [`Emitter::write_verbatim()`] writes at, and is
+    /// 0 outside of block collections.
+    pub fn current_indent(&self) -> i32 {
+        self.indent.max(0)
+    }
+
+    /// Check if the emitter is inside a flow collection.
+    pub fn in_flow_context(&self) -> bool {
+        self.flow_level != 0
+    }
+
+    /// Check if the next node written is a mapping key.
+    pub fn in_key_context(&self) -> bool {
+        matches!(
+            self.state,
+            EmitterState::FlowMappingFirstKey
+                | EmitterState::FlowMappingKey
+                | EmitterState::BlockMappingFirstKey
+                | EmitterState::BlockMappingKey
+        )
+    }
+
     /// Equivalent of the libyaml `FLUSH` macro.
     fn flush_if_needed(&mut self) -> Result<()> {
         if self.buffer.len() < OUTPUT_BUFFER_SIZE - 5 {
@@ -325,9 +1062,33 @@ impl<'w> Emitter<'w> {
         }
     }
 
+    /// Write the base indentation if nothing has been written on the current
+    /// line yet. This does not count towards `self.column`.
+    fn write_base_indent(&mut self) {
+        if self.column == 0 {
+            for _ in 0..self.base_indent {
+                self.buffer.push(' ');
+            }
+        }
+    }
+
+    /// Fail if writing `width` more characters on the current line would
+    /// exceed the hard line limit. This is checked before the characters are
+    /// buffered, so nothing past the limit is ever written.
+    fn check_line_limit(&self, width: usize) -> Result<()> {
+        if let Some(limit) = self.hard_line_limit {
+            if (self.base_indent + self.column) as usize + width > limit {
+                return Err(Error::emitter("line exceeds hard limit"));
+            }
+        }
+        Ok(())
+    }
+
     /// Equivalent of the libyaml `PUT` macro.
     fn put(&mut self, value: char) -> Result<()> {
+        self.check_line_limit(1)?;
         self.flush_if_needed()?;
+        self.write_base_indent();
         self.buffer.push(value);
         self.column += 1;
         Ok(())
@@ -351,27 +1112,32 @@ impl<'w> Emitter<'w> {
     /// Write UTF-8 charanters from `string` to `emitter` and increment
     /// `emitter.column` the appropriate number of times. It is assumed that the
     /// string does not contain line breaks!
-    fn write_str(&mut self, string: &str) -> Result<()> {
-        if self.buffer.len() + string.len() > OUTPUT_BUFFER_SIZE {
+    fn write_str(&mut self, mut string: &str) -> Result<()> {
+        let width = string.chars().count();
+        self.check_line_limit(width)?;
+        self.write_base_indent();
+        self.column += width as i32;
+
+        // Flush strings that do not fit in the buffer in chunks, so that the
+        // buffer never grows past `OUTPUT_BUFFER_SIZE`.
+        while self.buffer.len() + string.len() > OUTPUT_BUFFER_SIZE {
+            let mut split = OUTPUT_BUFFER_SIZE.saturating_sub(self.buffer.len());
+            while !string.is_char_boundary(split) {
+                split -= 1;
+            }
+            self.buffer.push_str(&string[..split]);
+            string = &string[split..];
             self.flush()?;
         }
-
-        // Note: Reserves less than what is necessary if there are UTF-8
-        // characters present.
-        self.buffer.reserve(string.len());
-
-        self.column += string.chars().count() as i32;
-
-        // Note: This may cause the buffer to become slightly larger than
-        // `OUTPUT_BUFFER_SIZE`, but not by much.
         self.buffer.push_str(string);
-
         Ok(())
     }
 
     /// Equivalent of the libyaml `WRITE` macro.
     fn write_char(&mut self, ch: char) -> Result<()> {
+        self.check_line_limit(1)?;
         self.flush_if_needed()?;
+        self.write_base_indent();
         self.buffer.push(ch);
         self.column += 1;
         Ok(())
@@ -400,7 +1166,10 @@ impl<'w> Emitter<'w> {
             _ => return self.events.pop_front(),
         };
 
-        if self.events.len() > accummulate {
+        // Aligning the values of a mapping needs all of its keys.
+        let whole_mapping =
+            self.align_values && matches!(first.data, EventData::MappingStart { .. });
+        if !whole_mapping && self.events.len() > accummulate {
             return self.events.pop_front();
         }
 
@@ -444,6 +1213,56 @@ impl<'w> Emitter<'w> {
         Ok(())
     }
 
+    /// Define the default tag directives for the current document, unless
+    /// they are disabled or their handles are already defined.
+    fn append_default_tag_directives(&mut self) -> Result<()> {
+        if self.default_tag_directives {
+            for (handle, prefix) in DEFAULT_TAG_DIRECTIVES {
+                let tag_directive = TagDirective {
+                    handle: String::from(handle),
+                    prefix: String::from(prefix),
+                };
+                self.append_tag_directive(tag_directive, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if a character is escaped in a double-quoted scalar.
+    fn check_double_quoted_escape(&self, ch: char) -> bool {
+        !is_printable(ch)
+            || self.must_escape(ch)
+            || is_bom(ch)
+            || is_break(ch)
+            || ch == '"'
+            || ch == '\\'
+    }
+
+    /// Check if a printable character must be escaped.
+    fn must_escape(&self, ch: char) -> bool {
+        match &self.escape_policy {
+            Some(policy) => policy(ch),
+            None => !self.unicode && !is_ascii(ch),
+        }
+    }
+
+    /// The preferred line width of the innermost mapping key or value being
+    /// written.
+    fn context_width(&self) -> i32 {
+        let width = match self.width_contexts.last() {
+            Some(WidthContext::Key) => self.key_width,
+            Some(WidthContext::Value) => self.value_width,
+            Some(WidthContext::Any) | None => None,
+        };
+        width.unwrap_or(self.best_width)
+    }
+
+    fn set_width_context(&mut self, context: WidthContext) {
+        if let Some(last) = self.width_contexts.last_mut() {
+            *last = context;
+        }
+    }
+
     fn increase_indent(&mut self, flow: bool, indentless: bool) {
         self.indents.push(self.indent);
         if self.indent < 0 {
@@ -491,31 +1310,8 @@ impl<'w> Emitter<'w> {
     }
 
     fn emit_stream_start(&mut self, event: &Event) -> Result<()> {
-        self.open_ended = 0;
         if let EventData::StreamStart { ref encoding } = event.data {
-            if self.encoding == Encoding::Any {
-                self.encoding = *encoding;
-            }
-            if self.encoding == Encoding::Any {
-                self.encoding = Encoding::Utf8;
-            }
-            if self.best_indent < 2 || self.best_indent > 9 {
-                self.best_indent = 2;
-            }
-            if self.best_width >= 0 && self.best_width <= self.best_indent * 2 {
-                self.best_width = 80;
-            }
-            if self.best_width < 0 {
-                self.best_width = i32::MAX;
-            }
-            if self.line_break == Break::Any {
-                self.line_break = Break::Ln;
-            }
-            self.indent = -1;
-            self.line = 0;
-            self.column = 0;
-            self.whitespace = true;
-            self.indention = true;
+            self.start_stream(*encoding);
             if self.encoding != Encoding::Utf8 {
                 self.write_bom()?;
             }
@@ -525,6 +1321,28 @@ impl<'w> Emitter<'w> {
         Err(Error::emitter("expected STREAM-START"))
     }
 
+    /// Resolve the encoding, indentation, widths and line break, and reset
+    /// the position, at the start of a stream or a fragment.
+    fn start_stream(&mut self, encoding: Encoding) {
+        self.open_ended = 0;
+        if self.encoding == Encoding::Any {
+            self.encoding = encoding;
+        }
+        if self.encoding == Encoding::Any {
+            self.encoding = Encoding::Utf8;
+        }
+        self.best_indent = self.resolved_indent();
+        self.resolve_widths();
+        if self.line_break == Break::Any {
+            self.line_break = Break::Ln;
+        }
+        self.indent = -1;
+        self.line = 0;
+        self.column = 0;
+        self.whitespace = true;
+        self.indention = true;
+    }
+
     fn emit_document_start(&mut self, event: &Event, first: bool) -> Result<()> {
         if let EventData::DocumentStart {
             version_directive,
@@ -532,17 +1350,6 @@ impl<'w> Emitter<'w> {
             implicit,
         } = &event.data
         {
-            let default_tag_directives: [TagDirective; 2] = [
-                // TODO: Avoid these heap allocations.
-                TagDirective {
-                    handle: String::from("!"),
-                    prefix: String::from("!"),
-                },
-                TagDirective {
-                    handle: String::from("!!"),
-                    prefix: String::from("tag:yaml.org,2002:"),
-                },
-            ];
             let mut implicit = *implicit;
             if let Some(version_directive) = version_directive {
                 Self::analyze_version_directive(*version_directive)?;
@@ -551,17 +1358,39 @@ impl<'w> Emitter<'w> {
                 Self::analyze_tag_directive(tag_directive)?;
                 self.append_tag_directive(tag_directive.clone(), false)?;
             }
-            for tag_directive in default_tag_directives {
-                self.append_tag_directive(tag_directive, true)?;
+            let registered_tag_directives: Vec<TagDirective> = self
+                .registered_tag_directives
+                .iter()
+                .filter(|registered| {
+                    !tag_directives
+                        .iter()
+                        .any(|tag_directive| tag_directive.handle == registered.handle)
+                })
+                .cloned()
+                .collect();
+            for tag_directive in &registered_tag_directives {
+                self.append_tag_directive(tag_directive.clone(), true)?;
             }
+            self.append_default_tag_directives()?;
             if !first || self.canonical {
                 implicit = false;
             }
-            if (version_directive.is_some() || !tag_directives.is_empty()) && self.open_ended != 0 {
+            if self.json_compatible {
+                self.open_ended = 0;
+                self.state = EmitterState::DocumentContent;
+                return Ok(());
+            }
+            let has_tag_directives =
+                !tag_directives.is_empty() || !registered_tag_directives.is_empty();
+            if (version_directive.is_some() || has_tag_directives)
+                && self.open_ended != 0
+                && self.open_ended_policy != OpenEndedPolicy::Never
+            {
                 self.write_indicator("...", true, false, false)?;
                 self.write_indent()?;
             }
             self.open_ended = 0;
+            self.write_leading_comments(&event.comments.leading)?;
             if let Some(version_directive) = version_directive {
                 implicit = false;
                 self.write_indicator("%YAML", true, false, false)?;
@@ -572,16 +1401,17 @@ impl<'w> Emitter<'w> {
                 }
                 self.write_indent()?;
             }
-            if !tag_directives.is_empty() {
+            if has_tag_directives {
                 implicit = false;
-                for tag_directive in tag_directives {
+                for tag_directive in tag_directives.iter().chain(&registered_tag_directives) {
                     self.write_indicator("%TAG", true, false, false)?;
                     self.write_tag_handle(&tag_directive.handle)?;
                     self.write_tag_content(&tag_directive.prefix, true)?;
                     self.write_indent()?;
                 }
             }
-            if Self::check_empty_document() {
+            let empty = self.check_empty_document();
+            if empty {
                 implicit = false;
             }
             if !implicit {
@@ -591,17 +1421,32 @@ impl<'w> Emitter<'w> {
                     self.write_indent()?;
                 }
             }
-            self.state = EmitterState::DocumentContent;
+            self.state = if empty {
+                EmitterState::DocumentEnd
+            } else {
+                EmitterState::DocumentContent
+            };
             self.open_ended = 0;
             return Ok(());
         } else if let EventData::StreamEnd = &event.data {
-            if self.open_ended == 2 {
+            if self.open_ended == 2
+                && self.open_ended_policy != OpenEndedPolicy::Never
+                && !self.json_compatible
+            {
                 self.write_indicator("...", true, false, false)?;
                 self.open_ended = 0;
                 self.write_indent()?;
             }
-            self.flush()?;
+            if self.trailing_newline {
+                if self.column != 0 {
+                    self.put_break()?;
+                }
+            } else if !self.block_scalar_break {
+                let len = self.buffer.len() - Self::trailing_break_len(&self.buffer);
+                self.buffer.truncate(len);
+            }
             self.state = EmitterState::End;
+            self.flush()?;
             return Ok(());
         }
 
@@ -609,6 +1454,10 @@ impl<'w> Emitter<'w> {
     }
 
     fn emit_document_content(&mut self, event: &Event, analysis: &mut Analysis) -> Result<()> {
+        if !event.comments.leading.is_empty() {
+            self.write_indent()?;
+            self.write_leading_comments(&event.comments.leading)?;
+        }
         self.states.push(EmitterState::DocumentEnd);
         self.emit_node(event, true, false, false, false, analysis)
     }
@@ -617,7 +1466,9 @@ impl<'w> Emitter<'w> {
         if let EventData::DocumentEnd { implicit } = &event.data {
             let implicit = *implicit;
             self.write_indent()?;
-            if !implicit {
+            if self.json_compatible {
+                self.open_ended = 0;
+            } else if !implicit || self.open_ended_policy == OpenEndedPolicy::Always {
                 self.write_indicator("...", true, false, false)?;
                 self.open_ended = 0;
                 self.write_indent()?;
@@ -658,7 +1509,7 @@ impl<'w> Emitter<'w> {
         if !first {
             self.write_indicator(",", false, false, false)?;
         }
-        if self.canonical || self.column > self.best_width {
+        if self.canonical || self.flow_wrap && self.column > self.context_width() {
             self.write_indent()?;
         }
         self.states.push(EmitterState::FlowSequenceItem);
@@ -672,7 +1523,9 @@ impl<'w> Emitter<'w> {
         analysis: &mut Analysis,
     ) -> Result<()> {
         if first {
-            self.write_indicator("{", true, true, false)?;
+            if !self.compact_mapping {
+                self.write_indicator("{", true, true, false)?;
+            }
             self.increase_indent(true, false);
             self.flow_level += 1;
         }
@@ -684,17 +1537,41 @@ impl<'w> Emitter<'w> {
                 self.write_indicator(",", false, false, false)?;
                 self.write_indent()?;
             }
-            self.write_indicator("}", false, false, false)?;
+            if self.compact_mapping {
+                self.compact_mapping = false;
+            } else {
+                self.write_indicator("}", false, false, false)?;
+            }
+            self.width_contexts.pop();
             self.state = self.states.pop().unwrap();
             return Ok(());
         }
+        self.set_width_context(WidthContext::Key);
         if !first {
             self.write_indicator(",", false, false, false)?;
         }
-        if self.canonical || self.column > self.best_width {
+        if self.canonical || self.flow_wrap && self.column > self.context_width() {
             self.write_indent()?;
         }
-        if !self.canonical && self.check_simple_key(event, analysis) {
+        if self.json_compatible {
+            let EventData::Scalar { value, .. } = &event.data else {
+                return Err(Error::emitter(
+                    "mapping keys must be scalars in JSON-compatible mode",
+                ));
+            };
+            // A longer key is not read back as an implicit key, and JSON has
+            // no explicit keys.
+            if self.json_string_len(value) > 1024 {
+                return Err(Error::emitter(
+                    "mapping key is too long for JSON-compatible mode",
+                ));
+            }
+            self.states.push(EmitterState::FlowMappingSimpleValue);
+            self.emit_node(event, false, false, true, true, analysis)
+        } else if !self.canonical
+            && !self.force_explicit_keys
+            && Self::check_simple_key(event, self.events.front(), analysis)
+        {
             self.states.push(EmitterState::FlowMappingSimpleValue);
             self.emit_node(event, false, false, true, true, analysis)
         } else {
@@ -710,10 +1587,11 @@ impl<'w> Emitter<'w> {
         simple: bool,
         analysis: &mut Analysis,
     ) -> Result<()> {
+        self.set_width_context(WidthContext::Value);
         if simple {
             self.write_indicator(":", false, false, false)?;
         } else {
-            if self.canonical || self.column > self.best_width {
+            if self.canonical || self.flow_wrap && self.column > self.context_width() {
                 self.write_indent()?;
             }
             self.write_indicator(":", true, false, false)?;
@@ -729,7 +1607,9 @@ impl<'w> Emitter<'w> {
         analysis: &mut Analysis,
     ) -> Result<()> {
         if first {
-            self.increase_indent(false, self.mapping_context && !self.indention);
+            let indentless =
+                self.mapping_context && !self.indention && !self.sequence_under_key_indent;
+            self.increase_indent(false, indentless);
         }
         if let EventData::SequenceEnd = &event.data {
             self.indent = self.indents.pop().unwrap();
@@ -737,6 +1617,7 @@ impl<'w> Emitter<'w> {
             return Ok(());
         }
         self.write_indent()?;
+        self.write_leading_comments(&event.comments.leading)?;
         self.write_indicator("-", true, false, true)?;
         self.states.push(EmitterState::BlockSequenceItem);
         self.emit_node(event, false, true, false, false, analysis)
@@ -753,11 +1634,23 @@ impl<'w> Emitter<'w> {
         }
         if let EventData::MappingEnd = &event.data {
             self.indent = self.indents.pop().unwrap();
+            self.width_contexts.pop();
+            self.key_alignments.pop();
             self.state = self.states.pop().unwrap();
             return Ok(());
         }
+        self.set_width_context(WidthContext::Key);
         self.write_indent()?;
-        if self.check_simple_key(event, analysis) {
+        if !first
+            && self.blank_line_between_top_level_keys
+            && self.states.last() == Some(&EmitterState::DocumentEnd)
+            && !(self.block_scalar_break && self.open_ended == 2)
+        {
+            self.put_break()?;
+        }
+        self.write_leading_comments(&event.comments.leading)?;
+        if !self.force_explicit_keys && Self::check_simple_key(event, self.events.front(), analysis)
+        {
             self.states.push(EmitterState::BlockMappingSimpleValue);
             self.emit_node(event, false, false, true, true, analysis)
         } else {
@@ -773,8 +1666,21 @@ impl<'w> Emitter<'w> {
         simple: bool,
         analysis: &mut Analysis,
     ) -> Result<()> {
+        self.set_width_context(WidthContext::Value);
         if simple {
             self.write_indicator(":", false, false, false)?;
+            if self.check_long_scalar(event) {
+                let indent = self.indent;
+                self.indent += self.best_indent;
+                self.write_indent()?;
+                self.indent = indent;
+            } else if !self.check_block_collection(event, analysis) {
+                let alignment = self.key_alignments.last().copied().unwrap_or(0);
+                let column = self.indent + alignment + 1;
+                while self.column < column {
+                    self.put(' ')?;
+                }
+            }
         } else {
             self.write_indent()?;
             self.write_indicator(":", true, false, true)?;
@@ -821,17 +1727,34 @@ impl<'w> Emitter<'w> {
         let Analysis {
             anchor,
             tag,
+            implied_tag,
             scalar: Some(scalar),
         } = analysis
         else {
             unreachable!("no scalar analysis");
         };
 
-        self.select_scalar_style(event, scalar, tag)?;
+        if self.json_compatible {
+            self.increase_indent(true, false);
+            self.write_json_scalar(event)?;
+            self.indent = self.indents.pop().unwrap();
+            self.state = self.states.pop().unwrap();
+            return Ok(());
+        }
+
+        self.select_scalar_style(event, scalar, tag, implied_tag.take())?;
         self.process_anchor(anchor)?;
         self.process_tag(tag)?;
         self.increase_indent(true, false);
         self.process_scalar(scalar)?;
+        if let Some(comment) = &event.comments.trailing {
+            if self.flow_level == 0
+                && !self.simple_key_context
+                && !matches!(scalar.style, ScalarStyle::Literal | ScalarStyle::Folded)
+            {
+                self.write_comment(comment)?;
+            }
+        }
         self.indent = self.indents.pop().unwrap();
         self.state = self.states.pop().unwrap();
         Ok(())
@@ -848,6 +1771,7 @@ impl<'w> Emitter<'w> {
 
         if self.flow_level != 0
             || self.canonical
+            || self.json_compatible
             || *style == SequenceStyle::Flow
             || self.check_empty_sequence(event)
         {
@@ -867,20 +1791,169 @@ impl<'w> Emitter<'w> {
             unreachable!()
         };
 
+        self.width_contexts.push(WidthContext::Key);
         if self.flow_level != 0
             || self.canonical
+            || self.json_compatible
             || *style == MappingStyle::Flow
             || self.check_empty_mapping(event)
         {
+            self.compact_mapping = self.check_compact_mapping(analysis);
             self.state = EmitterState::FlowMappingFirstKey;
         } else {
+            self.key_alignments
+                .push(core::mem::take(&mut self.pending_key_alignment));
             self.state = EmitterState::BlockMappingFirstKey;
         }
         Ok(())
     }
 
-    fn check_empty_document() -> bool {
-        false
+    /// Check if the DOCUMENT-START event being emitted is immediately
+    /// followed by the DOCUMENT-END event, so the document has no content.
+    fn check_empty_document(&self) -> bool {
+        matches!(
+            self.events.front().map(|event| &event.data),
+            Some(EventData::DocumentEnd { .. })
+        )
+    }
+
+    /// Check if the mapping being started can be written as a compact pair in
+    /// a flow sequence: it has no properties, and its only key and value are
+    /// scalars or aliases.
+    fn check_compact_mapping(&self, analysis: &Analysis) -> bool {
+        let is_leaf = |event: Option<&Event>| {
+            matches!(
+                event.map(|event| &event.data),
+                Some(EventData::Scalar { .. } | EventData::Alias { .. })
+            )
+        };
+        self.compact_flow_mapping
+            && !self.canonical
+            && self.flow_level != 0
+            && self.sequence_context
+            && analysis.anchor.is_none()
+            && analysis.tag.is_none()
+            && is_leaf(self.events.front())
+            && is_leaf(self.events.get(1))
+            && matches!(
+                self.events.get(2).map(|event| &event.data),
+                Some(EventData::MappingEnd)
+            )
+    }
+
+    /// The width of the widest simple key of the mapping being started, whose
+    /// content is at the front of the event queue.
+    fn aligned_key_width(&mut self, tag_directives: &[TagDirective]) -> i32 {
+        if self.force_explicit_keys {
+            return 0;
+        }
+        let simple_key_context = core::mem::replace(&mut self.simple_key_context, true);
+        let mut width = 0;
+        let mut level = 0;
+        let mut key = true;
+        let mut events = self.events.iter().peekable();
+        while let Some(event) = events.next() {
+            if level == 0 && key {
+                if let Some(key_width) =
+                    self.simple_key_width(event, events.peek().copied(), tag_directives)
+                {
+                    width = width.max(key_width);
+                }
+            }
+            match event.data {
+                EventData::SequenceStart { .. } | EventData::MappingStart { .. } => level += 1,
+                EventData::SequenceEnd | EventData::MappingEnd if level == 0 => break,
+                EventData::SequenceEnd | EventData::MappingEnd => level -= 1,
+                _ => {}
+            }
+            if level == 0 {
+                key = !key;
+            }
+        }
+        self.simple_key_context = simple_key_context;
+        width
+    }
+
+    /// The number of columns `event` takes when written as a simple key, or
+    /// `None` if it cannot be written as a simple key.
+    fn simple_key_width(
+        &self,
+        event: &Event,
+        next: Option<&Event>,
+        tag_directives: &[TagDirective],
+    ) -> Option<i32> {
+        let mut analysis = self.analyze_event(event, tag_directives).ok()?;
+        if !Self::check_simple_key(event, next, &analysis) {
+            return None;
+        }
+        if let Some(scalar) = &mut analysis.scalar {
+            self.select_scalar_style(event, scalar, &mut analysis.tag, analysis.implied_tag)
+                .ok()?;
+        }
+
+        let mut parts = Vec::with_capacity(3);
+        if let Some(anchor) = &analysis.anchor {
+            parts.push(1 + anchor.anchor.len());
+        }
+        if let Some(tag) = &analysis.tag {
+            let suffix = tag.suffix.chars().count();
+            if tag.handle.is_empty() {
+                parts.push(suffix + 3);
+            } else {
+                parts.push(tag.handle.len() + suffix);
+            }
+        }
+        match (&event.data, &analysis.scalar) {
+            // An alias used as a simple key is followed by a space.
+            (EventData::Alias { .. }, _) => parts.push(1),
+            (_, Some(scalar)) => parts.push(self.scalar_width(scalar)),
+            _ => parts.push(2),
+        }
+        let width = parts.iter().sum::<usize>() + parts.len() - 1;
+        i32::try_from(width).ok()
+    }
+
+    /// The number of columns a single-line scalar takes in its chosen style.
+    fn scalar_width(&self, scalar: &ScalarAnalysis) -> usize {
+        let value = scalar.value;
+        match scalar.style {
+            ScalarStyle::SingleQuoted => 2 + value.chars().count() + value.matches('\'').count(),
+            ScalarStyle::DoubleQuoted => {
+                let escaped = value.chars().map(|ch| match ch {
+                    _ if !self.check_double_quoted_escape(ch) => 1,
+                    '\0' | '\x07' | '\x08' | '\t' | '\n' | '\x0B' | '\x0C' | '\r' | '\x1B'
+                    | '"' | '\\' | '\u{85}' | '\u{a0}' | '\u{2028}' | '\u{2029}' => 2,
+                    '\0'..='\u{ff}' => 4,
+                    '\u{100}'..='\u{ffff}' => 6,
+                    _ => 10,
+                });
+                2 + escaped.sum::<usize>()
+            }
+            _ => value.chars().count(),
+        }
+    }
+
+    /// Check if `event` starts a block collection without properties, which
+    /// is written on the lines following its key.
+    fn check_block_collection(&self, event: &Event, analysis: &Analysis) -> bool {
+        let block = match &event.data {
+            EventData::SequenceStart { style, .. } => {
+                *style != SequenceStyle::Flow && !self.check_empty_sequence(event)
+            }
+            EventData::MappingStart { style, .. } => {
+                *style != MappingStyle::Flow && !self.check_empty_mapping(event)
+            }
+            _ => false,
+        };
+        block && analysis.anchor.is_none() && analysis.tag.is_none()
+    }
+
+    fn check_long_scalar(&self, event: &Event) -> bool {
+        let EventData::Scalar { value, .. } = &event.data else {
+            return false;
+        };
+        self.value_on_new_line_threshold
+            .is_some_and(|threshold| value.chars().count() > threshold)
     }
 
     fn check_empty_sequence(&self, event: &Event) -> bool {
@@ -901,11 +1974,14 @@ impl<'w> Emitter<'w> {
         start && end
     }
 
-    fn check_simple_key(&self, event: &Event, analysis: &Analysis) -> bool {
+    /// Check if `event` can be written as a simple key. `next` is the event
+    /// following it, which tells if a collection is empty.
+    fn check_simple_key(event: &Event, next: Option<&Event>, analysis: &Analysis) -> bool {
         let Analysis {
             tag,
             anchor,
             scalar,
+            ..
         } = analysis;
 
         let mut length = anchor.as_ref().map_or(0, |a| a.anchor.len())
@@ -926,12 +2002,12 @@ impl<'w> Emitter<'w> {
                 length += scalar.value.len();
             }
             EventData::SequenceStart { .. } => {
-                if !self.check_empty_sequence(event) {
+                if !matches!(next.map(|event| &event.data), Some(EventData::SequenceEnd)) {
                     return false;
                 }
             }
             EventData::MappingStart { .. } => {
-                if !self.check_empty_mapping(event) {
+                if !matches!(next.map(|event| &event.data), Some(EventData::MappingEnd)) {
                     return false;
                 }
             }
@@ -945,16 +2021,19 @@ impl<'w> Emitter<'w> {
         true
     }
 
-    fn select_scalar_style(
-        &mut self,
+    fn select_scalar_style<'a>(
+        &self,
         event: &Event,
         scalar_analysis: &mut ScalarAnalysis,
-        tag_analysis: &mut Option<TagAnalysis>,
+        tag_analysis: &mut Option<TagAnalysis<'a>>,
+        implied_tag: Option<TagAnalysis<'a>>,
     ) -> Result<()> {
         let EventData::Scalar {
+            tag,
             plain_implicit,
             quoted_implicit,
             style,
+            force_style,
             ..
         } = &event.data
         else {
@@ -968,45 +2047,137 @@ impl<'w> Emitter<'w> {
                 "neither tag nor implicit flags are specified",
             ));
         }
-        if style == ScalarStyle::Any {
-            style = ScalarStyle::Plain;
+        if let Some(forced) = force_style.filter(|style| *style != ScalarStyle::Any) {
+            let allowed = match forced {
+                ScalarStyle::Plain => {
+                    let plain_allowed = if self.flow_level != 0 {
+                        scalar_analysis.flow_plain_allowed
+                    } else {
+                        scalar_analysis.block_plain_allowed
+                    };
+                    plain_allowed
+                        && !(scalar_analysis.value.is_empty()
+                            && (self.flow_level != 0 || self.simple_key_context))
+                        && !(self.simple_key_context && scalar_analysis.multiline)
+                }
+                ScalarStyle::SingleQuoted => {
+                    scalar_analysis.single_quoted_allowed
+                        && !(self.simple_key_context && scalar_analysis.multiline)
+                }
+                ScalarStyle::Literal | ScalarStyle::Folded => {
+                    scalar_analysis.block_allowed
+                        && self.flow_level == 0
+                        && !self.simple_key_context
+                }
+                ScalarStyle::Any | ScalarStyle::DoubleQuoted => true,
+            };
+            if !allowed {
+                return Err(Error::emitter(
+                    "scalar value cannot be written in the forced style",
+                ));
+            }
+            style = forced;
+        } else {
+            self.choose_scalar_style(
+                &mut style,
+                *plain_implicit,
+                tag.as_deref(),
+                no_tag,
+                scalar_analysis,
+            );
+            if style == ScalarStyle::Plain
+                && self.check_auto_fold(scalar_analysis, no_tag && !*quoted_implicit)
+            {
+                // Like the plain style, the folded one needs no tag here.
+                scalar_analysis.style = ScalarStyle::Folded;
+                return Ok(());
+            }
+        }
+        if no_tag && !*quoted_implicit && style != ScalarStyle::Plain && !self.is_quoted_key() {
+            *tag_analysis = Some(implied_tag.unwrap_or(TagAnalysis {
+                handle: "!",
+                suffix: "",
+            }));
+        }
+        scalar_analysis.style = style;
+        Ok(())
+    }
+
+    /// Check if a scalar that would be written plain is long enough to be
+    /// written as a folded scalar instead. `implied_tag` is set if the tag of
+    /// the scalar is implied by the plain style.
+    fn check_auto_fold(&self, scalar_analysis: &ScalarAnalysis, implied_tag: bool) -> bool {
+        self.auto_fold_threshold
+            .is_some_and(|threshold| scalar_analysis.value.chars().count() > threshold)
+            && self.flow_level == 0
+            && !self.simple_key_context
+            && !scalar_analysis.multiline
+            && scalar_analysis.block_allowed
+            && !(implied_tag && is_ambiguous_plain(scalar_analysis.value))
+    }
+
+    /// Choose the style of a scalar, falling back to a quoted style when the
+    /// requested one cannot represent the value.
+    fn choose_scalar_style(
+        &self,
+        style: &mut ScalarStyle,
+        plain_implicit: bool,
+        tag: Option<&str>,
+        no_tag: bool,
+        scalar_analysis: &ScalarAnalysis,
+    ) {
+        if *style == ScalarStyle::Any {
+            *style = ScalarStyle::Plain;
         }
         if self.canonical {
-            style = ScalarStyle::DoubleQuoted;
+            *style = ScalarStyle::DoubleQuoted;
         }
         if self.simple_key_context && scalar_analysis.multiline {
-            style = ScalarStyle::DoubleQuoted;
+            *style = ScalarStyle::DoubleQuoted;
         }
-        if style == ScalarStyle::Plain {
+        if *style == ScalarStyle::Plain {
+            let quoted_style = match self.quote_preference {
+                QuotePreference::Single => ScalarStyle::SingleQuoted,
+                QuotePreference::Double => ScalarStyle::DoubleQuoted,
+            };
             if self.flow_level != 0 && !scalar_analysis.flow_plain_allowed
                 || self.flow_level == 0 && !scalar_analysis.block_plain_allowed
             {
-                style = ScalarStyle::SingleQuoted;
+                *style = quoted_style;
             }
             if scalar_analysis.value.is_empty() && (self.flow_level != 0 || self.simple_key_context)
             {
-                style = ScalarStyle::SingleQuoted;
+                *style = quoted_style;
             }
-            if no_tag && !*plain_implicit {
-                style = ScalarStyle::SingleQuoted;
+            if no_tag && !plain_implicit {
+                *style = quoted_style;
+            }
+            if self.quote_ambiguous_strings
+                && (tag == Some(STR_TAG) || !plain_implicit)
+                && is_ambiguous_plain(scalar_analysis.value)
+            {
+                *style = quoted_style;
             }
         }
-        if style == ScalarStyle::SingleQuoted && !scalar_analysis.single_quoted_allowed {
-            style = ScalarStyle::DoubleQuoted;
+        if *style == ScalarStyle::SingleQuoted && !scalar_analysis.single_quoted_allowed {
+            *style = ScalarStyle::DoubleQuoted;
         }
-        if (style == ScalarStyle::Literal || style == ScalarStyle::Folded)
+        if (*style == ScalarStyle::Literal || *style == ScalarStyle::Folded)
             && (!scalar_analysis.block_allowed || self.flow_level != 0 || self.simple_key_context)
         {
-            style = ScalarStyle::DoubleQuoted;
+            *style = ScalarStyle::DoubleQuoted;
         }
-        if no_tag && !*quoted_implicit && style != ScalarStyle::Plain {
-            *tag_analysis = Some(TagAnalysis {
-                handle: "!",
-                suffix: "",
-            });
+        if self.is_quoted_key() {
+            *style = ScalarStyle::DoubleQuoted;
         }
-        scalar_analysis.style = style;
-        Ok(())
+    }
+
+    /// Check if the scalar being written is a mapping key that is quoted
+    /// because of [`Emitter::set_quote_keys()`].
+    fn is_quoted_key(&self) -> bool {
+        self.quote_keys
+            && self.mapping_context
+            && self.width_contexts.last() == Some(&WidthContext::Key)
     }
 
     fn process_anchor(&mut self, analysis: &Option<AnchorAnalysis>) -> Result<()> {
@@ -1021,13 +2192,24 @@ impl<'w> Emitter<'w> {
         let Some(analysis) = analysis.as_ref() else {
             return Ok(());
         };
+        if self.json_compatible {
+            return Ok(());
+        }
 
         if analysis.handle.is_empty() && analysis.suffix.is_empty() {
             return Ok(());
         }
         if analysis.handle.is_empty() {
             self.write_indicator("!<", true, false, false)?;
-            self.write_tag_content(analysis.suffix, false)?;
+            // The leading `!` of a local tag is kept as is, like in `!<!bar>`.
+            let suffix = match analysis.suffix.strip_prefix('!') {
+                Some(rest) => {
+                    self.write_char('!')?;
+                    rest
+                }
+                None => analysis.suffix,
+            };
+            self.write_tag_content(suffix, false)?;
             self.write_indicator(">", false, false, false)?;
         } else {
             self.write_tag_handle(analysis.handle)?;
@@ -1090,7 +2272,7 @@ impl<'w> Emitter<'w> {
         Ok(())
     }
 
-    fn analyze_anchor(anchor: &str, alias: bool) -> Result<AnchorAnalysis<'_>> {
+    pub(crate) fn analyze_anchor(anchor: &str, alias: bool) -> Result<AnchorAnalysis<'_>> {
         if anchor.is_empty() {
             return Err(Error::emitter(if alias {
                 "alias value must not be empty"
@@ -1099,14 +2281,15 @@ impl<'w> Emitter<'w> {
             }));
         }
 
-        for ch in anchor.chars() {
-            if !is_alpha(ch) {
-                return Err(Error::emitter(if alias {
+        if !anchor.chars().all(is_alpha) {
+            return Err(Error::emitter_value(
+                if alias {
                     "alias value must contain alphanumerical characters only"
                 } else {
                     "anchor value must contain alphanumerical characters only"
-                }));
-            }
+                },
+                anchor,
+            ));
         }
 
         Ok(AnchorAnalysis { anchor, alias })
@@ -1135,175 +2318,45 @@ impl<'w> Emitter<'w> {
         Ok(TagAnalysis { handle, suffix })
     }
 
-    fn analyze_scalar<'a>(&mut self, value: &'a str) -> Result<ScalarAnalysis<'a>> {
-        let mut block_indicators = false;
-        let mut flow_indicators = false;
-        let mut line_breaks = false;
-        let mut special_characters = false;
-        let mut leading_space = false;
-        let mut leading_break = false;
-        let mut trailing_space = false;
-        let mut trailing_break = false;
-        let mut break_space = false;
-        let mut space_break = false;
-        let mut preceded_by_whitespace;
-        let mut previous_space = false;
-        let mut previous_break = false;
-
-        if value.is_empty() {
-            return Ok(ScalarAnalysis {
-                value: "",
-                multiline: false,
-                flow_plain_allowed: false,
-                block_plain_allowed: true,
-                single_quoted_allowed: true,
-                block_allowed: false,
-                style: ScalarStyle::Any,
-            });
-        }
-
-        if value.starts_with("---") || value.starts_with("...") {
-            block_indicators = true;
-            flow_indicators = true;
-        }
-        preceded_by_whitespace = true;
-
-        let mut chars = value.chars();
-        let mut first = true;
-
-        while let Some(ch) = chars.next() {
-            let next = chars.clone().next();
-            let followed_by_whitespace = is_blankz(next);
-            if first {
-                match ch {
-                    '#' | ',' | '[' | ']' | '{' | '}' | '&' | '*' | '!' | '|' | '>' | '\''
-                    | '"' | '%' | '@' | '`' => {
-                        flow_indicators = true;
-                        block_indicators = true;
-                    }
-                    '?' | ':' => {
-                        flow_indicators = true;
-                        if followed_by_whitespace {
-                            block_indicators = true;
-                        }
-                    }
-                    '-' if followed_by_whitespace => {
-                        flow_indicators = true;
-                        block_indicators = true;
-                    }
-                    _ => {}
-                }
-            } else {
-                match ch {
-                    ',' | '?' | '[' | ']' | '{' | '}' => {
-                        flow_indicators = true;
-                    }
-                    ':' => {
-                        flow_indicators = true;
-                        if followed_by_whitespace {
-                            block_indicators = true;
-                        }
-                    }
-                    '#' if preceded_by_whitespace => {
-                        flow_indicators = true;
-                        block_indicators = true;
-                    }
-                    _ => {}
-                }
-            }
+    fn analyze_scalar<'a>(&self, value: &'a str) -> Result<ScalarAnalysis<'a>> {
+        Ok(analyze_scalar(value, |ch| self.must_escape(ch)))
+    }
 
-            if !is_printable(ch) || !is_ascii(ch) && !self.unicode {
-                special_characters = true;
-            }
-            if is_break(ch) {
-                line_breaks = true;
-            }
+    fn analyze_event<'a>(
+        &self,
+        event: &'a Event,
+        tag_directives: &'a [TagDirective],
+    ) -> Result<Analysis<'a>> {
+        let mut analysis = Analysis::default();
+        let shorthands = if self.prefer_verbatim_tags {
+            &[]
+        } else {
+            tag_directives
+        };
 
-            if is_space(ch) {
-                if first {
-                    leading_space = true;
-                }
-                if next.is_none() {
-                    trailing_space = true;
-                }
-                if previous_break {
-                    break_space = true;
+        if self.json_compatible {
+            match &event.data {
+                EventData::Alias { .. } => {
+                    return Err(Error::emitter(
+                        "aliases are not allowed in JSON-compatible mode",
+                    ));
                 }
-                previous_space = true;
-                previous_break = false;
-            } else if is_break(ch) {
-                if first {
-                    leading_break = true;
+                EventData::Scalar {
+                    anchor: Some(_), ..
                 }
-                if next.is_none() {
-                    trailing_break = true;
+                | EventData::SequenceStart {
+                    anchor: Some(_), ..
                 }
-                if previous_space {
-                    space_break = true;
+                | EventData::MappingStart {
+                    anchor: Some(_), ..
+                } => {
+                    return Err(Error::emitter(
+                        "anchors are not allowed in JSON-compatible mode",
+                    ));
                 }
-                previous_space = false;
-                previous_break = true;
-            } else {
-                previous_space = false;
-                previous_break = false;
+                _ => {}
             }
-
-            preceded_by_whitespace = is_blankz(ch);
-            first = false;
-        }
-
-        let mut analysis = ScalarAnalysis {
-            value,
-            multiline: line_breaks,
-            flow_plain_allowed: true,
-            block_plain_allowed: true,
-            single_quoted_allowed: true,
-            block_allowed: true,
-            style: ScalarStyle::Any,
-        };
-
-        analysis.multiline = line_breaks;
-        analysis.flow_plain_allowed = true;
-        analysis.block_plain_allowed = true;
-        analysis.single_quoted_allowed = true;
-        analysis.block_allowed = true;
-        if leading_space || leading_break || trailing_space || trailing_break {
-            analysis.flow_plain_allowed = false;
-            analysis.block_plain_allowed = false;
-        }
-        if trailing_space {
-            analysis.block_allowed = false;
-        }
-        if break_space {
-            analysis.flow_plain_allowed = false;
-            analysis.block_plain_allowed = false;
-            analysis.single_quoted_allowed = false;
-        }
-        if space_break || special_characters {
-            analysis.flow_plain_allowed = false;
-            analysis.block_plain_allowed = false;
-            analysis.single_quoted_allowed = false;
-            analysis.block_allowed = false;
         }
-        if line_breaks {
-            analysis.flow_plain_allowed = false;
-            analysis.block_plain_allowed = false;
-        }
-        if flow_indicators {
-            analysis.flow_plain_allowed = false;
-        }
-        if block_indicators {
-            analysis.block_plain_allowed = false;
-        }
-        Ok(analysis)
-    }
-
-    fn analyze_event<'a>(
-        &mut self,
-        event: &'a Event,
-        tag_directives: &'a [TagDirective],
-    ) -> Result<Analysis<'a>> {
-        let mut analysis = Analysis::default();
 
         match &event.data {
             EventData::Alias { anchor } => {
@@ -1322,8 +2375,9 @@ impl<'w> Emitter<'w> {
                     analysis.anchor = Some(Self::analyze_anchor(anchor, false)?);
                 }
                 if tag.is_some() && (self.canonical || !plain_implicit && !quoted_implicit) {
-                    analysis.tag =
-                        Some(Self::analyze_tag(tag.as_deref().unwrap(), tag_directives)?);
+                    analysis.tag = Some(Self::analyze_tag(tag.as_deref().unwrap(), shorthands)?);
+                } else if let Some(tag) = tag.as_deref().filter(|_| !quoted_implicit) {
+                    analysis.implied_tag = Self::analyze_tag(tag, shorthands).ok();
                 }
                 analysis.scalar = Some(self.analyze_scalar(value)?);
             }
@@ -1337,8 +2391,7 @@ impl<'w> Emitter<'w> {
                     analysis.anchor = Some(Self::analyze_anchor(anchor, false)?);
                 }
                 if tag.is_some() && (self.canonical || !*implicit) {
-                    analysis.tag =
-                        Some(Self::analyze_tag(tag.as_deref().unwrap(), tag_directives)?);
+                    analysis.tag = Some(Self::analyze_tag(tag.as_deref().unwrap(), shorthands)?);
                 }
             }
             EventData::MappingStart {
@@ -1351,8 +2404,7 @@ impl<'w> Emitter<'w> {
                     analysis.anchor = Some(Self::analyze_anchor(anchor, false)?);
                 }
                 if tag.is_some() && (self.canonical || !*implicit) {
-                    analysis.tag =
-                        Some(Self::analyze_tag(tag.as_deref().unwrap(), tag_directives)?);
+                    analysis.tag = Some(Self::analyze_tag(tag.as_deref().unwrap(), shorthands)?);
                 }
             }
             _ => {}
@@ -1391,11 +2443,40 @@ impl<'w> Emitter<'w> {
             self.put(' ')?;
         }
         self.write_str(indicator)?;
+        self.block_scalar_break = false;
         self.whitespace = is_whitespace;
         self.indention = self.indention && is_indention;
         Ok(())
     }
 
+    /// Write each line of the comments on its own line at the current
+    /// indentation. Comments are not written in flow context.
+    fn write_leading_comments(&mut self, comments: &[String]) -> Result<()> {
+        if self.flow_level != 0 || self.json_compatible || comments.is_empty() {
+            return Ok(());
+        }
+        for line in comments.iter().flat_map(|comment| comment.split(is_break)) {
+            self.write_indent()?;
+            self.write_comment(line)?;
+        }
+        self.write_indent()
+    }
+
+    fn write_comment(&mut self, comment: &str) -> Result<()> {
+        self.write_indicator("#", true, false, false)?;
+        let mut chars = comment
+            .chars()
+            .map(|ch| if is_break(ch) { ' ' } else { ch });
+        if let Some(first) = chars.next() {
+            self.put(' ')?;
+            self.put(first)?;
+            for ch in chars {
+                self.put(ch)?;
+            }
+        }
+        Ok(())
+    }
+
     fn write_anchor(&mut self, value: &str) -> Result<()> {
         self.write_str(value)?;
         self.whitespace = false;
@@ -1466,7 +2547,8 @@ impl<'w> Emitter<'w> {
         while let Some(ch) = chars.next() {
             let next = chars.clone().next();
             if is_space(ch) {
-                if allow_breaks && !spaces && self.column > self.best_width && !is_space(next) {
+                if allow_breaks && !spaces && self.column > self.context_width() && !is_space(next)
+                {
                     self.write_indent()?;
                 } else {
                     self.write_char(ch)?;
@@ -1507,7 +2589,7 @@ impl<'w> Emitter<'w> {
             if is_space(ch) {
                 if allow_breaks
                     && !spaces
-                    && self.column > self.best_width
+                    && self.column > self.context_width()
                     && !is_first
                     && !is_last
                     && !is_space(next)
@@ -1548,19 +2630,110 @@ impl<'w> Emitter<'w> {
         Ok(())
     }
 
+    /// Write a scalar in JSON-compatible mode, either as a JSON literal or as a
+    /// JSON string.
+    fn write_json_scalar(&mut self, event: &Event) -> Result<()> {
+        let EventData::Scalar {
+            tag,
+            value,
+            plain_implicit,
+            style,
+            ..
+        } = &event.data
+        else {
+            unreachable!()
+        };
+        // Scalars with an explicit `!!str` tag, and quoted or block scalars
+        // without a core schema tag, are strings whatever their value.
+        let resolvable = match tag.as_deref() {
+            Some(NULL_TAG | BOOL_TAG | INT_TAG | FLOAT_TAG) => true,
+            None | Some(STR_TAG) => {
+                *plain_implicit && matches!(style, ScalarStyle::Any | ScalarStyle::Plain)
+            }
+            Some(_) => false,
+        };
+        let literal = if !resolvable || self.simple_key_context {
+            None
+        } else if resolver::is_null(value) {
+            Some(String::from("null"))
+        } else if let Some(value) = resolver::parse_bool(value) {
+            Some(value.to_string())
+        } else if is_json_number(value) && resolver::parse_float(value).is_some_and(f64::is_finite)
+        {
+            Some(value.clone())
+        } else if let Some(value) = resolver::parse_int(value) {
+            Some(value.to_string())
+        } else {
+            resolver::parse_float(value)
+                .filter(|value| value.is_finite())
+                .map(|value| value.to_string())
+        };
+        match literal {
+            Some(literal) => self.write_plain_scalar(&literal, false),
+            None => self.write_json_string(value),
+        }
+    }
+
+    /// Write a double-quoted scalar using only the escapes that are valid in
+    /// both YAML and JSON, and without line breaks.
+    ///
+    /// Characters outside the basic multilingual plane are written as is,
+    /// since JSON escapes them as surrogate pairs, which YAML does not accept.
+    fn write_json_string(&mut self, value: &str) -> Result<()> {
+        self.write_indicator("\"", true, false, false)?;
+        for ch in value.chars() {
+            match self.json_escape(ch) {
+                Some(escape) => self.write_str(&escape)?,
+                None => self.write_char(ch)?,
+            }
+        }
+        self.write_indicator("\"", false, false, false)?;
+        self.whitespace = false;
+        self.indention = false;
+        Ok(())
+    }
+
+    /// The escape sequence of `ch` in a string written by
+    /// [`Emitter::write_json_string()`], if it needs one.
+    fn json_escape(&self, ch: char) -> Option<Cow<'static, str>> {
+        let escape = match ch {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\x08' => "\\b",
+            '\x0C' => "\\f",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            _ if ch <= '\u{ffff}'
+                && (!is_printable(ch) || self.must_escape(ch) || is_bom(ch) || is_break(ch)) =>
+            {
+                return Some(Cow::Owned(alloc::format!("\\u{:04X}", ch as u32)));
+            }
+            _ => return None,
+        };
+        Some(Cow::Borrowed(escape))
+    }
+
+    /// The length in bytes of `value` written by
+    /// [`Emitter::write_json_string()`], with its quotes.
+    fn json_string_len(&self, value: &str) -> usize {
+        let escaped: usize = value
+            .chars()
+            .map(|ch| {
+                self.json_escape(ch)
+                    .map_or(ch.len_utf8(), |escape| escape.len())
+            })
+            .sum();
+        escaped + 2
+    }
+
     fn write_double_quoted_scalar(&mut self, value: &str, allow_breaks: bool) -> Result<()> {
         let mut spaces = false;
         self.write_indicator("\"", true, false, false)?;
         let mut chars = value.chars();
         let mut first = true;
         while let Some(ch) = chars.next() {
-            if !is_printable(ch)
-                || !self.unicode && !is_ascii(ch)
-                || is_bom(ch)
-                || is_break(ch)
-                || ch == '"'
-                || ch == '\\'
-            {
+            if self.check_double_quoted_escape(ch) {
                 self.put('\\')?;
                 match ch {
                     // TODO: Double check these character mappings.
@@ -1636,7 +2809,7 @@ impl<'w> Emitter<'w> {
             } else if is_space(ch) {
                 if allow_breaks
                     && !spaces
-                    && self.column > self.best_width
+                    && self.column > self.context_width()
                     && !first
                     && chars.clone().next().is_some()
                 {
@@ -1718,6 +2891,7 @@ impl<'w> Emitter<'w> {
                 breaks = false;
             }
         }
+        self.block_scalar_break = is_break(value.chars().next_back());
         Ok(())
     }
 
@@ -1736,8 +2910,13 @@ impl<'w> Emitter<'w> {
             if is_break(ch) {
                 if !breaks && !leading_spaces && ch == '\n' {
                     let mut skip_breaks = chars.clone();
-                    while is_break(skip_breaks.next()) {}
-                    if !is_blankz(skip_breaks.next()) {
+                    let next = loop {
+                        let next = skip_breaks.next();
+                        if !is_break(next) {
+                            break next;
+                        }
+                    };
+                    if !is_blankz(next) {
                         self.put_break()?;
                     }
                 }
@@ -1752,7 +2931,7 @@ impl<'w> Emitter<'w> {
                 if !breaks
                     && is_space(ch)
                     && !is_space(chars.clone().next())
-                    && self.column > self.best_width
+                    && self.column > self.context_width()
                 {
                     self.write_indent()?;
                 } else {
@@ -1762,25 +2941,55 @@ impl<'w> Emitter<'w> {
                 breaks = false;
             }
         }
+        self.block_scalar_break = is_break(value.chars().next_back());
         Ok(())
     }
 
+    /// The length of the line break at the end of `buffer`, if any.
+    fn trailing_break_len(buffer: &str) -> usize {
+        if buffer.ends_with("\r\n") {
+            2
+        } else {
+            usize::from(buffer.ends_with(['\r', '\n']))
+        }
+    }
+
     /// Flush the accumulated characters to the output.
+    ///
+    /// If the trailing newline is disabled, a line break at the end of the
+    /// output is only written once more output follows it or the stream is
+    /// closed, see [`Emitter::set_trailing_newline()`].
+    ///
+    /// If no output handler is set, the output is collected by the emitter
+    /// itself, to be returned by [`Emitter::into_vec()`].
     pub fn flush(&mut self) -> Result<()> {
-        assert!((self.write_handler).is_some());
         assert_ne!(self.encoding, Encoding::Any);
 
-        if self.buffer.is_empty() {
+        let held = if self.trailing_newline || self.state == EmitterState::End {
+            0
+        } else {
+            Self::trailing_break_len(&self.buffer)
+        };
+        let end = self.buffer.len() - held;
+        if end == 0 {
+            return Ok(());
+        }
+
+        if let Some(handler) = self.fmt_write_handler.as_mut() {
+            if handler.write_str(&self.buffer[..end]).is_err() {
+                return Err(formatter_error());
+            }
+            self.buffer.drain(..end);
             return Ok(());
         }
 
         if self.encoding == Encoding::Utf8 {
-            let to_emit = self.buffer.as_bytes();
-            self.write_handler
-                .as_mut()
-                .expect("non-null writer")
-                .write_all(to_emit)?;
-            self.buffer.clear();
+            let to_emit = &self.buffer.as_bytes()[..end];
+            match self.write_handler.as_mut() {
+                Some(handler) => handler.write_all(to_emit)?,
+                None => self.owned_output.extend_from_slice(to_emit),
+            }
+            self.buffer.drain(..end);
             return Ok(());
         }
 
@@ -1792,7 +3001,7 @@ impl<'w> Emitter<'w> {
             Encoding::Utf16Be => true,
         };
 
-        for ch in self.buffer.encode_utf16() {
+        for ch in self.buffer[..end].encode_utf16() {
             let bytes = if big_endian {
                 ch.to_be_bytes()
             } else {
@@ -1802,30 +3011,2022 @@ impl<'w> Emitter<'w> {
         }
 
         let to_emit = self.raw_buffer.as_slice();
-
-        self.write_handler
-            .as_mut()
-            .expect("non-null function pointer")
-            .write_all(to_emit)?;
-        self.buffer.clear();
+        match self.write_handler.as_mut() {
+            Some(handler) => handler.write_all(to_emit)?,
+            None => self.owned_output.extend_from_slice(to_emit),
+        }
+        self.buffer.drain(..end);
         self.raw_buffer.clear();
         Ok(())
     }
 
-    pub(crate) fn reset_anchors(&mut self) {
-        self.anchors.clear();
-        self.last_anchor_id = 0;
+    /// Start a sequence whose items are emitted one at a time with the
+    /// returned [`SequenceWriter`].
+    ///
+    /// This emits the SEQUENCE-START event, so the emitter must be in a
+    /// position where a node is expected. The sequence is closed with
+    /// [`SequenceWriter::finish()`].
+    pub fn begin_sequence(
+        &mut self,
+        tag: Option<&str>,
+        style: SequenceStyle,
+    ) -> Result<SequenceWriter<'_, 'w>> {
+        self.emit(Event::sequence_start(None, tag, tag.is_none(), style))?;
+        Ok(SequenceWriter { emitter: self })
     }
 
-    pub(crate) fn anchor_node_sub(&mut self, index: i32) {
-        self.anchors[index as usize - 1].references += 1;
-        if self.anchors[index as usize - 1].references == 2 {
-            self.last_anchor_id += 1;
-            self.anchors[index as usize - 1].anchor = self.last_anchor_id;
-        }
+    /// Emit a plain implicit scalar, which may be written in any style.
+    pub fn scalar(&mut self, value: &str) -> Result<()> {
+        self.emit(Event::scalar(
+            None,
+            None,
+            value,
+            true,
+            false,
+            ScalarStyle::Any,
+        ))
     }
 
-    pub(crate) fn generate_anchor(anchor_id: i32) -> String {
-        alloc::format!("id{anchor_id:03}")
+    /// Emit a mapping whose pairs are emitted by `body`, between the
+    /// MAPPING-START and MAPPING-END events.
+    ///
+    /// The emitter must be in a position where a node is expected. An error
+    /// returned by `body` is returned without emitting the MAPPING-END event.
+    pub fn mapping(
+        &mut self,
+        body: impl FnOnce(&mut MappingScope<'_, 'w>) -> Result<()>,
+    ) -> Result<()> {
+        self.emit(Event::mapping_start(None, None, true, MappingStyle::Any))?;
+        body(&mut MappingScope { emitter: self })?;
+        self.emit(Event::mapping_end())
+    }
+
+    /// Emit a sequence whose items are emitted by `body`, between the
+    /// SEQUENCE-START and SEQUENCE-END events.
+    ///
+    /// The emitter must be in a position where a node is expected. An error
+    /// returned by `body` is returned without emitting the SEQUENCE-END event.
+    pub fn sequence(
+        &mut self,
+        body: impl FnOnce(&mut SequenceScope<'_, 'w>) -> Result<()>,
+    ) -> Result<()> {
+        self.emit(Event::sequence_start(None, None, true, SequenceStyle::Any))?;
+        body(&mut SequenceScope { emitter: self })?;
+        self.emit(Event::sequence_end())
+    }
+
+    pub(crate) fn generate_anchor(anchor_id: i32) -> String {
+        alloc::format!("id{anchor_id:03}")
+    }
+}
+
+/// Writes the items of a sequence one at a time, see
+/// [`Emitter::begin_sequence()`].
+///
+/// The output is flushed after each item, so only the events of the item
+/// being written are kept in memory.
+#[must_use = "the sequence must be closed with `finish()`"]
+pub struct SequenceWriter<'e, 'w> {
+    emitter: &'e mut Emitter<'w>,
+}
+
+impl<'w> SequenceWriter<'_, 'w> {
+    /// Emit the events of one item and flush the output.
+    pub fn push(&mut self, item: impl IntoIterator<Item = Event>) -> Result<()> {
+        for event in item {
+            self.emitter.emit(event)?;
+        }
+        self.emitter.flush()
+    }
+
+    /// Start a nested sequence as the next item.
+    ///
+    /// The nested sequence must be finished before more items can be pushed
+    /// to this one.
+    pub fn begin_sequence(
+        &mut self,
+        tag: Option<&str>,
+        style: SequenceStyle,
+    ) -> Result<SequenceWriter<'_, 'w>> {
+        self.emitter.begin_sequence(tag, style)
+    }
+
+    /// Emit the SEQUENCE-END event and flush the output.
+    pub fn finish(self) -> Result<()> {
+        self.emitter.emit(Event::sequence_end())?;
+        self.emitter.flush()
+    }
+}
+
+/// Emits the pairs of a mapping, see [`Emitter::mapping()`].
+pub struct MappingScope<'e, 'w> {
+    emitter: &'e mut Emitter<'w>,
+}
+
+impl<'w> MappingScope<'_, 'w> {
+    /// Emit a pair with a scalar key, whose value is emitted by `value`.
+    ///
+    /// `value` must emit exactly one node, such as a scalar with
+    /// [`Emitter::scalar()`] or a collection with [`Emitter::mapping()`] or
+    /// [`Emitter::sequence()`].
+    pub fn entry(
+        &mut self,
+        key: &str,
+        value: impl FnOnce(&mut Emitter<'w>) -> Result<()>,
+    ) -> Result<()> {
+        self.emitter.scalar(key)?;
+        value(self.emitter)
+    }
+}
+
+/// Emits the items of a sequence, see [`Emitter::sequence()`].
+pub struct SequenceScope<'e, 'w> {
+    emitter: &'e mut Emitter<'w>,
+}
+
+impl<'w> SequenceScope<'_, 'w> {
+    /// Emit an item, which is emitted by `item`.
+    ///
+    /// `item` must emit exactly one node, like the value of
+    /// [`MappingScope::entry()`].
+    pub fn item(&mut self, item: impl FnOnce(&mut Emitter<'w>) -> Result<()>) -> Result<()> {
+        item(self.emitter)
+    }
+}
+
+/// Check if the value has to be quoted to be written in the given context.
+///
+/// This applies the rules the emitter uses to decide if a scalar can be
+/// written in the plain style, with non-ASCII characters left unescaped as
+/// with [`Emitter::set_unicode()`]. Only the syntax is checked: values like
+/// `true` or `1` can be written plain, but are then not read back as strings,
+/// see [`core_schema_tag()`](crate::core_schema_tag).
+pub fn scalar_needs_quoting(value: &str, context: ScalarContext) -> bool {
+    let analysis = analyze_scalar(value, |_| false);
+    let (flow, key) = match context {
+        ScalarContext::BlockKey => (false, true),
+        ScalarContext::BlockValue => (false, false),
+        ScalarContext::FlowKey => (true, true),
+        ScalarContext::FlowValue => (true, false),
+    };
+    let plain_allowed = if flow {
+        analysis.flow_plain_allowed
+    } else {
+        analysis.block_plain_allowed
+    };
+    !plain_allowed || value.is_empty() && (flow || key) || key && analysis.multiline
+}
+
+/// Analyze the styles the value can be written in, given the characters that
+/// have to be escaped.
+fn analyze_scalar(value: &str, must_escape: impl Fn(char) -> bool) -> ScalarAnalysis<'_> {
+    if is_simple_scalar(value) {
+        return ScalarAnalysis {
+            value,
+            multiline: false,
+            flow_plain_allowed: true,
+            block_plain_allowed: true,
+            single_quoted_allowed: true,
+            block_allowed: true,
+            style: ScalarStyle::Any,
+        };
+    }
+    analyze_scalar_chars(value, must_escape)
+}
+
+fn analyze_scalar_chars(value: &str, must_escape: impl Fn(char) -> bool) -> ScalarAnalysis<'_> {
+    let mut block_indicators = false;
+    let mut flow_indicators = false;
+    let mut line_breaks = false;
+    let mut special_characters = false;
+    let mut leading_space = false;
+    let mut leading_break = false;
+    let mut trailing_space = false;
+    let mut trailing_break = false;
+    let mut break_space = false;
+    let mut space_break = false;
+    let mut preceded_by_whitespace;
+    let mut previous_space = false;
+    let mut previous_break = false;
+
+    if value.is_empty() {
+        return ScalarAnalysis {
+            value: "",
+            multiline: false,
+            flow_plain_allowed: false,
+            block_plain_allowed: true,
+            single_quoted_allowed: true,
+            block_allowed: false,
+            style: ScalarStyle::Any,
+        };
+    }
+
+    if value.starts_with("---") || value.starts_with("...") {
+        block_indicators = true;
+        flow_indicators = true;
+    }
+    preceded_by_whitespace = true;
+
+    let mut chars = value.chars();
+    let mut first = true;
+
+    while let Some(ch) = chars.next() {
+        let next = chars.clone().next();
+        let followed_by_whitespace = is_blankz(next);
+        if first {
+            match ch {
+                '#' | ',' | '[' | ']' | '{' | '}' | '&' | '*' | '!' | '|' | '>' | '\'' | '"'
+                | '%' | '@' | '`' => {
+                    flow_indicators = true;
+                    block_indicators = true;
+                }
+                '?' | ':' => {
+                    flow_indicators = true;
+                    if followed_by_whitespace {
+                        block_indicators = true;
+                    }
+                }
+                '-' if followed_by_whitespace => {
+                    flow_indicators = true;
+                    block_indicators = true;
+                }
+                _ => {}
+            }
+        } else {
+            match ch {
+                ',' | '?' | '[' | ']' | '{' | '}' => {
+                    flow_indicators = true;
+                }
+                ':' => {
+                    flow_indicators = true;
+                    if followed_by_whitespace {
+                        block_indicators = true;
+                    }
+                }
+                '#' if preceded_by_whitespace => {
+                    flow_indicators = true;
+                    block_indicators = true;
+                }
+                _ => {}
+            }
+        }
+
+        if !is_printable(ch) || must_escape(ch) {
+            special_characters = true;
+        }
+        if is_break(ch) {
+            line_breaks = true;
+        }
+
+        if is_space(ch) {
+            if first {
+                leading_space = true;
+            }
+            if next.is_none() {
+                trailing_space = true;
+            }
+            if previous_break {
+                break_space = true;
+            }
+            previous_space = true;
+            previous_break = false;
+        } else if is_break(ch) {
+            if first {
+                leading_break = true;
+            }
+            if next.is_none() {
+                trailing_break = true;
+            }
+            if previous_space {
+                space_break = true;
+            }
+            previous_space = false;
+            previous_break = true;
+        } else {
+            previous_space = false;
+            previous_break = false;
+        }
+
+        preceded_by_whitespace = is_blankz(ch);
+        first = false;
+    }
+
+    let mut analysis = ScalarAnalysis {
+        value,
+        multiline: line_breaks,
+        flow_plain_allowed: true,
+        block_plain_allowed: true,
+        single_quoted_allowed: true,
+        block_allowed: true,
+        style: ScalarStyle::Any,
+    };
+
+    analysis.multiline = line_breaks;
+    analysis.flow_plain_allowed = true;
+    analysis.block_plain_allowed = true;
+    analysis.single_quoted_allowed = true;
+    analysis.block_allowed = true;
+    if leading_space || leading_break || trailing_space || trailing_break {
+        analysis.flow_plain_allowed = false;
+        analysis.block_plain_allowed = false;
+    }
+    if trailing_space {
+        analysis.block_allowed = false;
+    }
+    if break_space {
+        analysis.flow_plain_allowed = false;
+        analysis.block_plain_allowed = false;
+        analysis.single_quoted_allowed = false;
+    }
+    if space_break || special_characters {
+        analysis.flow_plain_allowed = false;
+        analysis.block_plain_allowed = false;
+        analysis.single_quoted_allowed = false;
+        analysis.block_allowed = false;
+    }
+    if line_breaks {
+        analysis.flow_plain_allowed = false;
+        analysis.block_plain_allowed = false;
+    }
+    if flow_indicators {
+        analysis.flow_plain_allowed = false;
+    }
+    if block_indicators {
+        analysis.block_plain_allowed = false;
+    }
+    analysis
+}
+
+/// Check if the value can be written in any style without looking at each
+/// character in turn.
+///
+/// This is true for values that only contain printable ASCII characters
+/// without line breaks or indicators, and do not start or end with a space.
+/// Such values are common, and the analysis of them always allows all styles.
+fn is_simple_scalar(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    let (Some(first), Some(last)) = (bytes.first(), bytes.last()) else {
+        return false;
+    };
+    let is_simple = |byte: &u8| {
+        byte.is_ascii_alphanumeric()
+            || matches!(
+                byte,
+                b'_' | b'/' | b'\\' | b'(' | b')' | b'+' | b'=' | b'<' | b'$' | b'^' | b'~' | b';'
+            )
+    };
+    is_simple(first)
+        && *last != b' '
+        && bytes
+            .iter()
+            .all(|byte| is_simple(byte) || matches!(byte, b' ' | b'-' | b'.'))
+}
+
+/// Check if the value is a number in the JSON syntax.
+fn is_json_number(value: &str) -> bool {
+    let value = value.strip_prefix('-').unwrap_or(value);
+    let (mantissa, exponent) = match value.find(['e', 'E']) {
+        Some(index) => (&value[..index], Some(&value[index + 1..])),
+        None => (value, None),
+    };
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let is_digits =
+        |digits: &str| !digits.is_empty() && digits.bytes().all(|ch| ch.is_ascii_digit());
+    is_digits(integer)
+        && (integer == "0" || !integer.starts_with('0'))
+        && fraction.map_or(true, is_digits)
+        && exponent.map_or(true, |exponent| {
+            is_digits(exponent.strip_prefix(['-', '+']).unwrap_or(exponent))
+        })
+}
+
+/// Check if a plain scalar with this value would be resolved to something
+/// other than a string, by either the YAML 1.2 core schema or the YAML 1.1
+/// boolean rules.
+fn is_ambiguous_plain(value: &str) -> bool {
+    const YAML_1_1_BOOLS: &[&str] = &[
+        "y", "Y", "yes", "Yes", "YES", "n", "N", "no", "No", "NO", "on", "On", "ON", "off", "Off",
+        "OFF",
+    ];
+    YAML_1_1_BOOLS.contains(&value)
+        || resolver::is_null(value)
+        || resolver::parse_bool(value).is_some()
+        || resolver::parse_int(value).is_some()
+        || resolver::parse_float(value).is_some()
+}
+
+/// Parse a YAML stream from `reader` and write it to `writer` again, with the
+/// configuration of the `config` emitter.
+///
+/// The events are passed on as they are parsed, so no document is kept in
+/// memory. The styles of the scalars and collections are kept when the
+/// emitter allows them, while the indentation and line widths of the output
+/// are the ones of the emitter. Comments are not kept. The output handler of
+/// `config`, if any, is not used.
+#[cfg(feature = "std")]
+pub fn reformat<R: std::io::BufRead, W: std::io::Write>(
+    mut reader: R,
+    mut writer: W,
+    mut config: Emitter,
+) -> Result<()> {
+    let mut parser = crate::Parser::new();
+    parser.set_input(&mut reader);
+    let mut emitter = config.take_config();
+    emitter.set_output(&mut writer);
+    loop {
+        let event = parser.parse()?;
+        let done = matches!(event.data, EventData::StreamEnd);
+        emitter.emit(event)?;
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// Replace a preferred width that is too small for the indentation
+/// increment by 80, and an unlimited width by `i32::MAX`.
+fn resolve_width(width: i32, best_indent: i32) -> i32 {
+    if width < 0 {
+        i32::MAX
+    } else if width <= best_indent * 2 {
+        80
+    } else {
+        width
+    }
+}
+
+/// The error reported when a formatter output handler fails.
+#[cfg(feature = "std")]
+fn formatter_error() -> Error {
+    std::io::Error::new(std::io::ErrorKind::Other, "formatter error").into()
+}
+
+/// The error reported when a formatter output handler fails.
+#[cfg(not(feature = "std"))]
+fn formatter_error() -> Error {
+    Error::emitter("formatter error")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Document, NodeData, Parser};
+    use alloc::{format, vec};
+
+    fn reemit(input: &str, configure: impl FnOnce(&mut Emitter)) -> String {
+        let mut parser = Parser::new();
+        let mut read_in = input.as_bytes();
+        parser.set_input_string(&mut read_in);
+
+        let mut emitter = Emitter::new();
+        configure(&mut emitter);
+        for event in parser {
+            emitter.emit(event.unwrap()).unwrap();
+        }
+        String::from_utf8(emitter.into_vec().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn try_constructors() {
+        let problem = |result: Result<Event>| result.unwrap_err().problem();
+        assert_eq!(
+            problem(Event::try_alias("")),
+            "alias value must not be empty"
+        );
+        assert_eq!(
+            problem(Event::try_scalar(
+                Some("a b"),
+                None,
+                "c",
+                true,
+                true,
+                ScalarStyle::Any
+            )),
+            "anchor value must contain alphanumerical characters only"
+        );
+        assert_eq!(
+            problem(Event::try_scalar(
+                None,
+                None,
+                "c",
+                false,
+                false,
+                ScalarStyle::Any
+            )),
+            "neither tag nor implicit flags are specified"
+        );
+        assert_eq!(
+            problem(Event::try_sequence_start(
+                None,
+                Some(""),
+                false,
+                SequenceStyle::Any
+            )),
+            "tag value must not be empty"
+        );
+        assert_eq!(
+            problem(Event::try_mapping_start(
+                Some(""),
+                None,
+                true,
+                MappingStyle::Any
+            )),
+            "anchor value must not be empty"
+        );
+
+        let mut emitter = Emitter::new();
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        emitter
+            .emit(Event::document_start(None, &[], true))
+            .unwrap();
+        for event in [
+            Event::try_sequence_start(Some("a"), None, true, SequenceStyle::Flow),
+            Event::try_mapping_start(None, Some("!m"), false, MappingStyle::Flow),
+            Event::try_scalar(None, None, "b", true, true, ScalarStyle::Any),
+            Event::try_alias("a"),
+        ] {
+            emitter.emit(event.unwrap()).unwrap();
+        }
+        emitter.emit(Event::mapping_end()).unwrap();
+        emitter.emit(Event::sequence_end()).unwrap();
+        emitter.emit(Event::document_end(true)).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(output, "&a [!m {b: *a}]\n");
+    }
+
+    #[test]
+    fn emit_fragment() {
+        let load = |input: &str| Document::load(&mut Parser::from_str(input)).unwrap();
+        let mut emitter = Emitter::new();
+        emitter.set_encoding(Encoding::Utf16Le);
+        emitter.set_base_indent(4);
+        emitter
+            .emit_fragment(load(
+                "%TAG !e! tag:example.com,2000:\n--- !e!point\na: [1, 2]\nb: !!int 3\n",
+            ))
+            .unwrap();
+        emitter.emit_fragment(load("- c\n- d\n")).unwrap();
+        emitter.emit_fragment(load("")).unwrap();
+        let err = emitter.emit_fragment(load("e")).and_then(|()| {
+            emitter.emit(Event::document_start(None, &[], true))?;
+            emitter.emit_fragment(load("f"))
+        });
+        assert_eq!(
+            err.unwrap_err().problem(),
+            "cannot emit a fragment while events are waiting"
+        );
+        let output: Vec<u16> = emitter
+            .into_vec()
+            .unwrap()
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        assert_eq!(
+            String::from_utf16(&output).unwrap(),
+            "    !<tag:example.com,2000:point>\n    a: [1, 2]\n    b: !!int 3\n    - c\n    - d\n    e\n"
+        );
+
+        let mut emitter = Emitter::new();
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        emitter
+            .emit(Event::document_start(None, &[], true))
+            .unwrap();
+        emitter
+            .emit(Event::scalar(None, None, "g", true, true, ScalarStyle::Any))
+            .unwrap();
+        let err = emitter.emit_fragment(load("h")).unwrap_err();
+        assert_eq!(err.problem(), "expected no open document for a fragment");
+    }
+
+    #[test]
+    fn scoped_collections() {
+        let mut emitter = Emitter::new();
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        emitter
+            .emit(Event::document_start(None, &[], true))
+            .unwrap();
+        emitter
+            .mapping(|m| {
+                m.entry("name", |e| e.scalar("app"))?;
+                m.entry("ports", |e| {
+                    e.sequence(|s| {
+                        s.item(|e| e.scalar("80"))?;
+                        s.item(|e| e.mapping(|m| m.entry("tls", |e| e.scalar("443"))))
+                    })
+                })?;
+                m.entry("empty", |e| e.mapping(|_| Ok(())))
+            })
+            .unwrap();
+        emitter.emit(Event::document_end(true)).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(output, "name: app\nports:\n- 80\n- tls: 443\nempty: {}\n");
+
+        let mut emitter = Emitter::new();
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        emitter
+            .emit(Event::document_start(None, &[], true))
+            .unwrap();
+        let err = emitter
+            .sequence(|s| s.item(|e| e.emit(Event::document_end(true))))
+            .unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "expected SCALAR, SEQUENCE-START, MAPPING-START, or ALIAS"
+        );
+    }
+
+    #[test]
+    fn prefer_verbatim_tags() {
+        const INPUT: &str = "%TAG !e! tag:example.com,2024:\n---\na: !e!thing x\nb: !<tag:example.com,2024:thing> y\nc: !!str z\nd: !local w\ne: !<tag:other.org,2024:x> v\n";
+        assert_eq!(
+            reemit(INPUT, |_| {}),
+            "%TAG !e! tag:example.com,2024:\n---\na: !e!thing x\nb: !e!thing y\nc: !!str z\nd: !local w\ne: !<tag:other.org,2024:x> v\n"
+        );
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter.set_prefer_verbatim_tags(true)),
+            "%TAG !e! tag:example.com,2024:\n---\na: !<tag:example.com,2024:thing> x\nb: !<tag:example.com,2024:thing> y\nc: !<tag:yaml.org,2002:str> z\nd: !<!local> w\ne: !<tag:other.org,2024:x> v\n"
+        );
+        let tags: Vec<_> = Parser::from_str("!<!local> w\n")
+            .filter_map(|event| match event.unwrap().data {
+                EventData::Scalar { tag, .. } => tag,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tags, ["!local"]);
+    }
+
+    #[test]
+    fn position_accessors() {
+        let mut parser = Parser::from_str("a:\n  b: [c, {d: e}]\n  f: g\n");
+        let mut emitter = Emitter::new();
+        let mut positions = Vec::new();
+        for event in &mut parser {
+            emitter.emit(event.unwrap()).unwrap();
+            positions.push((
+                emitter.flow_level(),
+                emitter.current_indent(),
+                emitter.in_flow_context(),
+                emitter.in_key_context(),
+            ));
+        }
+        assert_eq!(
+            positions,
+            [
+                (0, 0, false, false),
+                (0, 0, false, false),
+                // Collection starts wait for the following events, so the
+                // position lags behind until enough events are queued.
+                (0, 0, false, false),
+                (0, 0, false, false),
+                (0, 0, false, false),
+                (0, 0, false, false),
+                (0, 0, false, false),
+                (0, 2, false, false),
+                (1, 4, true, false),
+                (1, 4, true, false),
+                (1, 4, true, false),
+                (1, 4, true, false),
+                (0, 2, false, true),
+                (0, 2, false, false),
+                (0, 2, false, true),
+                (0, 0, false, true),
+                (0, 0, false, false),
+                (0, 0, false, false),
+                (0, 0, false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn quote_keys() {
+        let input = "name: app\nports:\n- 80\n- {host: 8080}\n1: one\n? - a\n: 'b'\n";
+        assert_eq!(reemit(input, |_| {}), input);
+        let output = reemit(input, |emitter| emitter.set_quote_keys(true));
+        assert_eq!(
+            output,
+            "\"name\": app\n\"ports\":\n- 80\n- {\"host\": 8080}\n\"1\": one\n? - a\n: 'b'\n"
+        );
+
+        let output = reemit("? |\n  long\n  key\n: x\n", |emitter| {
+            emitter.set_quote_keys(true);
+            emitter.set_force_explicit_keys(true);
+        });
+        assert_eq!(output, "? \"long\\nkey\\n\"\n: x\n");
+    }
+
+    #[test]
+    fn write_verbatim() {
+        let mut emitter = Emitter::new();
+        let err = emitter.write_verbatim("a: 1\n").unwrap_err();
+        assert_eq!(err.problem(), "expected an open stream for verbatim text");
+
+        let mut parser = Parser::from_str("outer:\n  a: 1\n  b: 2\n");
+        let mut emitter = Emitter::new();
+        for event in &mut parser {
+            let event = event.unwrap();
+            let value = |expected| matches!(&event.data, EventData::Scalar { value, .. } if value == expected);
+            let (first, last) = (value("1"), value("2"));
+            emitter.emit(event).unwrap();
+            if last {
+                emitter
+                    .write_verbatim("vendored:  # kept as is\n  x: [1,2]\n\n  'y': |\n    z\n")
+                    .unwrap();
+            } else if first {
+                // The events of the inner mapping are held until its first
+                // pairs are known.
+                let err = emitter.write_verbatim("c: 3\n").unwrap_err();
+                assert_eq!(
+                    err.problem(),
+                    "cannot write verbatim text while events are waiting"
+                );
+            }
+        }
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            "outer:\n  a: 1\n  b: 2\n  vendored:  # kept as is\n    x: [1,2]\n\n    'y': |\n      z\n"
+        );
+        assert_eq!(crate::parse_all(output.as_bytes()).unwrap().len(), 23);
+    }
+
+    #[test]
+    fn trailing_newline() {
+        let cases = [
+            ("a", "a\n", "a"),
+            ("{a: [b]}", "{a: [b]}\n", "{a: [b]}"),
+            ("a: |\n  x\n", "a: |\n  x\n", "a: |\n  x\n"),
+            ("a: |-\n  x\n", "a: |-\n  x\n", "a: |-\n  x"),
+            (
+                "--- a\n--- >\n  b\n...\n",
+                "--- a\n--- >\n  b\n...\n",
+                "--- a\n--- >\n  b\n...",
+            ),
+            ("", "", ""),
+        ];
+        for (input, with, without) in cases {
+            assert_eq!(reemit(input, |_| {}), with);
+            assert_eq!(
+                reemit(input, |emitter| emitter.set_trailing_newline(false)),
+                without
+            );
+        }
+
+        let output = reemit("--- |+\n  x\n\n", |emitter| {
+            emitter.set_trailing_newline(false);
+            emitter.set_open_ended_policy(OpenEndedPolicy::Never);
+        });
+        assert_eq!(output, "--- |+\n  x\n\n");
+        let output = reemit("[a]\n", |emitter| {
+            emitter.set_trailing_newline(false);
+            emitter.set_break(Break::CrLn);
+        });
+        assert_eq!(output, "[a]");
+    }
+
+    #[test]
+    fn align_values() {
+        const INPUT: &str = "name: alice
+country: canada
+&a k: 1
+*a : 2
+'it''s': 3
+? [x, y]
+: 4
+nested:
+  a: 1
+  longer: 2
+  list:
+  - q: 1
+    qq: 2
+z: |
+  literal
+";
+        const OUTPUT: &str = "name:    alice
+country: canada
+&a k:    1
+*a :     2
+'it''s': 3
+? [x, y]
+: 4
+nested:
+  a:      1
+  longer: 2
+  list:
+  - q:  1
+    qq: 2
+z:       |
+  literal
+";
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter.set_align_values(true)),
+            OUTPUT
+        );
+        assert_eq!(
+            reemit("a: 1\nbb: [x]\n", |emitter| {
+                emitter.set_align_values(true);
+                emitter.set_force_explicit_keys(true);
+            }),
+            "? a\n: 1\n? bb\n: [x]\n"
+        );
+    }
+
+    #[test]
+    fn blank_line_between_top_level_keys() {
+        const INPUT: &str = "name: alice
+address:
+  street: main
+  city: paris
+tags: [a, b]
+note: |
+  literal
+kept: |+
+  text
+
+last: 1
+";
+        const OUTPUT: &str = "name: alice
+
+address:
+  street: main
+  city: paris
+
+tags: [a, b]
+
+note: |
+  literal
+
+kept: |+
+  text
+
+last: 1
+...
+";
+        assert_eq!(
+            reemit(INPUT, |emitter| {
+                emitter.set_blank_line_between_top_level_keys(true);
+            }),
+            OUTPUT
+        );
+        assert_eq!(
+            reemit("- a: 1\n  b: 2\n", |emitter| {
+                emitter.set_blank_line_between_top_level_keys(true);
+            }),
+            "- a: 1\n  b: 2\n"
+        );
+    }
+
+    #[test]
+    fn base_indent() {
+        const INPUT: &str = "%YAML 1.2
+---
+a: 1
+b:
+- x
+- |
+  literal
+
+  text
+";
+        const OUTPUT: &str = "  %YAML 1.2
+  ---
+  a: 1
+  b:
+  - x
+  - |
+    literal
+
+    text
+";
+        assert_eq!(reemit(INPUT, |emitter| emitter.set_base_indent(2)), OUTPUT);
+    }
+
+    #[test]
+    fn open_ended_policy() {
+        const INPUT: &str = "--- |+
+  keep
+
+%YAML 1.2
+--- plain
+";
+        assert_eq!(
+            reemit(INPUT, |_| {}),
+            "--- |+\n  keep\n\n...\n%YAML 1.2\n--- plain\n"
+        );
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter
+                .set_open_ended_policy(OpenEndedPolicy::Never)),
+            "--- |+\n  keep\n\n%YAML 1.2\n--- plain\n"
+        );
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter
+                .set_open_ended_policy(OpenEndedPolicy::Always)),
+            "--- |+\n  keep\n\n...\n%YAML 1.2\n--- plain\n...\n"
+        );
+    }
+
+    #[test]
+    fn hard_line_limit() {
+        let token = "x".repeat(200);
+
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_hard_line_limit(Some(80));
+        emitter.set_output_string(&mut output);
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        emitter
+            .emit(Event::document_start(None, &[], true))
+            .unwrap();
+        let err = emitter
+            .emit(Event::scalar(
+                None,
+                None,
+                &token,
+                true,
+                true,
+                ScalarStyle::Plain,
+            ))
+            .unwrap_err();
+        assert_eq!(err.problem(), "line exceeds hard limit");
+        // The line is cut at the limit rather than fully buffered.
+        assert_eq!(emitter.buffer, "x".repeat(80));
+
+        let short = reemit("key: value\n", |emitter| {
+            emitter.set_hard_line_limit(Some(80));
+        });
+        assert_eq!(short, "key: value\n");
+    }
+
+    #[test]
+    fn flow_wrap() {
+        let items: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let input = format!("[{}]\n", items.join(", "));
+
+        let wrapped = reemit(&input, |emitter| emitter.set_width(40));
+        let lines: Vec<&str> = wrapped.lines().collect();
+        assert!(lines.len() > 1);
+        for line in &lines[..lines.len() - 1] {
+            assert!(line.len() <= 40 + 4, "{line:?}");
+            assert!(line.ends_with(','), "{line:?}");
+        }
+        for line in &lines[1..] {
+            assert!(
+                line.starts_with("  ") && !line.starts_with("   "),
+                "{line:?}"
+            );
+        }
+
+        let mut parser = Parser::new();
+        let mut read_in = wrapped.as_bytes();
+        parser.set_input_string(&mut read_in);
+        let values: Vec<String> = parser
+            .filter_map(|event| match event.unwrap().data {
+                EventData::Scalar { value, .. } => Some(value),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(values, items);
+
+        let unwrapped = reemit(&input, |emitter| {
+            emitter.set_width(40);
+            emitter.set_flow_wrap(false);
+        });
+        assert_eq!(unwrapped, input);
+    }
+
+    #[test]
+    fn output_fmt() {
+        struct Greeting;
+
+        impl core::fmt::Display for Greeting {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                let mut emitter = Emitter::new();
+                emitter.set_output_fmt(f);
+                let events = [
+                    Event::stream_start(Encoding::Utf8),
+                    Event::document_start(None, &[], true),
+                    Event::scalar(None, None, "h\u{e9}llo", true, true, ScalarStyle::Any),
+                    Event::document_end(true),
+                    Event::stream_end(),
+                ];
+                for event in events {
+                    emitter.emit(event).map_err(|_| core::fmt::Error)?;
+                }
+                Ok(())
+            }
+        }
+
+        assert_eq!(Greeting.to_string(), "\"h\\xE9llo\"\n");
+    }
+
+    #[test]
+    fn quote_ambiguous_strings() {
+        fn emit(value: &str, tag: Option<&str>, plain_implicit: bool) -> String {
+            let mut output = Vec::new();
+            let mut emitter = Emitter::new();
+            emitter.set_quote_ambiguous_strings(true);
+            emitter.set_output_string(&mut output);
+            let events = [
+                Event::stream_start(Encoding::Utf8),
+                Event::document_start(None, &[], true),
+                Event::scalar(None, tag, value, plain_implicit, true, ScalarStyle::Any),
+                Event::document_end(true),
+                Event::stream_end(),
+            ];
+            for event in events {
+                emitter.emit(event).unwrap();
+            }
+            drop(emitter);
+            String::from_utf8(output).unwrap()
+        }
+
+        for value in [
+            "true", "no", "NULL", "~", "123", "-1.5e3", ".5", "0x1F", "0o17", ".nan",
+        ] {
+            assert_eq!(emit(value, None, false), format!("'{value}'\n"));
+            assert_eq!(emit(value, Some(STR_TAG), true), format!("'{value}'\n"));
+            assert_eq!(emit(value, None, true), format!("{value}\n"));
+        }
+        for value in ["yes please", "1.2.3", "0x", "1e", "nan", "_1"] {
+            assert_eq!(emit(value, Some(STR_TAG), true), format!("{value}\n"));
+        }
+    }
+
+    #[test]
+    fn try_set_indent_and_width() {
+        let mut emitter = Emitter::new();
+        assert!(emitter.try_set_width(4).is_err());
+        for indent in [0, 1, 10, -3] {
+            let err = emitter.try_set_indent(indent).unwrap_err();
+            assert_eq!(err.problem(), "indent must be between 2 and 9");
+        }
+        emitter.try_set_indent(4).unwrap();
+        assert_eq!(emitter.best_indent, 4);
+
+        for width in [-2, 0, 8] {
+            assert!(emitter.try_set_width(width).is_err());
+        }
+        emitter.try_set_width(9).unwrap();
+        assert_eq!(emitter.best_width, 9);
+        emitter.try_set_width(-1).unwrap();
+        assert_eq!(emitter.best_width, -1);
+        assert_eq!(emitter.width(), i32::MAX);
+    }
+
+    #[test]
+    fn width_between_documents() {
+        let mut emitter = Emitter::new();
+        assert_eq!(emitter.width(), 80);
+        emitter.set_width(-1);
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        assert_eq!(emitter.width(), i32::MAX);
+        let value = "the quick brown fox jumps over the lazy dog";
+        for width in [20, 0, 3, 30] {
+            emitter
+                .emit(Event::document_start(None, &[], false))
+                .unwrap();
+            emitter
+                .emit(Event::scalar(
+                    None,
+                    None,
+                    value,
+                    true,
+                    true,
+                    ScalarStyle::Any,
+                ))
+                .unwrap();
+            emitter.emit(Event::document_end(true)).unwrap();
+            emitter.set_width(width);
+        }
+        assert_eq!(emitter.width(), 30);
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            "--- the quick brown fox jumps over the lazy dog\n\
+             --- the quick brown fox\n  jumps over the lazy\n  dog\n\
+             --- the quick brown fox jumps over the lazy dog\n\
+             --- the quick brown fox jumps over the lazy dog\n"
+        );
+    }
+
+    #[test]
+    fn force_explicit_keys() {
+        const INPUT: &str = "a: 1\nb:\n  c: [x, y]\n  d: {e: f, g: h}\n";
+        let output = reemit(INPUT, |emitter| emitter.set_force_explicit_keys(true));
+        assert_eq!(
+            output,
+            "? a\n: 1\n? b\n: ? c\n  : [x, y]\n  ? d\n  : {? e : f, ? g : h}\n"
+        );
+
+        let events = |input: &str| {
+            let mut parser = Parser::new();
+            let mut read_in = input.as_bytes();
+            parser.set_input_string(&mut read_in);
+            parser
+                .map(|event| {
+                    let mut data = event.unwrap().data;
+                    // The input has no anchors or tags, only the positions of
+                    // the values differ.
+                    if let EventData::Scalar { value_mark, .. }
+                    | EventData::SequenceStart { value_mark, .. }
+                    | EventData::MappingStart { value_mark, .. } = &mut data
+                    {
+                        *value_mark = None;
+                    }
+                    format!("{data:?}")
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(events(&output), events(INPUT));
+    }
+
+    #[test]
+    fn comments() {
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output_string(&mut output);
+        let events = [
+            Event::stream_start(Encoding::Utf8),
+            Event::document_start(None, &[], true).with_leading_comment("header"),
+            Event::mapping_start(None, None, true, MappingStyle::Block),
+            Event::scalar(None, None, "a", true, true, ScalarStyle::Plain)
+                .with_leading_comment("about a\nsecond line"),
+            Event::scalar(None, None, "1", true, true, ScalarStyle::Plain)
+                .with_trailing_comment("one"),
+            Event::scalar(None, None, "b", true, true, ScalarStyle::Plain)
+                .with_trailing_comment("not written in a simple key"),
+            Event::sequence_start(None, None, true, SequenceStyle::Block),
+            Event::scalar(None, None, "x", true, true, ScalarStyle::Plain)
+                .with_leading_comment("first item")
+                .with_trailing_comment("x"),
+            Event::sequence_end(),
+            Event::scalar(None, None, "c", true, true, ScalarStyle::Plain),
+            Event::sequence_start(None, None, true, SequenceStyle::Flow),
+            Event::scalar(None, None, "y", true, true, ScalarStyle::Plain)
+                .with_trailing_comment("not written in flow context"),
+            Event::sequence_end(),
+            Event::mapping_end(),
+            Event::document_end(true),
+            Event::stream_end(),
+        ];
+        for event in events {
+            emitter.emit(event).unwrap();
+        }
+        drop(emitter);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "# header
+# about a
+# second line
+a: 1 # one
+b:
+# first item
+- x # x
+c: [y]
+"
+        );
+
+        let mut parser = Parser::from_str(&output);
+        let values: Vec<String> = parser
+            .by_ref()
+            .filter_map(|event| match event.unwrap().data {
+                EventData::Scalar { value, .. } => Some(value),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(values, ["a", "1", "b", "x", "c", "y"]);
+    }
+
+    #[test]
+    fn quote_preference() {
+        fn emit(preference: QuotePreference) -> String {
+            let mut output = Vec::new();
+            let mut emitter = Emitter::new();
+            emitter.set_quote_preference(preference);
+            emitter.set_output_string(&mut output);
+            emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+            emitter
+                .emit(Event::document_start(None, &[], true))
+                .unwrap();
+            emitter
+                .emit(Event::sequence_start(None, None, true, SequenceStyle::Flow))
+                .unwrap();
+            for (value, style) in [
+                ("", ScalarStyle::Any),
+                ("- x", ScalarStyle::Any),
+                ("plain", ScalarStyle::Any),
+                ("single", ScalarStyle::SingleQuoted),
+            ] {
+                let event = Event::scalar(None, None, value, true, true, style);
+                emitter.emit(event).unwrap();
+            }
+            emitter.emit(Event::sequence_end()).unwrap();
+            emitter.emit(Event::document_end(true)).unwrap();
+            emitter.emit(Event::stream_end()).unwrap();
+            drop(emitter);
+            String::from_utf8(output).unwrap()
+        }
+
+        assert_eq!(
+            emit(QuotePreference::Single),
+            "['', '- x', plain, 'single']\n"
+        );
+        assert_eq!(
+            emit(QuotePreference::Double),
+            "[\"\", \"- x\", plain, 'single']\n"
+        );
+    }
+
+    #[test]
+    fn into_vec() {
+        let mut emitter = Emitter::new();
+        for event in Parser::from_str("a: [b, c]\n") {
+            emitter.emit(event.unwrap()).unwrap();
+        }
+        assert_eq!(emitter.into_vec().unwrap(), b"a: [b, c]\n");
+
+        let mut emitter = Emitter::new();
+        emitter.set_encoding(Encoding::Utf16Le);
+        for event in Parser::from_str("a") {
+            emitter.emit(event.unwrap()).unwrap();
+        }
+        assert_eq!(emitter.into_vec().unwrap(), b"\xff\xfea\0\n\0");
+
+        assert!(Emitter::new().into_vec().unwrap().is_empty());
+
+        let mut emitter = Emitter::new();
+        for event in Parser::from_str("- a\n- b\n").take(5) {
+            emitter.emit(event.unwrap()).unwrap();
+        }
+        emitter.flush().unwrap();
+        assert_eq!(emitter.owned_output, b"- a\n- b");
+        assert_eq!(emitter.into_vec().unwrap(), b"- a\n- b");
+    }
+
+    #[test]
+    fn crlf_breaks() {
+        const INPUT: &str =
+            "literal: |\n  a\n\n  b\nfolded: >\n  c\n\n  d\nplain: e\n\n  f\nquoted: 'g\n\n  h'\n";
+        let output = reemit(INPUT, |emitter| emitter.set_break(Break::CrLn));
+        assert_eq!(output.replace("\r\n", "\n"), reemit(INPUT, |_| {}));
+        assert!(output
+            .split("\r\n")
+            .all(|line| !line.contains(['\r', '\n'])));
+
+        let mut emitter = Emitter::new();
+        emitter.set_break(Break::CrLn);
+        for event in [
+            Event::stream_start(Encoding::Utf8),
+            Event::document_start(None, &[], true),
+            Event::scalar(None, None, "x\n\ny\n", true, true, ScalarStyle::Literal),
+            Event::document_end(true),
+            Event::stream_end(),
+        ] {
+            emitter.emit(event).unwrap();
+        }
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(output, "|\r\n  x\r\n\r\n  y\r\n");
+    }
+
+    #[test]
+    fn sequence_under_key_indent() {
+        const INPUT: &str = "a:\n- 1\n- b:\n  - 2\n  c: 3\n- - 4\n";
+        assert_eq!(reemit(INPUT, |_| {}), INPUT);
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter.set_sequence_under_key_indent(true)),
+            "a:\n  - 1\n  - b:\n      - 2\n    c: 3\n  - - 4\n"
+        );
+    }
+
+    #[test]
+    fn simple_scalar_analysis() {
+        for value in [
+            "a",
+            "hello world",
+            "a-b.c d_e/f",
+            "x.y",
+            "(1+2)=3",
+            "C\\path",
+            "a  b",
+        ] {
+            assert!(is_simple_scalar(value), "{value:?}");
+            let analysis = analyze_scalar_chars(value, |ch| !is_ascii(ch));
+            assert!(!analysis.multiline);
+            assert!(analysis.flow_plain_allowed);
+            assert!(analysis.block_plain_allowed);
+            assert!(analysis.single_quoted_allowed);
+            assert!(analysis.block_allowed);
+        }
+        for value in [
+            "", "-", "-a", ".", "...", "---", " a", "a ", "a:b", "a #b", "a,b", "[a]", "a\nb",
+            "a\tb", "\u{e9}", "'a'", "a?", "*a", "a!",
+        ] {
+            assert!(!is_simple_scalar(value), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn scalar_needs_quoting() {
+        use super::scalar_needs_quoting as needs;
+        for value in ["a", "hello world", "caf\u{e9}", "-1", "true", "a#b"] {
+            for context in [
+                ScalarContext::BlockKey,
+                ScalarContext::BlockValue,
+                ScalarContext::FlowKey,
+                ScalarContext::FlowValue,
+            ] {
+                assert!(!needs(value, context), "{value:?} {context:?}");
+            }
+        }
+        for value in ["", "a,b", "[a]", "a?", "{"] {
+            assert!(needs(value, ScalarContext::FlowValue), "{value:?}");
+        }
+        for value in ["a,b", "a?", "a[b]", "a:b"] {
+            assert!(!needs(value, ScalarContext::BlockValue), "{value:?}");
+        }
+        for value in [
+            "- a",
+            "a: b",
+            "a #b",
+            " a",
+            "a ",
+            "'a'",
+            "*a",
+            "---",
+            "a\nb",
+            "a\tb\u{7}",
+        ] {
+            assert!(needs(value, ScalarContext::BlockValue), "{value:?}");
+        }
+        assert!(!needs("", ScalarContext::BlockValue));
+        assert!(needs("", ScalarContext::BlockKey));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reformat() {
+        const INPUT: &str = "%TAG !e! tag:example.com,2000:
+---
+a:   'quoted'
+b: [1,   2, {c: d}]
+e:
+    - |
+      literal
+    - !e!x   plain
+---
+f: >-
+    folded
+...
+";
+        const OUTPUT: &str = "%TAG !e! tag:example.com,2000:
+---
+a: 'quoted'
+b: [1, 2, {c: d}]
+e:
+- |
+  literal
+- !e!x plain
+---
+f: >-
+  folded
+...
+";
+        let mut output = Vec::new();
+        super::reformat(INPUT.as_bytes(), &mut output, Emitter::new()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), OUTPUT);
+
+        let mut config = Emitter::new();
+        config.set_indent(4);
+        let mut output = Vec::new();
+        super::reformat("a:\n  b: 1\n".as_bytes(), &mut output, config).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "a:\n    b: 1\n");
+
+        let err = super::reformat("a: [b\n".as_bytes(), Vec::new(), Emitter::new()).unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Parser);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn output_writer() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        #[derive(Clone, Default)]
+        struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+        impl std::io::Write for SharedBuffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        fn make_emitter(output: SharedBuffer) -> Emitter<'static> {
+            let mut emitter = Emitter::new();
+            emitter.set_output_writer(output);
+            emitter
+        }
+
+        let output = SharedBuffer::default();
+        let mut emitter = make_emitter(output.clone());
+        for event in [
+            Event::stream_start(Encoding::Utf8),
+            Event::document_start(None, &[], true),
+            Event::scalar(None, None, "a", true, true, ScalarStyle::Any),
+            Event::document_end(true),
+            Event::stream_end(),
+        ] {
+            emitter.emit(event).unwrap();
+        }
+        drop(emitter);
+        assert_eq!(&*output.0.borrow(), b"a\n");
+    }
+
+    #[test]
+    fn default_tag_directives() {
+        const INPUT: &str = "- !!int 1\n- !local a\n";
+        assert_eq!(reemit(INPUT, |_| {}), INPUT);
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter.set_default_tag_directives(false)),
+            "- !<tag:yaml.org,2002:int> 1\n- !<!local> a\n"
+        );
+    }
+
+    #[test]
+    fn register_tag_directive() {
+        const INPUT: &str =
+            "--- !<tag:example.com,2024:foo> a\n--- !<tag:example.com,2024:foo> b\n";
+        assert_eq!(
+            reemit(INPUT, |emitter| {
+                emitter
+                    .register_tag_directive("!e!", "tag:example.com,2024:")
+                    .unwrap();
+            }),
+            "%TAG !e! tag:example.com,2024:\n--- !e!foo a\n...\n%TAG !e! tag:example.com,2024:\n--- !e!foo b\n"
+        );
+        let mut emitter = Emitter::new();
+        assert!(emitter.register_tag_directive("e", "x").is_err());
+    }
+
+    #[test]
+    fn block_scalar_chomping() {
+        const VALUES: &[&str] = &[
+            "", "\n", "\n\n", "\n\n\n", "a", "a\n", "a\n\n", "a\n\n\n", " a", "  a\n", " a\n\n",
+            "\n a\n", "\na", "a\n b\n", "a\n\nb",
+        ];
+        for value in VALUES {
+            for style in [ScalarStyle::Literal, ScalarStyle::Folded] {
+                let scalar = || Event::scalar(None, None, value, true, true, style);
+                let key = || Event::scalar(None, None, "k", true, true, ScalarStyle::Any);
+                let node = |kind| match kind {
+                    0 => vec![scalar()],
+                    1 => vec![
+                        Event::sequence_start(None, None, true, SequenceStyle::Block),
+                        scalar(),
+                        scalar(),
+                        Event::sequence_end(),
+                    ],
+                    2 => vec![
+                        Event::mapping_start(None, None, true, MappingStyle::Block),
+                        key(),
+                        scalar(),
+                        key(),
+                        scalar(),
+                        Event::mapping_end(),
+                    ],
+                    _ => vec![
+                        Event::mapping_start(None, None, true, MappingStyle::Block),
+                        key(),
+                        Event::sequence_start(None, None, true, SequenceStyle::Block),
+                        scalar(),
+                        scalar(),
+                        Event::sequence_end(),
+                        Event::mapping_end(),
+                    ],
+                };
+                for (kind, indent) in (0..4).flat_map(|kind| [(kind, 2), (kind, 4)]) {
+                    let mut output = Vec::new();
+                    let mut emitter = Emitter::new();
+                    emitter.set_indent(indent);
+                    emitter.set_output_string(&mut output);
+                    emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+                    for _ in 0..2 {
+                        emitter
+                            .emit(Event::document_start(None, &[], true))
+                            .unwrap();
+                        for event in node(kind) {
+                            emitter.emit(event).unwrap();
+                        }
+                        emitter.emit(Event::document_end(true)).unwrap();
+                    }
+                    emitter.emit(Event::stream_end()).unwrap();
+                    drop(emitter);
+                    let output = String::from_utf8(output).unwrap();
+
+                    let mut parser = Parser::new();
+                    let mut read_in = output.as_bytes();
+                    parser.set_input_string(&mut read_in);
+                    let mut scalars = 0;
+                    for event in parser {
+                        let event = event.unwrap_or_else(|err| panic!("{output:?}: {err}"));
+                        if let EventData::Scalar {
+                            value: parsed,
+                            style: parsed_style,
+                            ..
+                        } = &event.data
+                        {
+                            if parsed == "k" && *parsed_style == ScalarStyle::Plain {
+                                continue;
+                            }
+                            assert_eq!(parsed, value, "{output:?}");
+                            scalars += 1;
+                        }
+                    }
+                    let expected = node(kind)
+                        .iter()
+                        .filter(|event| matches!(&event.data, EventData::Scalar { style: s, .. } if *s == style))
+                        .count();
+                    assert_eq!(scalars, 2 * expected, "{output:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn forced_style() {
+        let emit = |value: &str, style: ScalarStyle| {
+            let mut emitter = Emitter::new();
+            emitter.emit(Event::stream_start(Encoding::Utf8))?;
+            emitter.emit(Event::document_start(None, &[], true))?;
+            emitter.emit(Event::sequence_start(
+                None,
+                None,
+                true,
+                SequenceStyle::Block,
+            ))?;
+            emitter.emit(
+                Event::scalar(None, Some(STR_TAG), value, false, true, ScalarStyle::Any)
+                    .with_forced_style(style),
+            )?;
+            emitter.emit(Event::sequence_end())?;
+            emitter.emit(Event::document_end(true))?;
+            emitter.emit(Event::stream_end())?;
+            Ok::<_, Error>(String::from_utf8(emitter.into_vec()?).unwrap())
+        };
+        assert_eq!(emit("true", ScalarStyle::Any).unwrap(), "- 'true'\n");
+        assert_eq!(emit("true", ScalarStyle::Plain).unwrap(), "- true\n");
+        assert!(emit("a: b", ScalarStyle::Plain).is_err());
+        assert_eq!(emit("a", ScalarStyle::Literal).unwrap(), "- |-\n  a\n");
+        assert!(emit(" a", ScalarStyle::Plain).is_err());
+        assert_eq!(
+            emit("it's", ScalarStyle::SingleQuoted).unwrap(),
+            "- 'it''s'\n"
+        );
+        assert!(emit("a\tb", ScalarStyle::SingleQuoted).is_err());
+        assert!(emit("a \n", ScalarStyle::Literal).is_err());
+        assert_eq!(
+            Event::sequence_end().with_forced_style(ScalarStyle::Plain),
+            Event::sequence_end()
+        );
+    }
+
+    #[test]
+    fn error_marks() {
+        let emit = |node: Event| {
+            let mut emitter = Emitter::new();
+            emitter.emit(Event::stream_start(Encoding::Utf8))?;
+            emitter.emit(Event::document_start(None, &[], true))?;
+            emitter.emit(node)?;
+            emitter.emit(Event::document_end(true))
+        };
+        // An event built by hand has no position.
+        let event = Event::scalar(None, Some(""), "a", false, false, ScalarStyle::Any);
+        let err = emit(event).unwrap_err();
+        assert_eq!(err.problem(), "tag value must not be empty");
+        assert_eq!(err.problem_mark(), None);
+
+        let mut event = Parser::from_str("--- !t a\n").nth(2).unwrap().unwrap();
+        if let EventData::Scalar { tag, .. } = &mut event.data {
+            *tag = Some(String::new());
+        }
+        let err = emit(event).unwrap_err();
+        assert_eq!(err.problem_mark().map(|mark| mark.column), Some(4));
+    }
+
+    #[test]
+    fn invalid_anchors() {
+        let emit = |node: Event| {
+            let mut emitter = Emitter::new();
+            emitter.emit(Event::stream_start(Encoding::Utf8))?;
+            emitter.emit(Event::document_start(None, &[], true))?;
+            emitter.emit(Event::sequence_start(
+                None,
+                None,
+                true,
+                SequenceStyle::Block,
+            ))?;
+            emitter.emit(node)?;
+            emitter.emit(Event::sequence_end())
+        };
+        let err = emit(Event::alias("a b")).unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "alias value must contain alphanumerical characters only"
+        );
+        assert!(err.to_string().ends_with(" (\"a b\")"));
+        let err = emit(Event::alias("")).unwrap_err();
+        assert_eq!(err.problem(), "alias value must not be empty");
+        let err = emit(Event::scalar(
+            Some("x\n"),
+            None,
+            "v",
+            true,
+            true,
+            ScalarStyle::Any,
+        ))
+        .unwrap_err();
+        assert!(err.to_string().ends_with(" (\"x\\n\")"));
+        let err = emit(Event::mapping_start(
+            Some("\u{e9}"),
+            None,
+            true,
+            MappingStyle::Block,
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "anchor value must contain alphanumerical characters only"
+        );
+    }
+
+    #[test]
+    fn auto_fold_threshold() {
+        let sentence = "The quick brown fox jumps over the lazy dog. ".repeat(7);
+        let sentence = sentence.trim_end();
+        assert!(sentence.len() > 300);
+        let input = format!("description: {sentence}\nitems:\n- {sentence}\nshort: a b c\n");
+        let output = reemit(&input, |emitter| emitter.set_auto_fold_threshold(Some(80)));
+        assert!(output.starts_with("description: >-\n  The quick brown fox"));
+        assert!(output.contains("items:\n- >-\n  The quick"));
+        assert!(output.ends_with("short: a b c\n"));
+        assert!(output.lines().all(|line| line.len() <= 82));
+
+        let mut parser = Parser::from_str(&output);
+        let mut document = Document::load(&mut parser).unwrap();
+        let root = document.get_root_node().unwrap();
+        let NodeData::Mapping { pairs, .. } = &root.data else {
+            panic!("expected a mapping")
+        };
+        let pairs = pairs.clone();
+        let description = document.get_node(pairs[0].value).unwrap();
+        assert_eq!(description.as_str(), Some(sentence));
+        let NodeData::Sequence { items, .. } = &document.get_node(pairs[1].value).unwrap().data
+        else {
+            panic!("expected a sequence")
+        };
+        assert_eq!(
+            document.get_node(items[0]).unwrap().as_str(),
+            Some(sentence)
+        );
+
+        let number = "1".repeat(100);
+        let input = format!("[{sentence}]\n---\n{number}\n");
+        assert_eq!(
+            reemit(&input, |emitter| emitter.set_auto_fold_threshold(Some(80))),
+            reemit(&input, |_| {})
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reset_output() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Shared(Rc<RefCell<Vec<u8>>>);
+
+        impl std::io::Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let emit = |emitter: &mut Emitter, encoding: Encoding| {
+            let output = Rc::new(RefCell::new(Vec::new()));
+            emitter.reset_output();
+            emitter.set_encoding(encoding);
+            emitter.set_output_writer(Shared(output.clone()));
+            emitter.open().unwrap();
+            let mut doc = Document::new(None, &[], true, true);
+            let root = doc.add_mapping(None, MappingStyle::Block);
+            let key = doc.add_scalar(None, "a", ScalarStyle::Plain);
+            let value = doc.add_sequence(None, SequenceStyle::Block);
+            let item = doc.add_scalar(None, "b", ScalarStyle::Plain);
+            doc.append_sequence_item(value, item);
+            doc.yaml_document_append_mapping_pair(root, key, value);
+            doc.dump(emitter).unwrap();
+            emitter.close().unwrap();
+            output.take()
+        };
+        let mut emitter = Emitter::new();
+        emitter.set_indent(4);
+        emitter.set_sequence_under_key_indent(true);
+        assert_eq!(emit(&mut emitter, Encoding::Utf8), b"a:\n    - b\n");
+        let utf16: Vec<u8> = "\u{feff}a:\n    - b\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(emit(&mut emitter, Encoding::Utf16Le), utf16);
+        assert_eq!(emit(&mut emitter, Encoding::Utf8), b"a:\n    - b\n");
+    }
+
+    #[test]
+    fn width_for() {
+        const INPUT: &str = "- k: aaa bbb ccc ddd eee fff ggg\n- aaa bbb ccc ddd eee fff ggg\n";
+        const KEYS: &str = "? [aaa, bbb, ccc, ddd, eee]\n: [aaa, bbb, ccc, ddd, eee]\n";
+        assert_eq!(
+            reemit(KEYS, |emitter| {
+                emitter.set_flow_wrap(true);
+                emitter.set_width_for(WidthContext::Key, 20);
+            }),
+            "? [aaa, bbb, ccc, ddd,\n  eee]\n: [aaa, bbb, ccc, ddd, eee]\n"
+        );
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter
+                .set_width_for(WidthContext::Value, 20)),
+            "- k: aaa bbb ccc ddd eee\n    fff ggg\n- aaa bbb ccc ddd eee fff ggg\n"
+        );
+        assert_eq!(
+            reemit(INPUT, |emitter| {
+                emitter.set_width_for(WidthContext::Any, 20);
+                emitter.set_width_for(WidthContext::Value, -1);
+            }),
+            "- k: aaa bbb ccc ddd eee fff ggg\n- aaa bbb ccc ddd eee\n  fff ggg\n"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn long_scalars_are_flushed_in_chunks() {
+        struct MaxWrite(usize);
+        impl std::io::Write for MaxWrite {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0 = self.0.max(buf.len());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let value = "\u{e9}".repeat(512 * 1024);
+        let anchor = "a".repeat(256 * 1024);
+        let mut output = MaxWrite(0);
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        for event in [
+            Event::stream_start(Encoding::Utf8),
+            Event::document_start(None, &[], true),
+            Event::sequence_start(None, None, true, SequenceStyle::Block),
+            Event::scalar(None, None, &value, true, true, ScalarStyle::SingleQuoted),
+            Event::scalar(Some(&anchor), None, "b", true, true, ScalarStyle::Plain),
+            Event::sequence_end(),
+            Event::document_end(true),
+            Event::stream_end(),
+        ] {
+            emitter.emit(event).unwrap();
+        }
+        assert!(emitter.buffer.capacity() <= OUTPUT_BUFFER_SIZE);
+        drop(emitter);
+        assert!(output.0 > 0 && output.0 <= OUTPUT_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn escape_policy() {
+        const INPUT: &str = "- caf\u{e9}\n- \"a\u{202e}b\\u200Bc\"\n";
+        let escape_invisible = |emitter: &mut Emitter| {
+            emitter.set_escape_policy(|ch| matches!(ch, '\u{200b}' | '\u{202a}'..='\u{202e}'));
+        };
+        assert_eq!(
+            reemit(INPUT, escape_invisible),
+            "- caf\u{e9}\n- \"a\\u202Eb\\u200Bc\"\n"
+        );
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter.set_unicode(true)),
+            "- caf\u{e9}\n- \"a\u{202e}b\u{200b}c\"\n"
+        );
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter.set_escape_policy(|ch| ch == 'f')),
+            "- ! \"ca\\x66\u{e9}\"\n- \"a\u{202e}b\u{200b}c\"\n"
+        );
+    }
+
+    #[test]
+    fn sequence_writer() {
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output_string(&mut output);
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        emitter
+            .emit(Event::document_start(None, &[], true))
+            .unwrap();
+        let mut outer = emitter.begin_sequence(None, SequenceStyle::Block).unwrap();
+        for i in 0..2 {
+            let value = i.to_string();
+            outer
+                .push([Event::scalar(
+                    None,
+                    None,
+                    &value,
+                    true,
+                    true,
+                    ScalarStyle::Any,
+                )])
+                .unwrap();
+        }
+        let mut inner = outer.begin_sequence(None, SequenceStyle::Flow).unwrap();
+        inner
+            .push([Event::scalar(None, None, "a", true, true, ScalarStyle::Any)])
+            .unwrap();
+        inner
+            .push([
+                Event::mapping_start(None, None, true, MappingStyle::Any),
+                Event::scalar(None, None, "b", true, true, ScalarStyle::Any),
+                Event::scalar(None, None, "c", true, true, ScalarStyle::Any),
+                Event::mapping_end(),
+            ])
+            .unwrap();
+        inner.finish().unwrap();
+        outer.finish().unwrap();
+        emitter.emit(Event::document_end(true)).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        drop(emitter);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "- 0\n- 1\n- [a, {b: c}]\n"
+        );
+    }
+
+    #[test]
+    fn empty_document() {
+        let emit = |events: Vec<Event>| {
+            let mut emitter = Emitter::new();
+            for event in events {
+                emitter.emit(event).unwrap();
+            }
+            String::from_utf8(emitter.into_vec().unwrap()).unwrap()
+        };
+        let output = emit(vec![
+            Event::stream_start(Encoding::Utf8),
+            Event::document_start(None, &[], true),
+            Event::document_end(true),
+            Event::document_start(None, &[], true),
+            Event::scalar(None, None, "a", true, true, ScalarStyle::Any),
+            Event::document_end(true),
+            Event::stream_end(),
+        ]);
+        assert_eq!(output, "---\n--- a\n");
+
+        let mut parser = Parser::from_str(&output);
+        assert_eq!(parser.count_documents().unwrap(), 2);
+    }
+
+    #[test]
+    fn value_on_new_line_threshold() {
+        const INPUT: &str = "a: short\nb:\n  url: https://example.com/a/long/path\n  list:\n  - https://example.com/item\n";
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter.set_value_on_new_line_threshold(10)),
+            "a: short\nb:\n  url:\n    https://example.com/a/long/path\n  list:\n  - https://example.com/item\n"
+        );
+        assert_eq!(reemit(INPUT, |_| {}), INPUT);
+    }
+
+    #[test]
+    fn compact_flow_mapping() {
+        const INPUT: &str = "[a: 1, {b: 2, c: 3}, &x {d: 4}, {e: [5]}, f]\n";
+        assert_eq!(
+            reemit(INPUT, |emitter| emitter.set_compact_flow_mapping(true)),
+            "[a: 1, {b: 2, c: 3}, &x {d: 4}, {e: [5]}, f]\n"
+        );
+        assert_eq!(
+            reemit(INPUT, |_| {}),
+            "[{a: 1}, {b: 2, c: 3}, &x {d: 4}, {e: [5]}, f]\n"
+        );
+        assert_eq!(
+            reemit("{a: [b: c]}\n", |emitter| emitter
+                .set_compact_flow_mapping(true)),
+            "{a: [b: c]}\n"
+        );
+    }
+
+    #[test]
+    fn json_compatible() {
+        const INPUT: &str = "%YAML 1.2
+---
+# comment
+a: 1
+b: [true, ~, 0x10, 1.5e3, .inf, '2', !!str 3]
+? c d
+: - \"line\\nbreak \\\"quoted\\\" \\t\"
+  - {x: null, 4: y}
+  - []
+  - \"\\u00e9\\U0001F600\\x07\"
+...
+";
+        let output = reemit(INPUT, |emitter| emitter.set_json_compatible(true));
+        assert_eq!(
+            output,
+            r#"{"a": 1, "b": [true, null, 16, 1.5e3, ".inf", "2", "3"], "c d": ["line\nbreak \"quoted\" \t",
+    {"x": null, "4": "y"}, [], "\u00E9😀\u0007"]}
+"#
+        );
+        let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(json["b"][2], 16);
+        assert_eq!(json["c d"][3], "\u{e9}\u{1f600}\x07");
+
+        let events = |input: &str| {
+            let mut parser = Parser::new();
+            let mut read_in = input.as_bytes();
+            parser.set_input_string(&mut read_in);
+            parser
+                .map(|event| match event.unwrap().data {
+                    EventData::Scalar { value, .. } => value,
+                    _ => String::new(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(events(&output).len(), events(INPUT).len());
+
+        // Loaded documents are written like the events they were loaded from.
+        let doc = Document::load(&mut Parser::from_str(
+            "[~, null, !!str ~, '~', 5, !!str 5, !!str true, !!str x, 1e400, -1e-400, !!int '7']",
+        ))
+        .unwrap();
+        let mut emitter = Emitter::new();
+        emitter.set_json_compatible(true);
+        doc.dump(&mut emitter).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        let output = String::from_utf8(emitter.into_vec().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            "[null, null, \"~\", \"~\", 5, \"5\", \"true\", \"x\", \"1e400\", -1e-400, 7]\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(json[8], "1e400");
+
+        for input in ["- &a x\n- *a\n", "? [a]\n: b\n"] {
+            let mut parser = Parser::from_str(input);
+            let mut emitter = Emitter::new();
+            emitter.set_json_compatible(true);
+            let result = parser
+                .by_ref()
+                .try_for_each(|event| emitter.emit(event.unwrap()));
+            assert!(result.is_err());
+        }
+
+        // Keys are only written if they are read back as implicit keys.
+        let emit_key = |key: &str| {
+            let mut emitter = Emitter::new();
+            emitter.set_json_compatible(true);
+            for event in [
+                Event::stream_start(Encoding::Utf8),
+                Event::document_start(None, &[], true),
+                Event::mapping_start(None, None, true, MappingStyle::Any),
+                Event::scalar(None, None, key, true, true, ScalarStyle::Any),
+                Event::scalar(None, None, "v", true, true, ScalarStyle::Any),
+                Event::mapping_end(),
+                Event::document_end(true),
+                Event::stream_end(),
+            ] {
+                emitter.emit(event)?;
+            }
+            emitter.into_vec()
+        };
+        let output = emit_key(&"k".repeat(1022)).unwrap();
+        let doc = Document::load(&mut Parser::from_str(
+            core::str::from_utf8(&output).unwrap(),
+        ));
+        assert_eq!(doc.unwrap().nodes.len(), 3);
+        for key in ["k".repeat(1023), "\n".repeat(600)] {
+            let err = emit_key(&key).unwrap_err();
+            assert_eq!(
+                err.problem(),
+                "mapping key is too long for JSON-compatible mode"
+            );
+        }
     }
 }
@@ -1,10 +1,20 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+};
+use core::fmt::Write as _;
+
+use crate::event::{Event, EventSource};
+use crate::macros::is_break;
+
 pub type Result<T, E = Error> = core::result::Result<T, E>;
 
 /// The pointer position.
 #[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 #[non_exhaustive]
 pub struct Mark {
-    /// The position index.
+    /// The position index, as a byte offset into the input decoded as UTF-8,
+    /// not counting any byte order mark.
     pub index: u64,
     /// The position line.
     pub line: u64,
@@ -12,8 +22,24 @@ pub struct Mark {
     pub column: u64,
 }
 
-impl std::fmt::Display for Mark {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Mark {
+    /// Translate a mark in an input that starts at `origin` in a larger
+    /// document. The scanner starts counting the index and line from `origin`,
+    /// so only the marks on the first line are shifted, by its column.
+    pub(crate) fn relative_to(self, origin: Mark) -> Mark {
+        Mark {
+            column: if self.line == origin.line {
+                origin.column + self.column
+            } else {
+                self.column
+            },
+            ..self
+        }
+    }
+}
+
+impl core::fmt::Display for Mark {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "line {} column {}", self.line, self.column)
     }
 }
@@ -37,7 +63,13 @@ enum ErrorImpl {
     Scanner(Problem),
     Parser(Problem),
     Composer(Problem),
-    Emitter(&'static str),
+    Emitter {
+        problem: &'static str,
+        problem_mark: Option<Mark>,
+        node: Option<i32>,
+        value: Option<String>,
+    },
+    #[cfg(feature = "std")]
     Io(std::io::Error),
 }
 
@@ -54,6 +86,7 @@ pub enum ErrorKind {
 #[derive(Debug)]
 pub struct Error(Box<ErrorImpl>);
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
         Self(Box::new(ErrorImpl::Io(value)))
@@ -112,7 +145,66 @@ impl Error {
     }
 
     pub(crate) fn emitter(problem: &'static str) -> Self {
-        Self(Box::new(ErrorImpl::Emitter(problem)))
+        Self(Box::new(ErrorImpl::Emitter {
+            problem,
+            problem_mark: None,
+            node: None,
+            value: None,
+        }))
+    }
+
+    /// An emitter error caused by `value`, which is included in the message.
+    pub(crate) fn emitter_value(problem: &'static str, value: &str) -> Self {
+        Self(Box::new(ErrorImpl::Emitter {
+            problem,
+            problem_mark: None,
+            node: None,
+            value: Some(value.to_string()),
+        }))
+    }
+
+    /// Locate an emitter error that was not located yet at the event being
+    /// emitted: at its start mark if it was parsed, or at the node it was
+    /// produced from.
+    pub(crate) fn located_at(mut self, event: &Event) -> Self {
+        if let ErrorImpl::Emitter {
+            problem_mark: problem_mark @ None,
+            node: node @ None,
+            ..
+        } = &mut *self.0
+        {
+            match event.source {
+                EventSource::Built => {}
+                EventSource::Input => *problem_mark = Some(event.start_mark),
+                EventSource::Node(id) => *node = Some(id),
+            }
+        }
+        self
+    }
+
+    /// Translate the position of the error in an input that starts at
+    /// `origin` in a larger document, see [`Mark::relative_to()`].
+    pub(crate) fn relative_to(mut self, origin: Mark) -> Self {
+        match &mut *self.0 {
+            ErrorImpl::Reader { offset, .. } => *offset += origin.index as usize,
+            ErrorImpl::Scanner(p) | ErrorImpl::Parser(p) | ErrorImpl::Composer(p) => {
+                p.problem_mark = p.problem_mark.relative_to(origin);
+                p.context_mark = p.context_mark.relative_to(origin);
+            }
+            ErrorImpl::Emitter { .. } => {}
+            #[cfg(feature = "std")]
+            ErrorImpl::Io(_) => {}
+        }
+        self
+    }
+
+    /// Is this the error raised when the input fed to a parser runs out, see
+    /// [`Parser::try_parse()`](crate::Parser::try_parse)?
+    pub(crate) fn is_incomplete(&self) -> bool {
+        matches!(
+            &*self.0,
+            ErrorImpl::Reader { problem, .. } if *problem == crate::reader::INCOMPLETE_INPUT
+        )
     }
 
     pub fn kind(&self) -> ErrorKind {
@@ -121,23 +213,41 @@ impl Error {
             ErrorImpl::Scanner(_) => ErrorKind::Scanner,
             ErrorImpl::Parser(_) => ErrorKind::Parser,
             ErrorImpl::Composer(_) => ErrorKind::Composer,
-            ErrorImpl::Emitter(_) => ErrorKind::Emitter,
+            ErrorImpl::Emitter { .. } => ErrorKind::Emitter,
+            #[cfg(feature = "std")]
             ErrorImpl::Io(_) => ErrorKind::Io,
         }
     }
 
     pub fn problem_mark(&self) -> Option<Mark> {
         match &*self.0 {
-            ErrorImpl::Reader { .. } | ErrorImpl::Emitter(_) | ErrorImpl::Io(_) => None,
+            ErrorImpl::Reader { .. } => None,
+            #[cfg(feature = "std")]
+            ErrorImpl::Io(_) => None,
+            ErrorImpl::Emitter { problem_mark, .. } => *problem_mark,
             ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
                 Some(p.problem_mark)
             }
         }
     }
 
+    /// Get the id of the document node an emitter error was raised for, when
+    /// the event was produced by
+    /// [`Document::into_events()`](crate::Document::into_events), such as in
+    /// [`Document::dump()`](crate::Document::dump) and
+    /// [`Document::validate()`](crate::Document::validate).
+    pub fn node_id(&self) -> Option<i32> {
+        match &*self.0 {
+            ErrorImpl::Emitter { node, .. } => *node,
+            _ => None,
+        }
+    }
+
     pub fn context_mark(&self) -> Option<Mark> {
         match &*self.0 {
-            ErrorImpl::Reader { .. } | ErrorImpl::Emitter(..) | ErrorImpl::Io(_) => None,
+            ErrorImpl::Reader { .. } | ErrorImpl::Emitter { .. } => None,
+            #[cfg(feature = "std")]
+            ErrorImpl::Io(_) => None,
             ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
                 if p.context.is_empty() {
                     None
@@ -148,19 +258,62 @@ impl Error {
         }
     }
 
+    /// Format the error followed by the line of `source` it occurred on, with
+    /// a `^` under the position of the problem.
+    ///
+    /// `source` must be the input the error was reported for. Tabs are
+    /// expanded to four spaces to keep the caret aligned. If the error has no
+    /// position, or the position is not within `source`, only the error is
+    /// formatted.
+    pub fn render_snippet(&self, source: &str) -> String {
+        let mut snippet = self.to_string();
+        let Some(mark) = self.problem_mark() else {
+            return snippet;
+        };
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let Some(index) = usize::try_from(mark.index)
+            .ok()
+            .filter(|index| source.is_char_boundary(*index))
+        else {
+            return snippet;
+        };
+
+        let line_start = source[..index].rfind(is_break).map_or(0, |start| {
+            start + source[start..].chars().next().unwrap().len_utf8()
+        });
+        let line_end = source[index..]
+            .find(is_break)
+            .map_or(source.len(), |end| index + end);
+        let expand_tabs = |text: &str| text.replace('\t', "    ");
+        let line = expand_tabs(&source[line_start..line_end]);
+        let caret_column = expand_tabs(&source[line_start..index]).chars().count();
+
+        let line_number = (mark.line + 1).to_string();
+        let gutter = " ".repeat(line_number.len());
+        let _ = write!(
+            snippet,
+            "\n{gutter} |\n{line_number} | {line}\n{gutter} | {:caret_column$}^",
+            ""
+        );
+        snippet
+    }
+
     pub fn problem(&self) -> &'static str {
         match &*self.0 {
-            ErrorImpl::Reader { problem, .. } | ErrorImpl::Emitter(problem) => problem,
+            ErrorImpl::Reader { problem, .. } | ErrorImpl::Emitter { problem, .. } => problem,
             ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
                 p.problem
             }
+            #[cfg(feature = "std")]
             ErrorImpl::Io(_) => "I/O error",
         }
     }
 
     pub fn context(&self) -> Option<&'static str> {
         match &*self.0 {
-            ErrorImpl::Reader { .. } | ErrorImpl::Emitter(..) | ErrorImpl::Io(_) => None,
+            ErrorImpl::Reader { .. } | ErrorImpl::Emitter { .. } => None,
+            #[cfg(feature = "std")]
+            ErrorImpl::Io(_) => None,
             ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
                 if p.context.is_empty() {
                     None
@@ -172,6 +325,7 @@ impl Error {
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         if let ErrorImpl::Io(ref err) = &*self.0 {
@@ -182,6 +336,7 @@ impl std::error::Error for Error {
     }
 }
 
+#[cfg(feature = "std")]
 impl TryFrom<Error> for std::io::Error {
     type Error = Error;
 
@@ -199,7 +354,7 @@ impl TryFrom<Error> for std::io::Error {
 }
 
 impl core::fmt::Display for ErrorKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_str(match self {
             ErrorKind::Reader => "Reader",
             ErrorKind::Scanner => "Scanner",
@@ -212,7 +367,7 @@ impl core::fmt::Display for ErrorKind {
 }
 
 impl core::fmt::Display for Problem {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let Self {
             problem,
             problem_mark,
@@ -229,7 +384,7 @@ impl core::fmt::Display for Problem {
 }
 
 impl core::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{} error: ", self.kind())?;
         match *self.0 {
             ErrorImpl::Reader {
@@ -240,7 +395,13 @@ impl core::fmt::Display for Error {
             ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
                 write!(f, "{p}")
             }
-            ErrorImpl::Emitter(problem) => write!(f, "{problem}"),
+            ErrorImpl::Emitter {
+                problem,
+                value: Some(ref value),
+                ..
+            } => write!(f, "{problem} ({value:?})"),
+            ErrorImpl::Emitter { problem, .. } => write!(f, "{problem}"),
+            #[cfg(feature = "std")]
             ErrorImpl::Io(ref err) => write!(f, "{err}"),
         }
     }
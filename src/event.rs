@@ -1,5 +1,8 @@
+use alloc::{string::String, vec::Vec};
+
 use crate::{
-    Encoding, MappingStyle, Mark, ScalarStyle, SequenceStyle, TagDirective, VersionDirective,
+    Emitter, Encoding, Error, MappingStyle, Mark, Result, ScalarStyle, SequenceStyle, TagDirective,
+    VersionDirective,
 };
 
 /// The event structure.
@@ -12,6 +15,41 @@ pub struct Event {
     pub start_mark: Mark,
     /// The end of the event.
     pub end_mark: Mark,
+    /// The comments attached to the event.
+    pub comments: Comments,
+    /// Where the event comes from, to locate the errors the emitter raises
+    /// for it.
+    pub(crate) source: EventSource,
+}
+
+/// Where an event comes from.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub(crate) enum EventSource {
+    /// The event was built by hand, so its marks are meaningless.
+    #[default]
+    Built,
+    /// The event was parsed, and its start mark is its position in the input.
+    Input,
+    /// The event was produced from the node with this id by
+    /// [`Document::into_events()`](crate::Document::into_events).
+    Node(i32),
+}
+
+/// Comments attached to an event.
+///
+/// The emitter writes leading comments on their own lines before block
+/// mapping keys, block sequence items, the root node of a document, and
+/// DOCUMENT-START events, and writes a trailing comment on the same line after
+/// a quoted or plain scalar in block context. Comments in other positions are
+/// not written, since they could change the meaning of the output.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Comments {
+    /// The comment lines before the event, without the `#` indicator.
+    pub leading: Vec<String>,
+    /// The comment after the event on the same line, without the `#`
+    /// indicator.
+    pub trailing: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -54,6 +92,15 @@ pub enum EventData {
         quoted_implicit: bool,
         /// The scalar style.
         style: ScalarStyle,
+        /// The style the scalar is written in, regardless of the emitter's
+        /// choice, see [`Event::with_forced_style()`].
+        force_style: Option<ScalarStyle>,
+        /// The beginning of the anchor, if any.
+        anchor_mark: Option<Mark>,
+        /// The beginning of the tag, if any.
+        tag_mark: Option<Mark>,
+        /// The beginning of the value, after the anchor and the tag.
+        value_mark: Option<Mark>,
     },
     /// The sequence parameters (for YAML_SEQUENCE_START_EVENT).
     SequenceStart {
@@ -65,6 +112,14 @@ pub enum EventData {
         implicit: bool,
         /// The sequence style.
         style: SequenceStyle,
+        /// The beginning of the anchor, if any.
+        anchor_mark: Option<Mark>,
+        /// The beginning of the tag, if any.
+        tag_mark: Option<Mark>,
+        /// The beginning of the content, after the anchor and the tag.
+        ///
+        /// For a block sequence, this is the beginning of its first entry.
+        value_mark: Option<Mark>,
     },
     SequenceEnd,
     /// The mapping parameters (for YAML_MAPPING_START_EVENT).
@@ -77,6 +132,14 @@ pub enum EventData {
         implicit: bool,
         /// The mapping style.
         style: MappingStyle,
+        /// The beginning of the anchor, if any.
+        anchor_mark: Option<Mark>,
+        /// The beginning of the tag, if any.
+        tag_mark: Option<Mark>,
+        /// The beginning of the content, after the anchor and the tag.
+        ///
+        /// For a block mapping, this is the beginning of its first entry.
+        value_mark: Option<Mark>,
     },
     MappingEnd,
 }
@@ -88,7 +151,56 @@ impl Event {
             data,
             start_mark: Mark::default(),
             end_mark: Mark::default(),
+            comments: Comments::default(),
+            source: EventSource::Built,
+        }
+    }
+
+    /// Make an event parsed from the input between two marks.
+    pub(crate) fn parsed(data: EventData, start_mark: Mark, end_mark: Mark) -> Self {
+        Self {
+            data,
+            start_mark,
+            end_mark,
+            comments: Comments::default(),
+            source: EventSource::Input,
+        }
+    }
+
+    /// Attach a comment line to be written before the event.
+    #[must_use]
+    pub fn with_leading_comment(mut self, comment: &str) -> Self {
+        self.comments.leading.push(String::from(comment));
+        self
+    }
+
+    /// Attach a comment to be written after the event on the same line.
+    #[must_use]
+    pub fn with_trailing_comment(mut self, comment: &str) -> Self {
+        self.comments.trailing = Some(String::from(comment));
+        self
+    }
+
+    /// Write a SCALAR event in `style`, bypassing the emitter's choice of
+    /// style.
+    ///
+    /// The emitter normally quotes scalars that it cannot safely write in the
+    /// requested style, for example plain scalars that would be read back as
+    /// another type, such as `true` or `1.0`, or that are not implicitly typed.
+    /// A forced style skips these checks, and only fails with an emitter error
+    /// if the value cannot be written in that style at all, such as a plain
+    /// scalar with leading spaces. Forcing the plain style on such ambiguous
+    /// values changes how they are read back, so it is only safe when the
+    /// reader resolves them the intended way.
+    ///
+    /// [`ScalarStyle::Any`] restores the emitter's choice. Other events are
+    /// returned unchanged.
+    #[must_use]
+    pub fn with_forced_style(mut self, style: ScalarStyle) -> Self {
+        if let EventData::Scalar { force_style, .. } = &mut self.data {
+            *force_style = Some(style);
         }
+        self
     }
 
     /// Create the STREAM-START event.
@@ -134,6 +246,13 @@ impl Event {
         })
     }
 
+    /// Create an ALIAS event, failing with an emitter error if the anchor
+    /// cannot be written, rather than when the event is emitted.
+    pub fn try_alias(anchor: &str) -> Result<Self> {
+        Emitter::analyze_anchor(anchor, true)?;
+        Ok(Self::alias(anchor))
+    }
+
     /// Create a SCALAR event.
     ///
     /// The `style` argument may be ignored by the emitter.
@@ -166,9 +285,40 @@ impl Event {
             plain_implicit,
             quoted_implicit,
             style,
+            force_style: None,
+            anchor_mark: None,
+            tag_mark: None,
+            value_mark: None,
         })
     }
 
+    /// Create a SCALAR event, failing with an emitter error if the anchor or
+    /// the tag cannot be written, or if neither the tag nor an implicit flag is
+    /// set, rather than when the event is emitted.
+    pub fn try_scalar(
+        anchor: Option<&str>,
+        tag: Option<&str>,
+        value: &str,
+        plain_implicit: bool,
+        quoted_implicit: bool,
+        style: ScalarStyle,
+    ) -> Result<Self> {
+        check_properties(anchor, tag)?;
+        if tag.is_none() && !plain_implicit && !quoted_implicit {
+            return Err(Error::emitter(
+                "neither tag nor implicit flags are specified",
+            ));
+        }
+        Ok(Self::scalar(
+            anchor,
+            tag,
+            value,
+            plain_implicit,
+            quoted_implicit,
+            style,
+        ))
+    }
+
     /// Create a SEQUENCE-START event.
     ///
     /// The `style` argument may be ignored by the emitter.
@@ -195,9 +345,25 @@ impl Event {
             tag: tag_copy,
             implicit,
             style,
+            anchor_mark: None,
+            tag_mark: None,
+            value_mark: None,
         })
     }
 
+    /// Create a SEQUENCE-START event, failing with an emitter error if the
+    /// anchor or the tag cannot be written, rather than when the event is
+    /// emitted.
+    pub fn try_sequence_start(
+        anchor: Option<&str>,
+        tag: Option<&str>,
+        implicit: bool,
+        style: SequenceStyle,
+    ) -> Result<Self> {
+        check_properties(anchor, tag)?;
+        Ok(Self::sequence_start(anchor, tag, implicit, style))
+    }
+
     /// Create a SEQUENCE-END event.
     pub fn sequence_end() -> Self {
         Self::new(EventData::SequenceEnd)
@@ -230,11 +396,157 @@ impl Event {
             tag: tag_copy,
             implicit,
             style,
+            anchor_mark: None,
+            tag_mark: None,
+            value_mark: None,
         })
     }
 
+    /// Create a MAPPING-START event, failing with an emitter error if the
+    /// anchor or the tag cannot be written, rather than when the event is
+    /// emitted.
+    pub fn try_mapping_start(
+        anchor: Option<&str>,
+        tag: Option<&str>,
+        implicit: bool,
+        style: MappingStyle,
+    ) -> Result<Self> {
+        check_properties(anchor, tag)?;
+        Ok(Self::mapping_start(anchor, tag, implicit, style))
+    }
+
     /// Create a MAPPING-END event.
     pub fn mapping_end() -> Self {
         Self::new(EventData::MappingEnd)
     }
 }
+
+/// Check the anchor and tag of a node event like the emitter does, see
+/// [`Event::try_scalar()`].
+fn check_properties(anchor: Option<&str>, tag: Option<&str>) -> Result<()> {
+    if let Some(anchor) = anchor {
+        Emitter::analyze_anchor(anchor, false)?;
+    }
+    if tag == Some("") {
+        return Err(Error::emitter("tag value must not be empty"));
+    }
+    Ok(())
+}
+
+/// Formats the event on a single line for debugging, for example
+/// `SCALAR value="foo" tag=!!str style=plain @1:5`.
+///
+/// Tags in the `tag:yaml.org,2002:` namespace are shortened to `!!`, and the
+/// start mark is written as a 1-based line and column.
+impl core::fmt::Display for Event {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.data {
+            EventData::StreamStart { encoding } => {
+                f.write_str("STREAM-START")?;
+                let encoding = match encoding {
+                    Encoding::Any => None,
+                    Encoding::Utf8 => Some("utf-8"),
+                    Encoding::Utf16Le => Some("utf-16le"),
+                    Encoding::Utf16Be => Some("utf-16be"),
+                };
+                if let Some(encoding) = encoding {
+                    write!(f, " encoding={encoding}")?;
+                }
+            }
+            EventData::StreamEnd => f.write_str("STREAM-END")?,
+            EventData::DocumentStart {
+                version_directive,
+                tag_directives,
+                implicit,
+            } => {
+                f.write_str("DOCUMENT-START")?;
+                if let Some(version) = version_directive {
+                    write!(f, " version={}.{}", version.major, version.minor)?;
+                }
+                for tag_directive in tag_directives {
+                    write!(f, " tag={}={}", tag_directive.handle, tag_directive.prefix)?;
+                }
+                if *implicit {
+                    f.write_str(" implicit")?;
+                }
+            }
+            EventData::DocumentEnd { implicit } => {
+                f.write_str("DOCUMENT-END")?;
+                if *implicit {
+                    f.write_str(" implicit")?;
+                }
+            }
+            EventData::Alias { anchor } => write!(f, "ALIAS *{anchor}")?,
+            EventData::Scalar {
+                anchor,
+                tag,
+                value,
+                style,
+                ..
+            } => {
+                f.write_str("SCALAR")?;
+                write_properties(f, anchor.as_deref(), None)?;
+                write!(f, " value={value:?}")?;
+                write_properties(f, None, tag.as_deref())?;
+                let style = match style {
+                    ScalarStyle::Any => "any",
+                    ScalarStyle::Plain => "plain",
+                    ScalarStyle::SingleQuoted => "single-quoted",
+                    ScalarStyle::DoubleQuoted => "double-quoted",
+                    ScalarStyle::Literal => "literal",
+                    ScalarStyle::Folded => "folded",
+                };
+                write!(f, " style={style}")?;
+            }
+            EventData::SequenceStart {
+                anchor, tag, style, ..
+            } => {
+                f.write_str("SEQUENCE-START")?;
+                write_properties(f, anchor.as_deref(), tag.as_deref())?;
+                let style = match style {
+                    SequenceStyle::Any => "any",
+                    SequenceStyle::Block => "block",
+                    SequenceStyle::Flow => "flow",
+                };
+                write!(f, " style={style}")?;
+            }
+            EventData::SequenceEnd => f.write_str("SEQUENCE-END")?,
+            EventData::MappingStart {
+                anchor, tag, style, ..
+            } => {
+                f.write_str("MAPPING-START")?;
+                write_properties(f, anchor.as_deref(), tag.as_deref())?;
+                let style = match style {
+                    MappingStyle::Any => "any",
+                    MappingStyle::Block => "block",
+                    MappingStyle::Flow => "flow",
+                };
+                write!(f, " style={style}")?;
+            }
+            EventData::MappingEnd => f.write_str("MAPPING-END")?,
+        }
+        write!(
+            f,
+            " @{}:{}",
+            self.start_mark.line + 1,
+            self.start_mark.column + 1
+        )
+    }
+}
+
+fn write_properties(
+    f: &mut core::fmt::Formatter<'_>,
+    anchor: Option<&str>,
+    tag: Option<&str>,
+) -> core::fmt::Result {
+    if let Some(anchor) = anchor {
+        write!(f, " anchor=&{anchor}")?;
+    }
+    if let Some(tag) = tag {
+        match tag.strip_prefix("tag:yaml.org,2002:") {
+            Some(suffix) => write!(f, " tag=!!{suffix}")?,
+            None => write!(f, " tag={tag}")?,
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,6 @@
 #![doc = include_str!("../README.md")]
 #![doc(html_root_url = "https://docs.rs/libyaml-safer/0.1.0")]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(clippy::pedantic)]
 #![allow(
     clippy::cast_lossless,
@@ -23,15 +24,19 @@
 
 extern crate alloc;
 
+use alloc::string::String;
+
 #[macro_use]
 mod macros;
 
+pub mod chars;
 mod document;
 mod emitter;
 mod error;
 mod event;
 mod parser;
 mod reader;
+mod resolver;
 mod scanner;
 mod token;
 
@@ -40,6 +45,8 @@ pub use crate::emitter::*;
 pub use crate::error::*;
 pub use crate::event::*;
 pub use crate::parser::*;
+pub use crate::reader::detect_encoding;
+pub use crate::resolver::*;
 pub use crate::scanner::*;
 pub use crate::token::*;
 
@@ -64,6 +71,9 @@ pub const TIMESTAMP_TAG: &str = "tag:yaml.org,2002:timestamp";
 pub const SEQ_TAG: &str = "tag:yaml.org,2002:seq";
 /// The tag `!!map` is used to denote mapping.
 pub const MAP_TAG: &str = "tag:yaml.org,2002:map";
+/// The tag `!!set` is used to denote sets, mappings whose values are all
+/// null.
+pub const SET_TAG: &str = "tag:yaml.org,2002:set";
 
 /// The default scalar tag is `!!str`.
 pub const DEFAULT_SCALAR_TAG: &str = STR_TAG;
@@ -72,6 +82,11 @@ pub const DEFAULT_SEQUENCE_TAG: &str = SEQ_TAG;
 /// The default mapping tag is `!!map`.
 pub const DEFAULT_MAPPING_TAG: &str = MAP_TAG;
 
+/// The handles and prefixes of the default tag directives, which bind `!` to
+/// `!` and `!!` to `tag:yaml.org,2002:` in every document unless disabled.
+pub(crate) const DEFAULT_TAG_DIRECTIVES: [(&str, &str); 2] =
+    [("!", "!"), ("!!", "tag:yaml.org,2002:")];
+
 /// The version directive data.
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[non_exhaustive]
@@ -168,6 +183,7 @@ pub enum MappingStyle {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::{format, vec::Vec};
 
     #[test]
     fn sanity() {
@@ -193,7 +209,7 @@ tie-fighter: '|\-*-/|'
 
         let mut emitter = Emitter::new();
         let mut output = Vec::new();
-        emitter.set_output(&mut output);
+        emitter.set_output_string(&mut output);
         doc.dump(&mut emitter).unwrap();
         let output_str = core::str::from_utf8(&output).expect("invalid UTF-8");
         assert_eq!(output_str, SANITY_OUTPUT);
@@ -205,7 +221,7 @@ tie-fighter: '|\-*-/|'
 c: true";
         let mut scanner = Scanner::new();
         let mut read_in = INPUT.as_bytes();
-        scanner.set_input(&mut read_in);
+        scanner.set_input_string(&mut read_in);
         let events = scanner.collect::<Result<Vec<_>, _>>().unwrap();
         let expected = &[
             Token {
@@ -381,6 +397,46 @@ c: true";
         );
     }
 
+    #[test]
+    fn scan_with_source() {
+        const INPUT: &str = "\u{feff}k\u{e9}y: 'v a'\nlist: [&x 1, *x]\nblock: |\n  text\n";
+        let mut scanner = Scanner::new();
+        scanner.set_input_str(INPUT);
+        let mut sources = Vec::new();
+        loop {
+            let (token, source) = scanner.scan_with_source().unwrap();
+            sources.push(source.unwrap());
+            if token.data == TokenData::StreamEnd {
+                break;
+            }
+        }
+        let sources: Vec<&str> = sources.into_iter().filter(|s| !s.is_empty()).collect();
+        assert_eq!(
+            sources,
+            [
+                "k\u{e9}y",
+                ":",
+                "'v a'",
+                "list",
+                ":",
+                "[",
+                "&x",
+                "1",
+                ",",
+                "*x",
+                "]",
+                "block",
+                ":",
+                "|\n  text\n",
+            ]
+        );
+
+        let mut scanner = Scanner::new();
+        let mut read_in = INPUT.as_bytes();
+        scanner.set_input_string(&mut read_in);
+        assert_eq!(scanner.scan_with_source().unwrap().1, None);
+    }
+
     fn zip_longest<A: Iterator, B: Iterator>(
         a: A,
         b: B,
@@ -1,10 +1,18 @@
+use alloc::{borrow::Cow, boxed::Box, string::String, vec, vec::Vec};
+
 use crate::scanner::Scanner;
 use crate::{
-    Encoding, Error, Event, EventData, MappingStyle, Mark, Result, ScalarStyle, SequenceStyle,
-    TagDirective, TokenData, VersionDirective,
+    core_schema_tag, Document, Encoding, Error, Event, EventData, MappingStyle, Mark, NodeKind,
+    Result, ScalarStyle, SequenceStyle, TagDirective, TokenData, VersionDirective,
+    DEFAULT_TAG_DIRECTIVES, NULL_TAG,
 };
 
 /// The parser structure.
+///
+/// A parser borrows its input and holds a [`TagResolver`], which is not
+/// required to be [`Send`], so it has to be used on the thread that created
+/// it. The [`Event`]s and [`Document`]s it produces own their data and can be
+/// sent to other threads.
 #[non_exhaustive]
 pub struct Parser<'r> {
     pub(crate) scanner: Scanner<'r>,
@@ -14,12 +22,75 @@ pub struct Parser<'r> {
     pub(crate) state: ParserState,
     /// The stack of marks.
     pub(crate) marks: Vec<Mark>,
-    /// The list of TAG directives.
+    /// The TAG directives of the current document, not including the default
+    /// ones, in the first `tag_directive_count` entries. The entries after
+    /// them were defined by earlier documents and are kept to reuse their
+    /// buffers.
     pub(crate) tag_directives: Vec<TagDirective>,
+    /// The number of TAG directives of the current document.
+    pub(crate) tag_directive_count: usize,
     /// The alias data.
     pub(crate) aliases: Vec<AliasData>,
+    /// The resolver for nodes without an explicit tag.
+    pub(crate) tag_resolver: TagResolver,
+    /// Are the default `!` and `!!` tag directives defined?
+    pub(crate) default_tag_directives: bool,
+    /// Must every document start with `---`?
+    pub(crate) require_explicit_document_start: bool,
+    /// Are the empty scalars synthesized for omitted nodes tagged `!!null`?
+    pub(crate) tag_empty_scalars: bool,
+    /// The number of collections started and not yet ended.
+    pub(crate) open_collections: usize,
+    /// What the loader does with tags that are not allowed.
+    pub(crate) tag_policy: TagPolicy,
+    /// The explicit tags accepted by the loader, see [`Parser::set_tag_policy()`].
+    pub(crate) allowed_tags: Vec<String>,
+    /// Does the loader accept nodes with the non-specific `!` tag?
+    pub(crate) allow_non_specific_tag: bool,
+    /// The collections being loaded when the last loading error occurred.
+    pub(crate) error_context_chain: Vec<(String, Mark)>,
+    /// What the loader does with anchors defined twice in a document.
+    pub(crate) duplicate_anchor_policy: DuplicateAnchorPolicy,
+    /// The value of the scalar event being parsed, if it is borrowed from the
+    /// input.
+    pub(crate) borrowed_value: Option<&'r str>,
+}
+
+/// What [`Document::load()`] does with nodes whose explicit tag is not one of
+/// the allowed tags, see [`Parser::set_tag_policy()`].
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum TagPolicy {
+    /// Keep every tag.
+    #[default]
+    PassThrough,
+    /// Fail with a composer error at the node.
+    Error,
+    /// Drop the tag and resolve the node as if it had none.
+    Strip,
+}
+
+/// What [`Document::load()`] does with an anchor defined a second time in a
+/// document, see [`Parser::set_duplicate_anchor_policy()`].
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum DuplicateAnchorPolicy {
+    /// Fail with a composer error at the second definition.
+    #[default]
+    Error,
+    /// Bind the following aliases to the latest definition, as in the YAML
+    /// 1.2 specification. Aliases before it keep referring to the earlier
+    /// node.
+    LastWins,
+    /// Bind every alias to the first definition, and ignore the anchor of the
+    /// later ones.
+    FirstWins,
 }
 
+/// A function resolving the tag of a node without an explicit tag, see
+/// [`Parser::set_tag_resolver()`].
+pub type TagResolver = Box<dyn Fn(&str, Option<&str>, NodeKind) -> Option<String>>;
+
 impl<'r> Default for Parser<'r> {
     fn default() -> Self {
         Self::new()
@@ -95,6 +166,22 @@ pub enum ParserState {
     End = 23,
 }
 
+/// Counters describing the progress of a parser, see [`Parser::stats()`].
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParserStats {
+    /// The number of tokens consumed by the parser so far.
+    pub tokens_parsed: usize,
+    /// The indentation column of the current block collection, or -1 outside
+    /// block collections.
+    pub indent: i32,
+    /// The number of flow collections the scanner is in.
+    pub flow_level: i32,
+    /// The number of collections started and not yet ended, in the events
+    /// produced so far.
+    pub open_collections: usize,
+}
+
 /// This structure holds aliases data.
 #[non_exhaustive]
 pub struct AliasData {
@@ -120,6 +207,37 @@ impl<'r> Iterator for Parser<'r> {
 
 impl<'r> core::iter::FusedIterator for Parser<'r> {}
 
+/// An iterator over the documents of a stream, see [`Parser::documents()`].
+pub struct Documents<'p, 'r> {
+    parser: &'p mut Parser<'r>,
+    done: bool,
+}
+
+impl Iterator for Documents<'_, '_> {
+    type Item = Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match Document::load(self.parser) {
+            Ok(mut document) => {
+                if document.get_root_node().is_none() {
+                    self.done = true;
+                    return None;
+                }
+                Some(Ok(document))
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl core::iter::FusedIterator for Documents<'_, '_> {}
+
 impl<'r> Parser<'r> {
     /// Create a parser.
     pub fn new() -> Parser<'r> {
@@ -129,21 +247,78 @@ impl<'r> Parser<'r> {
             state: ParserState::default(),
             marks: Vec::with_capacity(16),
             tag_directives: Vec::with_capacity(16),
+            tag_directive_count: 0,
             aliases: Vec::new(),
+            tag_resolver: Box::new(core_schema_tag),
+            default_tag_directives: true,
+            require_explicit_document_start: false,
+            tag_empty_scalars: false,
+            open_collections: 0,
+            tag_policy: TagPolicy::default(),
+            allowed_tags: Vec::new(),
+            allow_non_specific_tag: true,
+            error_context_chain: Vec::new(),
+            duplicate_anchor_policy: DuplicateAnchorPolicy::default(),
+            borrowed_value: None,
         }
     }
 
+    /// Create a parser reading from a string.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &'r str) -> Parser<'r> {
+        let mut parser = Parser::new();
+        parser.scanner.set_input_str(input);
+        parser
+    }
+
+    /// Create a parser reading from a generic input handler.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: std::io::BufRead>(input: &'r mut R) -> Parser<'r> {
+        let mut parser = Parser::new();
+        parser.set_input(input);
+        parser
+    }
+
     /// Reset the parser state.
     pub fn reset(&mut self) {
         *self = Self::new();
     }
 
+    /// Reset the parser state and remove the input, but keep the
+    /// configuration.
+    ///
+    /// The encoding set with [`Parser::set_encoding()`], the input limits, the
+    /// tag resolver and tag policy, and the other parsing options are kept. A
+    /// new input can then be set to parse another stream with the same
+    /// configuration. The position set with
+    /// [`Parser::set_initial_position()`] belongs to the previous input, so it
+    /// is reset.
+    pub fn reset_input(&mut self) {
+        let mut parser = Self::new();
+        parser.scanner.encoding = self.scanner.preset_encoding;
+        parser.scanner.preset_encoding = self.scanner.preset_encoding;
+        parser.scanner.max_input_len = self.scanner.max_input_len;
+        parser.scanner.max_scalar_len = self.scanner.max_scalar_len;
+        parser.scanner.reject_stray_bom = self.scanner.reject_stray_bom;
+        parser.scanner.validate_utf8 = self.scanner.validate_utf8;
+        parser.tag_resolver = core::mem::replace(&mut self.tag_resolver, Box::new(core_schema_tag));
+        parser.default_tag_directives = self.default_tag_directives;
+        parser.require_explicit_document_start = self.require_explicit_document_start;
+        parser.tag_empty_scalars = self.tag_empty_scalars;
+        parser.tag_policy = self.tag_policy;
+        parser.allowed_tags = core::mem::take(&mut self.allowed_tags);
+        parser.allow_non_specific_tag = self.allow_non_specific_tag;
+        parser.duplicate_anchor_policy = self.duplicate_anchor_policy;
+        *self = parser;
+    }
+
     /// Set a string input.
     pub fn set_input_string(&mut self, input: &'r mut &[u8]) {
         self.scanner.set_input_string(input);
     }
 
     /// Set a generic input handler.
+    #[cfg(feature = "std")]
     pub fn set_input(&mut self, input: &'r mut dyn std::io::BufRead) {
         self.scanner.set_input(input);
     }
@@ -153,6 +328,169 @@ impl<'r> Parser<'r> {
         self.scanner.set_encoding(encoding);
     }
 
+    /// Set if the default tag directives, binding `!` to `!` and `!!` to
+    /// `tag:yaml.org,2002:`, are defined in every document.
+    ///
+    /// When disabled, tags using the `!` and `!!` handles are an error unless
+    /// the document defines the handles with `%TAG` directives.
+    pub fn set_default_tag_directives(&mut self, enabled: bool) {
+        self.default_tag_directives = enabled;
+    }
+
+    /// Set if every document must start with an explicit `---` marker.
+    ///
+    /// When enabled, a document whose content is not preceded by `---` is an
+    /// error, even if it is the first document of the stream and has no
+    /// directives. Empty streams are still accepted.
+    pub fn set_require_explicit_document_start(&mut self, require: bool) {
+        self.require_explicit_document_start = require;
+    }
+
+    /// Set if the empty scalars produced for omitted nodes, like the value of
+    /// `key:` or the key of `: value`, are tagged with [`NULL_TAG`].
+    ///
+    /// By default these scalars have no tag and look the same as an empty
+    /// plain scalar in the input. When enabled, they can be told apart from
+    /// explicit empty values like `key: ''`.
+    pub fn set_tag_empty_scalars(&mut self, tag: bool) {
+        self.tag_empty_scalars = tag;
+    }
+
+    /// Set the maximum number of bytes to read from the input, see
+    /// [`Scanner::set_max_input_len()`].
+    pub fn set_max_input_len(&mut self, max_input_len: usize) {
+        self.scanner.set_max_input_len(max_input_len);
+    }
+
+    /// Set the maximum length of a scalar value, see
+    /// [`Scanner::set_max_scalar_len()`].
+    pub fn set_max_scalar_len(&mut self, max_scalar_len: usize) {
+        self.scanner.set_max_scalar_len(max_scalar_len);
+    }
+
+    /// Set if a byte order mark after the start of the stream is an error,
+    /// see [`Scanner::set_reject_stray_bom()`].
+    pub fn set_reject_stray_bom(&mut self, reject: bool) {
+        self.scanner.set_reject_stray_bom(reject);
+    }
+
+    /// Set if a UTF-8 input with an invalid byte sequence is an error, see
+    /// [`Scanner::set_validate_utf8()`].
+    pub fn set_validate_utf8(&mut self, validate: bool) {
+        self.scanner.set_validate_utf8(validate);
+    }
+
+    /// Set the position of the start of the input, for input embedded in a
+    /// larger document, like front matter or a fenced code block.
+    ///
+    /// The marks of the events and errors are then positions in the larger
+    /// document: `line` and `index` are added to every mark, and `column` to
+    /// the marks on the first line of the input. The offsets of reader errors
+    /// are shifted by `index` as well. This has to be called before the input
+    /// is parsed.
+    pub fn set_initial_position(&mut self, line: u64, column: u64, index: u64) {
+        self.scanner.origin = Mark {
+            index,
+            line,
+            column,
+        };
+        self.scanner.mark.index = index;
+        self.scanner.mark.line = line;
+    }
+
+    /// Set the function resolving the tags of nodes loaded with
+    /// [`Document::load()`](crate::Document::load) that have no explicit tag.
+    ///
+    /// The resolver is called with the value of the node (empty for
+    /// collections), the non-specific `!` tag if the node has it, and the kind
+    /// of node. The returned tag is stored on the node. If the resolver returns
+    /// `None`, the node is given the default tag of its kind, `!!str`, `!!seq`
+    /// or `!!map`.
+    ///
+    /// The default resolver is [`core_schema_tag()`](crate::core_schema_tag),
+    /// so a plain scalar such as `1` or `true` is loaded as `!!int` or
+    /// `!!bool`. A custom resolver can fall back to it for the nodes it does
+    /// not handle. Use a resolver that always returns `None` to load every
+    /// scalar as `!!str`, as libyaml does.
+    pub fn set_tag_resolver(
+        &mut self,
+        resolver: impl Fn(&str, Option<&str>, NodeKind) -> Option<String> + 'static,
+    ) {
+        self.tag_resolver = Box::new(resolver);
+    }
+
+    /// Set what [`Document::load()`] does with nodes that have an explicit
+    /// tag other than those in `allowed`.
+    ///
+    /// The tags are compared after their handles are expanded, so `!!str` is
+    /// allowed by `tag:yaml.org,2002:str`. Nodes without a tag, or with the
+    /// non-specific `!` tag, are accepted by the policy, and their tags are
+    /// resolved as usual, see also [`Parser::set_allow_non_specific_tag()`].
+    /// The policy does not apply to [`Parser::parse()`].
+    pub fn set_tag_policy(&mut self, policy: TagPolicy, allowed: &[&str]) {
+        self.tag_policy = policy;
+        self.allowed_tags = allowed.iter().map(|tag| String::from(*tag)).collect();
+    }
+
+    /// Set what [`Document::load()`] does with an anchor that is defined again
+    /// in the same document.
+    ///
+    /// By default, this is a composer error. The policy does not apply to
+    /// [`Parser::parse()`], which reports anchors and aliases as they are
+    /// written.
+    pub fn set_duplicate_anchor_policy(&mut self, policy: DuplicateAnchorPolicy) {
+        self.duplicate_anchor_policy = policy;
+    }
+
+    /// Set if [`Document::load()`] accepts nodes with the non-specific `!`
+    /// tag.
+    ///
+    /// By default, such nodes are given the default tag of their kind, `!!str`,
+    /// `!!seq` or `!!map`. When disabled, they fail with a composer error, for
+    /// schemas that do not allow them.
+    pub fn set_allow_non_specific_tag(&mut self, allow: bool) {
+        self.allow_non_specific_tag = allow;
+    }
+
+    /// Apply the tag policy to the explicit tag of a node being loaded.
+    pub(crate) fn check_tag(&self, tag: Option<String>, mark: Mark) -> Result<Option<String>> {
+        let Some(name) = tag.as_deref() else {
+            return Ok(tag);
+        };
+        if name == "!" && !self.allow_non_specific_tag {
+            return Err(Error::composer(
+                "",
+                Mark::default(),
+                "found a non-specific tag that is not allowed",
+                mark,
+            ));
+        }
+        if self.tag_policy == TagPolicy::PassThrough
+            || name == "!"
+            || self.allowed_tags.iter().any(|allowed| allowed == name)
+        {
+            return Ok(tag);
+        }
+        match self.tag_policy {
+            TagPolicy::Strip => Ok(None),
+            _ => Err(Error::composer(
+                "",
+                Mark::default(),
+                "found a tag that is not allowed",
+                mark,
+            )),
+        }
+    }
+
+    pub(crate) fn resolve_tag(
+        &self,
+        value: &str,
+        tag: Option<&str>,
+        kind: NodeKind,
+    ) -> Option<String> {
+        (self.tag_resolver)(value, tag, kind)
+    }
+
     /// Parse the input stream and produce the next parsing event.
     ///
     /// Call the function subsequently to produce a sequence of events
@@ -165,10 +503,215 @@ impl<'r> Parser<'r> {
     /// the calls of [`Document::load()`](crate::Document::load). Doing this
     /// will break the parser.
     pub fn parse(&mut self) -> Result<Event> {
-        if self.scanner.stream_end_produced || self.state == ParserState::End {
-            return Ok(Event::stream_end());
+        let (mut event, value) = self.parse_borrowed()?;
+        if let EventData::Scalar {
+            value: event_value, ..
+        } = &mut event.data
+        {
+            *event_value = value.into_owned();
+        }
+        Ok(event)
+    }
+
+    /// Parse the next event like [`Parser::parse()`], together with the value
+    /// of a scalar event, which is borrowed from the input when possible.
+    ///
+    /// The value of a plain scalar on a single line of an input set with
+    /// [`Parser::from_str()`] is the text of the input it spans, so it is
+    /// borrowed instead of being copied. Other scalar values are owned. The
+    /// value is moved out of the scalar event, whose `value` is left empty,
+    /// and is empty for other events.
+    pub fn parse_borrowed(&mut self) -> Result<(Event, Cow<'r, str>)> {
+        let origin = self.scanner.origin;
+        let mut event = if self.scanner.stream_end_produced || self.state == ParserState::End {
+            let mark = self.scanner.mark;
+            Event::parsed(EventData::StreamEnd, mark, mark)
+        } else {
+            self.borrowed_value = None;
+            self.state_machine()
+                .map_err(|err| err.relative_to(origin))?
+        };
+        event.start_mark = event.start_mark.relative_to(origin);
+        event.end_mark = event.end_mark.relative_to(origin);
+        if let EventData::Scalar {
+            anchor_mark,
+            tag_mark,
+            value_mark,
+            ..
+        }
+        | EventData::SequenceStart {
+            anchor_mark,
+            tag_mark,
+            value_mark,
+            ..
+        }
+        | EventData::MappingStart {
+            anchor_mark,
+            tag_mark,
+            value_mark,
+            ..
+        } = &mut event.data
+        {
+            for mark in [anchor_mark, tag_mark, value_mark].into_iter().flatten() {
+                *mark = mark.relative_to(origin);
+            }
+        }
+        match event.data {
+            EventData::SequenceStart { .. } | EventData::MappingStart { .. } => {
+                self.open_collections += 1;
+            }
+            EventData::SequenceEnd | EventData::MappingEnd => self.open_collections -= 1,
+            _ => {}
+        }
+        let value = match &mut event.data {
+            EventData::Scalar { value, .. } => match self.borrowed_value.take() {
+                Some(text) => Cow::Borrowed(text),
+                None => Cow::Owned(core::mem::take(value)),
+            },
+            _ => Cow::Borrowed(""),
+        };
+        Ok((event, value))
+    }
+
+    /// Append bytes to the input, to parse it with [`Parser::try_parse()`] as
+    /// it arrives.
+    ///
+    /// The input must not have been set otherwise. Once every byte has been
+    /// fed, the end of the input is marked with [`Parser::end_input()`].
+    pub fn feed(&mut self, input: &[u8]) {
+        self.scanner.feed(input);
+    }
+
+    /// Mark the end of the input fed with [`Parser::feed()`].
+    pub fn end_input(&mut self) {
+        self.scanner.end_input();
+    }
+
+    /// Parse the next event of the input fed with [`Parser::feed()`], or
+    /// return `None` if more input is needed to produce it.
+    ///
+    /// When `None` is returned, the parser is left as it was before the call,
+    /// so it can be called again once more input is fed. Until the end of the
+    /// input is marked with [`Parser::end_input()`], an event is only produced
+    /// when the input following it has been fed, so the last events of the
+    /// stream may only be produced after that.
+    pub fn try_parse(&mut self) -> Result<Option<Event>> {
+        if self.scanner.read_handler.is_none() || !self.scanner.push_input().ready() {
+            return Ok(None);
+        }
+        let scanner = self.scanner.checkpoint();
+        let states = self.states.clone();
+        let state = self.state;
+        let marks = self.marks.clone();
+        let tag_directive_count = self.tag_directive_count;
+        let open_collections = self.open_collections;
+        let pos = self.scanner.push_input().pos;
+        match self.parse() {
+            Ok(event) => {
+                self.scanner.push_input().commit();
+                Ok(Some(event))
+            }
+            Err(err) if err.is_incomplete() => {
+                self.scanner.restore(scanner);
+                self.states = states;
+                self.state = state;
+                self.marks = marks;
+                self.tag_directive_count = tag_directive_count;
+                self.open_collections = open_collections;
+                self.scanner.push_input().pos = pos;
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Get the contexts of the last error raised while loading a document with
+    /// [`Document::load()`], from the outermost to the innermost.
+    ///
+    /// Each context describes a collection that was being loaded, such as
+    /// `while composing mapping value for key 'spec'` with the mark of the
+    /// key, or `while composing sequence item 3` with the mark of the
+    /// sequence. The problem itself is described by the error. The chain is
+    /// cleared when the next document is loaded.
+    pub fn error_context_chain(&self) -> &[(String, Mark)] {
+        &self.error_context_chain
+    }
+
+    /// Get the indentation step used most often by the block collections
+    /// scanned so far, to keep the indentation of the input when it is
+    /// written again with [`Emitter::set_indent()`](crate::Emitter::set_indent).
+    ///
+    /// The step of a block collection is the number of columns it is indented
+    /// from the enclosing block collection. Sequences that are not indented
+    /// under their mapping key and steps of more than nine columns are not
+    /// counted. Of equally common steps, the smallest is returned. Returns
+    /// `None` until a nested block collection is scanned.
+    pub fn detected_indent(&self) -> Option<i32> {
+        let (step, count) = self
+            .scanner
+            .indent_steps
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, count)| **count)?;
+        (*count != 0).then_some(step as i32)
+    }
+
+    /// Get the encoding of the input stream, once it is known.
+    ///
+    /// The encoding is detected with [`detect_encoding()`](crate::detect_encoding)
+    /// when the input is first read, unless it was set with
+    /// [`Parser::set_encoding()`]. Before then, this returns `None`.
+    pub fn detected_encoding(&self) -> Option<Encoding> {
+        (self.scanner.encoding != Encoding::Any).then_some(self.scanner.encoding)
+    }
+
+    /// Get counters describing how much of the input has been parsed.
+    ///
+    /// This is meant for profiling and for guarding against pathological
+    /// inputs, for example by stopping when too many tokens are parsed for
+    /// the size of the input. The token counter, indentation and flow level
+    /// come from the scanner, which may have read ahead of the events
+    /// produced so far.
+    pub fn stats(&self) -> ParserStats {
+        ParserStats {
+            tokens_parsed: self.scanner.tokens_parsed,
+            indent: self.scanner.indent,
+            flow_level: self.scanner.flow_level,
+            open_collections: self.open_collections,
+        }
+    }
+
+    /// Parse the rest of the input stream and count the documents in it.
+    ///
+    /// This is cheaper than loading each document with
+    /// [`Document::load()`](crate::Document::load), since no nodes are built,
+    /// but the whole stream is still parsed, so malformed or truncated input
+    /// results in an error.
+    pub fn count_documents(&mut self) -> Result<usize> {
+        let mut count = 0;
+        loop {
+            match self.parse()?.data {
+                EventData::DocumentStart { .. } => count += 1,
+                EventData::StreamEnd => return Ok(count),
+                _ => {}
+            }
+        }
+    }
+
+    /// Iterate over the documents of the input stream, loading each one with
+    /// [`Document::load()`] when it is requested.
+    ///
+    /// The iterator ends after the last document of the stream, or after
+    /// yielding the first error. The iterator can be dropped before the end of
+    /// the stream; the parser can then be reset with [`Parser::reset()`] or
+    /// [`Parser::reset_input()`], but must not be used to parse the rest of
+    /// the stream with [`Parser::parse()`].
+    pub fn documents(&mut self) -> Documents<'_, 'r> {
+        Documents {
+            parser: self,
+            done: false,
         }
-        self.state_machine()
     }
 
     fn state_machine(&mut self) -> Result<Event> {
@@ -210,13 +753,13 @@ impl<'r> Parser<'r> {
         let token = self.scanner.peek()?;
 
         if let TokenData::StreamStart { encoding } = &token.data {
-            let event = Event {
-                data: EventData::StreamStart {
+            let event = Event::parsed(
+                EventData::StreamStart {
                     encoding: *encoding,
                 },
-                start_mark: token.start_mark,
-                end_mark: token.end_mark,
-            };
+                token.start_mark,
+                token.end_mark,
+            );
             self.state = ParserState::ImplicitDocumentStart;
             self.scanner.skip_token();
             Ok(event)
@@ -251,15 +794,23 @@ impl<'r> Parser<'r> {
                     | TokenData::StreamEnd
             )
         {
-            let event = Event {
-                data: EventData::DocumentStart {
+            if self.require_explicit_document_start {
+                return Err(Error::parser(
+                    "",
+                    Mark::default(),
+                    "did not find expected <document start>",
+                    token.start_mark,
+                ));
+            }
+            let event = Event::parsed(
+                EventData::DocumentStart {
                     version_directive: None,
                     tag_directives: vec![],
                     implicit: true,
                 },
-                start_mark: token.start_mark,
-                end_mark: token.end_mark,
-            };
+                token.start_mark,
+                token.end_mark,
+            );
             self.process_directives(None, None)?;
             self.states.push(ParserState::DocumentEnd);
             self.state = ParserState::BlockNode;
@@ -271,15 +822,15 @@ impl<'r> Parser<'r> {
             token = self.scanner.peek()?;
             if let TokenData::DocumentStart = token.data {
                 end_mark = token.end_mark;
-                let event = Event {
-                    data: EventData::DocumentStart {
+                let event = Event::parsed(
+                    EventData::DocumentStart {
                         version_directive,
                         tag_directives: core::mem::take(&mut tag_directives),
                         implicit: false,
                     },
                     start_mark,
                     end_mark,
-                };
+                );
                 self.states.push(ParserState::DocumentEnd);
                 self.state = ParserState::DocumentContent;
                 self.scanner.skip_token();
@@ -293,11 +844,7 @@ impl<'r> Parser<'r> {
                 ))
             }
         } else {
-            let event = Event {
-                data: EventData::StreamEnd,
-                start_mark: token.start_mark,
-                end_mark: token.end_mark,
-            };
+            let event = Event::parsed(EventData::StreamEnd, token.start_mark, token.end_mark);
             self.state = ParserState::End;
             self.scanner.skip_token();
             Ok(event)
@@ -314,7 +861,7 @@ impl<'r> Parser<'r> {
         {
             let mark = token.start_mark;
             self.state = self.states.pop().unwrap();
-            Self::process_empty_scalar(mark)
+            self.process_empty_scalar(mark)
         } else {
             self.parse_node(true, false)
         }
@@ -331,13 +878,13 @@ impl<'r> Parser<'r> {
             self.scanner.skip_token();
             implicit = false;
         }
-        self.tag_directives.clear();
+        self.tag_directive_count = 0;
         self.state = ParserState::DocumentStart;
-        Ok(Event {
-            data: EventData::DocumentEnd { implicit },
+        Ok(Event::parsed(
+            EventData::DocumentEnd { implicit },
             start_mark,
             end_mark,
-        })
+        ))
     }
 
     fn parse_node(&mut self, block: bool, indentless_sequence: bool) -> Result<Event> {
@@ -347,22 +894,19 @@ impl<'r> Parser<'r> {
         let mut tag: Option<String> = None;
         let mut start_mark: Mark;
         let mut end_mark: Mark;
-        let mut tag_mark = Mark {
-            index: 0,
-            line: 0,
-            column: 0,
-        };
+        let mut anchor_mark: Option<Mark> = None;
+        let mut tag_mark: Option<Mark> = None;
 
         let mut token = self.scanner.peek_mut()?;
 
         if let TokenData::Alias { value } = &mut token.data {
-            let event = Event {
-                data: EventData::Alias {
+            let event = Event::parsed(
+                EventData::Alias {
                     anchor: core::mem::take(value),
                 },
-                start_mark: token.start_mark,
-                end_mark: token.end_mark,
-            };
+                token.start_mark,
+                token.end_mark,
+            );
             self.state = self.states.pop().unwrap();
             self.scanner.skip_token();
             return Ok(event);
@@ -372,6 +916,7 @@ impl<'r> Parser<'r> {
         start_mark = end_mark;
         if let TokenData::Anchor { value } = &mut token.data {
             anchor = Some(core::mem::take(value));
+            anchor_mark = Some(token.start_mark);
             start_mark = token.start_mark;
             end_mark = token.end_mark;
             self.scanner.skip_token();
@@ -379,20 +924,21 @@ impl<'r> Parser<'r> {
             if let TokenData::Tag { handle, suffix } = &mut token.data {
                 tag_handle = Some(core::mem::take(handle));
                 tag_suffix = Some(core::mem::take(suffix));
-                tag_mark = token.start_mark;
+                tag_mark = Some(token.start_mark);
                 end_mark = token.end_mark;
                 self.scanner.skip_token();
             }
         } else if let TokenData::Tag { handle, suffix } = &mut token.data {
             tag_handle = Some(core::mem::take(handle));
             tag_suffix = Some(core::mem::take(suffix));
-            tag_mark = token.start_mark;
-            start_mark = tag_mark;
+            tag_mark = Some(token.start_mark);
+            start_mark = token.start_mark;
             end_mark = token.end_mark;
             self.scanner.skip_token();
             token = self.scanner.peek_mut()?;
             if let TokenData::Anchor { value } = &mut token.data {
                 anchor = Some(core::mem::take(value));
+                anchor_mark = Some(token.start_mark);
                 end_mark = token.end_mark;
                 self.scanner.skip_token();
             }
@@ -402,21 +948,33 @@ impl<'r> Parser<'r> {
             if tag_handle_value.is_empty() {
                 tag = tag_suffix;
             } else {
-                for tag_directive in &self.tag_directives {
-                    if tag_directive.handle == *tag_handle_value {
-                        let suffix = tag_suffix.as_deref().unwrap_or("");
-                        tag = Some(alloc::format!("{}{}", tag_directive.prefix, suffix));
-                        break;
-                    }
-                }
-                if tag.is_none() {
+                let prefix = self.tag_directives[..self.tag_directive_count]
+                    .iter()
+                    .find(|tag_directive| tag_directive.handle == *tag_handle_value)
+                    .map(|tag_directive| tag_directive.prefix.as_str())
+                    .or_else(|| {
+                        // The default directives are not stored, so that
+                        // documents do not allocate them.
+                        DEFAULT_TAG_DIRECTIVES
+                            .iter()
+                            .find(|(handle, _)| {
+                                self.default_tag_directives && handle == tag_handle_value
+                            })
+                            .map(|(_, prefix)| *prefix)
+                    });
+                let Some(prefix) = prefix else {
                     return Err(Error::parser(
                         "while parsing a node",
                         start_mark,
                         "found undefined tag handle",
-                        tag_mark,
+                        tag_mark.unwrap_or(start_mark),
                     ));
-                }
+                };
+                let suffix = tag_suffix.as_deref().unwrap_or("");
+                let mut value = String::with_capacity(prefix.len() + suffix.len());
+                value.push_str(prefix);
+                value.push_str(suffix);
+                tag = Some(value);
             }
         }
 
@@ -427,111 +985,140 @@ impl<'r> Parser<'r> {
         if indentless_sequence && matches!(token.data, TokenData::BlockEntry) {
             end_mark = token.end_mark;
             self.state = ParserState::IndentlessSequenceEntry;
-            let event = Event {
-                data: EventData::SequenceStart {
+            let event = Event::parsed(
+                EventData::SequenceStart {
                     anchor,
                     tag,
                     implicit,
                     style: SequenceStyle::Block,
+                    anchor_mark,
+                    tag_mark,
+                    value_mark: Some(token.start_mark),
                 },
                 start_mark,
                 end_mark,
-            };
+            );
             Ok(event)
         } else if let TokenData::Scalar { value, style } = &mut token.data {
             let mut plain_implicit = false;
             let mut quoted_implicit = false;
+            // The value of the token is left empty if it is borrowed.
+            let borrowed = *style == ScalarStyle::Plain && value.is_empty();
+            let value_mark = token.start_mark;
             end_mark = token.end_mark;
             if *style == ScalarStyle::Plain && tag.is_none() || tag.as_deref() == Some("!") {
                 plain_implicit = true;
             } else if tag.is_none() {
                 quoted_implicit = true;
             }
-            let event = Event {
-                data: EventData::Scalar {
+            let event = Event::parsed(
+                EventData::Scalar {
                     anchor,
                     tag,
                     value: core::mem::take(value),
                     plain_implicit,
                     quoted_implicit,
                     style: *style,
+                    force_style: None,
+                    anchor_mark,
+                    tag_mark,
+                    value_mark: Some(value_mark),
                 },
                 start_mark,
                 end_mark,
-            };
+            );
+            if borrowed {
+                self.borrowed_value = self.scanner.source_text(value_mark, end_mark);
+            }
             self.state = self.states.pop().unwrap();
             self.scanner.skip_token();
             return Ok(event);
         } else if let TokenData::FlowSequenceStart = &token.data {
             end_mark = token.end_mark;
             self.state = ParserState::FlowSequenceFirstEntry;
-            let event = Event {
-                data: EventData::SequenceStart {
+            let event = Event::parsed(
+                EventData::SequenceStart {
                     anchor,
                     tag,
                     implicit,
                     style: SequenceStyle::Flow,
+                    anchor_mark,
+                    tag_mark,
+                    value_mark: Some(token.start_mark),
                 },
                 start_mark,
                 end_mark,
-            };
+            );
             return Ok(event);
         } else if let TokenData::FlowMappingStart = &token.data {
             end_mark = token.end_mark;
             self.state = ParserState::FlowMappingFirstKey;
-            let event = Event {
-                data: EventData::MappingStart {
+            let event = Event::parsed(
+                EventData::MappingStart {
                     anchor,
                     tag,
                     implicit,
                     style: MappingStyle::Flow,
+                    anchor_mark,
+                    tag_mark,
+                    value_mark: Some(token.start_mark),
                 },
                 start_mark,
                 end_mark,
-            };
+            );
             return Ok(event);
         } else if block && matches!(token.data, TokenData::BlockSequenceStart) {
             end_mark = token.end_mark;
             self.state = ParserState::BlockSequenceFirstEntry;
-            let event = Event {
-                data: EventData::SequenceStart {
+            let event = Event::parsed(
+                EventData::SequenceStart {
                     anchor,
                     tag,
                     implicit,
                     style: SequenceStyle::Block,
+                    anchor_mark,
+                    tag_mark,
+                    value_mark: Some(token.start_mark),
                 },
                 start_mark,
                 end_mark,
-            };
+            );
             return Ok(event);
         } else if block && matches!(token.data, TokenData::BlockMappingStart) {
             end_mark = token.end_mark;
             self.state = ParserState::BlockMappingFirstKey;
-            let event = Event {
-                data: EventData::MappingStart {
+            let event = Event::parsed(
+                EventData::MappingStart {
                     anchor,
                     tag,
                     implicit,
                     style: MappingStyle::Block,
+                    anchor_mark,
+                    tag_mark,
+                    value_mark: Some(token.start_mark),
                 },
                 start_mark,
                 end_mark,
-            };
+            );
             return Ok(event);
         } else if anchor.is_some() || tag.is_some() {
             self.state = self.states.pop().unwrap();
-            let event = Event {
-                data: EventData::Scalar {
+            let event = Event::parsed(
+                EventData::Scalar {
                     anchor,
                     tag,
                     value: String::new(),
                     plain_implicit: implicit,
                     quoted_implicit: false,
                     style: ScalarStyle::Plain,
+                    force_style: None,
+                    anchor_mark,
+                    tag_mark,
+                    value_mark: Some(end_mark),
                 },
                 start_mark,
                 end_mark,
-            };
+            );
             return Ok(event);
         } else {
             return Err(Error::parser(
@@ -563,17 +1150,13 @@ impl<'r> Parser<'r> {
             token = self.scanner.peek()?;
             if matches!(token.data, TokenData::BlockEntry | TokenData::BlockEnd) {
                 self.state = ParserState::BlockSequenceEntry;
-                Self::process_empty_scalar(mark)
+                self.process_empty_scalar(mark)
             } else {
                 self.states.push(ParserState::BlockSequenceEntry);
                 self.parse_node(true, false)
             }
         } else if let TokenData::BlockEnd = token.data {
-            let event = Event {
-                data: EventData::SequenceEnd,
-                start_mark: token.start_mark,
-                end_mark: token.end_mark,
-            };
+            let event = Event::parsed(EventData::SequenceEnd, token.start_mark, token.end_mark);
             self.state = self.states.pop().unwrap();
             let _ = self.marks.pop();
             self.scanner.skip_token();
@@ -602,17 +1185,13 @@ impl<'r> Parser<'r> {
                 TokenData::BlockEntry | TokenData::Key | TokenData::Value | TokenData::BlockEnd
             ) {
                 self.state = ParserState::IndentlessSequenceEntry;
-                Self::process_empty_scalar(mark)
+                self.process_empty_scalar(mark)
             } else {
                 self.states.push(ParserState::IndentlessSequenceEntry);
                 self.parse_node(true, false)
             }
         } else {
-            let event = Event {
-                data: EventData::SequenceEnd,
-                start_mark: token.start_mark,
-                end_mark: token.end_mark,
-            };
+            let event = Event::parsed(EventData::SequenceEnd, token.start_mark, token.end_mark);
             self.state = self.states.pop().unwrap();
             Ok(event)
         }
@@ -636,17 +1215,13 @@ impl<'r> Parser<'r> {
                 TokenData::Key | TokenData::Value | TokenData::BlockEnd
             ) {
                 self.state = ParserState::BlockMappingValue;
-                Self::process_empty_scalar(mark)
+                self.process_empty_scalar(mark)
             } else {
                 self.states.push(ParserState::BlockMappingValue);
                 self.parse_node(true, true)
             }
         } else if let TokenData::BlockEnd = token.data {
-            let event = Event {
-                data: EventData::MappingEnd,
-                start_mark: token.start_mark,
-                end_mark: token.end_mark,
-            };
+            let event = Event::parsed(EventData::MappingEnd, token.start_mark, token.end_mark);
             self.state = self.states.pop().unwrap();
             _ = self.marks.pop();
             self.scanner.skip_token();
@@ -674,7 +1249,7 @@ impl<'r> Parser<'r> {
                 TokenData::Key | TokenData::Value | TokenData::BlockEnd
             ) {
                 self.state = ParserState::BlockMappingKey;
-                Self::process_empty_scalar(mark)
+                self.process_empty_scalar(mark)
             } else {
                 self.states.push(ParserState::BlockMappingKey);
                 self.parse_node(true, true)
@@ -682,7 +1257,7 @@ impl<'r> Parser<'r> {
         } else {
             let mark = token.start_mark;
             self.state = ParserState::BlockMappingKey;
-            Self::process_empty_scalar(mark)
+            self.process_empty_scalar(mark)
         }
     }
 
@@ -712,16 +1287,19 @@ impl<'r> Parser<'r> {
                 }
             }
             if let TokenData::Key = token.data {
-                let event = Event {
-                    data: EventData::MappingStart {
+                let event = Event::parsed(
+                    EventData::MappingStart {
                         anchor: None,
                         tag: None,
                         implicit: true,
                         style: MappingStyle::Flow,
+                        anchor_mark: None,
+                        tag_mark: None,
+                        value_mark: Some(token.start_mark),
                     },
-                    start_mark: token.start_mark,
-                    end_mark: token.end_mark,
-                };
+                    token.start_mark,
+                    token.end_mark,
+                );
                 self.state = ParserState::FlowSequenceEntryMappingKey;
                 self.scanner.skip_token();
                 return Ok(event);
@@ -730,11 +1308,7 @@ impl<'r> Parser<'r> {
                 return self.parse_node(false, false);
             }
         }
-        let event = Event {
-            data: EventData::SequenceEnd,
-            start_mark: token.start_mark,
-            end_mark: token.end_mark,
-        };
+        let event = Event::parsed(EventData::SequenceEnd, token.start_mark, token.end_mark);
         self.state = self.states.pop().unwrap();
         _ = self.marks.pop();
         self.scanner.skip_token();
@@ -750,7 +1324,7 @@ impl<'r> Parser<'r> {
             let mark: Mark = token.end_mark;
             self.scanner.skip_token();
             self.state = ParserState::FlowSequenceEntryMappingValue;
-            Self::process_empty_scalar(mark)
+            self.process_empty_scalar(mark)
         } else {
             self.states.push(ParserState::FlowSequenceEntryMappingValue);
             self.parse_node(false, false)
@@ -772,7 +1346,7 @@ impl<'r> Parser<'r> {
         }
         let mark = token.start_mark;
         self.state = ParserState::FlowSequenceEntryMappingEnd;
-        Self::process_empty_scalar(mark)
+        self.process_empty_scalar(mark)
     }
 
     fn parse_flow_sequence_entry_mapping_end(&mut self) -> Result<Event> {
@@ -780,11 +1354,7 @@ impl<'r> Parser<'r> {
         let start_mark = token.start_mark;
         let end_mark = token.end_mark;
         self.state = ParserState::FlowSequenceEntry;
-        Ok(Event {
-            data: EventData::MappingEnd,
-            start_mark,
-            end_mark,
-        })
+        Ok(Event::parsed(EventData::MappingEnd, start_mark, end_mark))
     }
 
     fn parse_flow_mapping_key(&mut self, first: bool) -> Result<Event> {
@@ -824,17 +1394,13 @@ impl<'r> Parser<'r> {
                 }
                 let mark = token.start_mark;
                 self.state = ParserState::FlowMappingValue;
-                return Self::process_empty_scalar(mark);
+                return self.process_empty_scalar(mark);
             } else if !matches!(token.data, TokenData::FlowMappingEnd) {
                 self.states.push(ParserState::FlowMappingEmptyValue);
                 return self.parse_node(false, false);
             }
         }
-        let event = Event {
-            data: EventData::MappingEnd,
-            start_mark: token.start_mark,
-            end_mark: token.end_mark,
-        };
+        let event = Event::parsed(EventData::MappingEnd, token.start_mark, token.end_mark);
         self.state = self.states.pop().unwrap();
         _ = self.marks.pop();
         self.scanner.skip_token();
@@ -846,7 +1412,7 @@ impl<'r> Parser<'r> {
         if empty {
             let mark = token.start_mark;
             self.state = ParserState::FlowMappingKey;
-            return Self::process_empty_scalar(mark);
+            return self.process_empty_scalar(mark);
         }
         if let TokenData::Value = token.data {
             self.scanner.skip_token();
@@ -858,22 +1424,27 @@ impl<'r> Parser<'r> {
         }
         let mark = token.start_mark;
         self.state = ParserState::FlowMappingKey;
-        Self::process_empty_scalar(mark)
+        self.process_empty_scalar(mark)
     }
 
-    fn process_empty_scalar(mark: Mark) -> Result<Event> {
-        Ok(Event {
-            data: EventData::Scalar {
+    fn process_empty_scalar(&self, mark: Mark) -> Result<Event> {
+        let tag = self.tag_empty_scalars.then(|| String::from(NULL_TAG));
+        Ok(Event::parsed(
+            EventData::Scalar {
+                plain_implicit: tag.is_none(),
                 anchor: None,
-                tag: None,
+                tag,
                 value: String::new(),
-                plain_implicit: true,
                 quoted_implicit: false,
                 style: ScalarStyle::Plain,
+                force_style: None,
+                anchor_mark: None,
+                tag_mark: None,
+                value_mark: Some(mark),
             },
-            start_mark: mark,
-            end_mark: mark,
-        })
+            mark,
+            mark,
+        ))
     }
 
     fn process_directives(
@@ -881,20 +1452,9 @@ impl<'r> Parser<'r> {
         version_directive_ref: Option<&mut Option<VersionDirective>>,
         tag_directives_ref: Option<&mut Vec<TagDirective>>,
     ) -> Result<()> {
-        let default_tag_directives: [TagDirective; 2] = [
-            // TODO: Get rid of these heap allocations.
-            TagDirective {
-                handle: String::from("!"),
-                prefix: String::from("!"),
-            },
-            TagDirective {
-                handle: String::from("!!"),
-                prefix: String::from("tag:yaml.org,2002:"),
-            },
-        ];
         let mut version_directive: Option<VersionDirective> = None;
 
-        let mut tag_directives = Vec::with_capacity(16);
+        let mut tag_directives = Vec::new();
 
         let mut token = self.scanner.peek_mut()?;
 
@@ -933,7 +1493,7 @@ impl<'r> Parser<'r> {
                     prefix: core::mem::take(prefix),
                 };
                 let mark = token.start_mark;
-                self.append_tag_directive(value.clone(), false, mark)?;
+                self.append_tag_directive(&value, mark)?;
 
                 tag_directives.push(value);
             }
@@ -942,11 +1502,6 @@ impl<'r> Parser<'r> {
             token = self.scanner.peek_mut()?;
         }
 
-        let start_mark = token.start_mark;
-        for default_tag_directive in default_tag_directives {
-            self.append_tag_directive(default_tag_directive, true, start_mark)?;
-        }
-
         if let Some(version_directive_ref) = version_directive_ref {
             *version_directive_ref = version_directive;
         }
@@ -964,17 +1519,11 @@ impl<'r> Parser<'r> {
         Ok(())
     }
 
-    fn append_tag_directive(
-        &mut self,
-        value: TagDirective,
-        allow_duplicates: bool,
-        mark: Mark,
-    ) -> Result<()> {
-        for tag_directive in &self.tag_directives {
+    /// Define a TAG directive for the current document, copying it into an
+    /// entry kept from an earlier document if there is one.
+    fn append_tag_directive(&mut self, value: &TagDirective, mark: Mark) -> Result<()> {
+        for tag_directive in &self.tag_directives[..self.tag_directive_count] {
             if value.handle == tag_directive.handle {
-                if allow_duplicates {
-                    return Ok(());
-                }
                 return Err(Error::parser(
                     "",
                     Mark::default(),
@@ -983,11 +1532,923 @@ impl<'r> Parser<'r> {
                 ));
             }
         }
-        self.tag_directives.push(value);
+        if let Some(tag_directive) = self.tag_directives.get_mut(self.tag_directive_count) {
+            tag_directive.handle.clone_from(&value.handle);
+            tag_directive.prefix.clone_from(&value.prefix);
+        } else {
+            self.tag_directives.push(value.clone());
+        }
+        self.tag_directive_count += 1;
         Ok(())
     }
 
     pub(crate) fn delete_aliases(&mut self) {
         self.aliases.clear();
     }
+
+    /// Take the anchors registered while loading a document, in the order
+    /// they were defined, appending them to `anchors`.
+    pub(crate) fn take_anchors(&mut self, mut anchors: Vec<(String, i32)>) -> Vec<(String, i32)> {
+        anchors.extend(
+            self.aliases
+                .drain(..)
+                .map(|alias_data| (alias_data.anchor, alias_data.index)),
+        );
+        anchors
+    }
+}
+
+/// Parse all the events of an input stream.
+///
+/// This never panics, whatever the input, and is meant as the entry point for
+/// fuzzing. Malformed input is reported as an error, including input that is
+/// not valid UTF-8 or UTF-16.
+pub fn parse_all(input: &[u8]) -> Result<Vec<Event>> {
+    let mut input = input;
+    let mut parser = Parser::new();
+    parser.set_input_string(&mut input);
+    let mut events = Vec::new();
+    loop {
+        let event = parser.parse()?;
+        let done = matches!(event.data, EventData::StreamEnd);
+        events.push(event);
+        if done {
+            return Ok(events);
+        }
+    }
+}
+
+/// Parse the input stream and list every event, one per line, for debugging.
+///
+/// Each event is formatted with its [`Display`](core::fmt::Display)
+/// implementation, and indented by its nesting depth.
+#[cfg(feature = "std")]
+pub fn debug_events<R: std::io::BufRead>(mut reader: R) -> Result<String> {
+    let mut parser = Parser::new();
+    parser.set_input(&mut reader);
+    let mut listing = String::new();
+    let mut depth = 0_usize;
+    loop {
+        let event = parser.parse()?;
+        if matches!(
+            event.data,
+            EventData::StreamEnd
+                | EventData::DocumentEnd { .. }
+                | EventData::SequenceEnd
+                | EventData::MappingEnd
+        ) {
+            depth = depth.saturating_sub(1);
+        }
+        for _ in 0..depth {
+            listing.push_str("  ");
+        }
+        listing.push_str(&event.to_string());
+        listing.push('\n');
+        match event.data {
+            EventData::StreamEnd => return Ok(listing),
+            EventData::StreamStart { .. }
+            | EventData::DocumentStart { .. }
+            | EventData::SequenceStart { .. }
+            | EventData::MappingStart { .. } => depth += 1,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn count_documents() {
+        let mut parser = Parser::from_str("a: 1\n---\nb: 2\n...\n--- [c]\n");
+        assert_eq!(parser.count_documents().unwrap(), 3);
+
+        let mut parser = Parser::from_str("");
+        assert_eq!(parser.count_documents().unwrap(), 0);
+
+        let mut parser = Parser::from_str("---\na: 1\n---\n[b, c\n");
+        assert!(parser.count_documents().is_err());
+    }
+
+    #[test]
+    fn documents() {
+        let mut parser = Parser::from_str("a: 1\n---\n- b\n...\n--- c\n");
+        let roots = parser
+            .documents()
+            .map(|document| {
+                let mut document = document.unwrap();
+                format!("{:?}", document.get_root_node().unwrap().data)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(roots.len(), 3);
+        assert!(roots[2].contains("\"c\""));
+        assert!(parser.documents().next().is_none());
+
+        let mut parser = Parser::from_str("");
+        assert!(parser.documents().next().is_none());
+
+        let mut parser = Parser::from_str("a\n--- [b\n--- c\n");
+        let mut documents = parser.documents();
+        assert!(documents.next().unwrap().is_ok());
+        assert!(documents.next().unwrap().is_err());
+        assert!(documents.next().is_none());
+
+        let mut input = &b"- x\n--- y\n"[..];
+        let mut parser = Parser::from_str("a\n--- b\n");
+        assert!(parser.documents().next().unwrap().is_ok());
+        parser.reset_input();
+        parser.set_input_string(&mut input);
+        assert_eq!(parser.documents().count(), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn constructors() {
+        let events = |parser: Parser| {
+            parser
+                .map(|event| format!("{:?}", event.unwrap().data))
+                .collect::<Vec<_>>()
+        };
+        let input = String::from("a: [b, c]\n");
+        let from_str = events(Parser::from_str(&input));
+        assert_eq!(from_str.len(), 11);
+
+        let mut reader = std::io::BufReader::new(input.as_bytes());
+        assert_eq!(events(Parser::from_reader(&mut reader)), from_str);
+    }
+
+    #[test]
+    fn scalar_styles() {
+        let styles = |input: &str| {
+            Parser::from_str(input)
+                .filter_map(|event| match event.unwrap().data {
+                    EventData::Scalar { style, .. } => Some(style),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let all = [
+            ScalarStyle::Plain,
+            ScalarStyle::SingleQuoted,
+            ScalarStyle::DoubleQuoted,
+            ScalarStyle::Literal,
+            ScalarStyle::Folded,
+        ];
+        assert_eq!(
+            styles("- plain\n- 'single'\n- \"double\"\n- |\n  literal\n- >\n  folded\n"),
+            all
+        );
+        assert_eq!(
+            styles(
+                "plain: a\n'single': b\n\"double\": c\n? |\n  literal\n: d\n? >\n  folded\n: e\n"
+            )
+            .into_iter()
+            .step_by(2)
+            .collect::<Vec<_>>(),
+            all
+        );
+        assert_eq!(styles("[plain, 'single', \"double\"]"), all[..3]);
+        assert_eq!(styles("{plain: 'single', \"double\": ''}")[..3], all[..3]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn debug_events_listing() {
+        let listing = debug_events("a: &x [1, 'b']\nc: *x\n".as_bytes()).unwrap();
+        assert_eq!(
+            listing,
+            "STREAM-START encoding=utf-8 @1:1
+  DOCUMENT-START implicit @1:1
+    MAPPING-START style=block @1:1
+      SCALAR value=\"a\" style=plain @1:1
+      SEQUENCE-START anchor=&x style=flow @1:4
+        SCALAR value=\"1\" style=plain @1:8
+        SCALAR value=\"b\" style=single-quoted @1:11
+      SEQUENCE-END @1:14
+      SCALAR value=\"c\" style=plain @2:1
+      ALIAS *x @2:4
+    MAPPING-END @3:1
+  DOCUMENT-END implicit @3:1
+STREAM-END @3:1
+"
+        );
+
+        let event = Event::scalar(
+            None,
+            Some(crate::INT_TAG),
+            "1",
+            false,
+            false,
+            ScalarStyle::Any,
+        );
+        assert_eq!(
+            event.to_string(),
+            "SCALAR value=\"1\" tag=!!int style=any @1:1"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn max_input_len() {
+        const INPUT: &str = "- aaaa\n- bbbb\n";
+        let mut parser = Parser::from_str(INPUT);
+        parser.set_max_input_len(INPUT.len());
+        assert_eq!(parser.count_documents().unwrap(), 1);
+
+        let mut input = std::io::BufReader::with_capacity(4, std::io::repeat(b'a'));
+        let mut parser = Parser::from_reader(&mut input);
+        parser.set_max_input_len(64);
+        let err = parser.count_documents().unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Reader);
+        assert_eq!(err.problem(), "input exceeds the maximum length");
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[test]
+    fn slice_source() {
+        let mut input = &b"\xff\xfea\0:\0 \0b\0"[..];
+        let mut parser = Parser::new();
+        parser.set_input_string(&mut input);
+        assert_eq!(parser.count_documents().unwrap(), 1);
+        drop(parser);
+        assert!(input.is_empty());
+
+        // Without `std`, a slice that ends too early is a reader error rather
+        // than an I/O error.
+        let err = super::parse_all(b"\xef\xbb").unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Reader);
+        assert_eq!(err.problem(), "unexpected end of input");
+    }
+
+    #[test]
+    fn detect_encoding() {
+        for (prefix, encoding) in [
+            (&b""[..], Encoding::Any),
+            (b"a", Encoding::Utf8),
+            (b"\xef\xbb\xbfa", Encoding::Utf8),
+            (b"\xff\xfea\0", Encoding::Utf16Le),
+            (b"\xfe\xff\0a", Encoding::Utf16Be),
+            (b"a\0", Encoding::Utf16Le),
+            (b"\0a", Encoding::Utf16Be),
+            (b"\0\0", Encoding::Utf8),
+        ] {
+            assert_eq!(crate::detect_encoding(prefix), encoding, "{prefix:?}");
+        }
+
+        let mut parser = Parser::from_str("a: b");
+        assert_eq!(parser.detected_encoding(), None);
+        parser.parse().unwrap();
+        assert_eq!(parser.detected_encoding(), Some(Encoding::Utf8));
+
+        for (input, encoding) in [
+            (&b"a\0:\0 \0b\0"[..], Encoding::Utf16Le),
+            (b"\xfe\xff\0a\0:\0 \0b", Encoding::Utf16Be),
+        ] {
+            let events = super::parse_all(input).unwrap();
+            assert_eq!(
+                events[0].data,
+                EventData::StreamStart { encoding },
+                "{input:?}"
+            );
+            assert_eq!(events.len(), 8);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn detect_encoding_from_short_reads() {
+        let utf16 = |input: &str, big_endian: bool| -> Vec<u8> {
+            input
+                .encode_utf16()
+                .flat_map(|unit| {
+                    if big_endian {
+                        unit.to_be_bytes()
+                    } else {
+                        unit.to_le_bytes()
+                    }
+                })
+                .collect()
+        };
+        for input in [
+            b"a: b".to_vec(),
+            "\u{e9}: \u{1f600}".into(),
+            b"\xef\xbb\xbfa".to_vec(),
+            b"a".to_vec(),
+            b"".to_vec(),
+            utf16("a: \u{e9}", false),
+            utf16("a: \u{1f600}", true),
+            utf16("\u{feff}a", false),
+            utf16("\u{e9}", true),
+        ] {
+            let expected = format!("{:?}", super::parse_all(&input));
+            let mut reader = std::io::BufReader::with_capacity(1, &input[..]);
+            let mut parser = Parser::from_reader(&mut reader);
+            let events: Result<Vec<Event>> = core::iter::from_fn(|| Some(parser.parse()))
+                .scan(false, |done, event| {
+                    if *done {
+                        return None;
+                    }
+                    *done = matches!(
+                        event,
+                        Ok(Event {
+                            data: EventData::StreamEnd,
+                            ..
+                        }) | Err(_)
+                    );
+                    Some(event)
+                })
+                .collect();
+            assert_eq!(format!("{events:?}"), expected, "{input:?}");
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn max_scalar_len() {
+        for input in [
+            "abcd",
+            "'abcd'",
+            "\"ab\\x63d\"",
+            "|\n abcd\n",
+            "- a\n  b c\n",
+        ] {
+            let mut parser = Parser::from_str(input);
+            parser.set_max_scalar_len(5);
+            assert_eq!(parser.count_documents().unwrap(), 1, "{input:?}");
+        }
+        for input in [
+            "abcdef",
+            "'abc  def'",
+            "\"abc\\n\n\n\n\"",
+            "|+\n abcd\n\n",
+            "[a,\n b\n\n\n\n\n c]",
+        ] {
+            let mut parser = Parser::from_str(input);
+            parser.set_max_scalar_len(5);
+            let err = parser.count_documents().unwrap_err();
+            assert_eq!(err.kind(), crate::ErrorKind::Scanner, "{input:?}");
+            assert_eq!(
+                err.problem(),
+                "found a scalar longer than the maximum length"
+            );
+        }
+
+        let mut input =
+            std::io::BufReader::new(std::io::Read::take(std::io::repeat(b'a'), 100 << 20));
+        let mut parser = Parser::from_reader(&mut input);
+        parser.set_max_scalar_len(1 << 20);
+        let err = parser.count_documents().unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Scanner);
+        assert_eq!(err.context(), Some("while scanning a plain scalar"));
+        let mark = err.problem_mark().unwrap();
+        assert_eq!((mark.line, mark.column), (0, (1 << 20) + 1));
+    }
+
+    #[test]
+    fn render_snippet() {
+        const TABS: &str = "a: 1\n\tb: 1\n";
+        const UNICODE: &str = "\u{e9}t\u{e9}: a: b\n";
+
+        let err = Parser::from_str(TABS).count_documents().unwrap_err();
+        assert_eq!(
+            err.render_snippet(TABS),
+            format!("{err}\n  |\n2 |     b: 1\n  | ^")
+        );
+
+        let err = Parser::from_str(UNICODE).count_documents().unwrap_err();
+        assert_eq!(
+            err.render_snippet(UNICODE),
+            format!("{err}\n  |\n1 | \u{e9}t\u{e9}: a: b\n  |       ^")
+        );
+    }
+
+    #[test]
+    fn default_tag_directives() {
+        const INPUT: &str = "- !!int 1\n- !local a\n";
+        const DEFINED: &str = "%TAG !! tag:example.com,2000:\n---\n- !!int 1\n";
+
+        let mut parser = Parser::from_str(INPUT);
+        assert_eq!(parser.count_documents().unwrap(), 1);
+
+        let mut parser = Parser::from_str(INPUT);
+        parser.set_default_tag_directives(false);
+        let err = parser.count_documents().unwrap_err();
+        assert_eq!(err.problem(), "found undefined tag handle");
+
+        // The directives of a document take precedence over the default ones,
+        // which apply again in the next document.
+        for enabled in [true, false] {
+            let mut parser = Parser::from_str(DEFINED);
+            parser.set_default_tag_directives(enabled);
+            let tags: Vec<_> = parser
+                .filter_map(|event| match event.unwrap().data {
+                    EventData::Scalar { tag, .. } => tag,
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(tags, ["tag:example.com,2000:int"]);
+        }
+        let parser = Parser::from_str("%TAG !! tag:example.com,2000:\n--- !!int 1\n--- !!int 2\n");
+        let tags: Vec<_> = parser
+            .filter_map(|event| match event.unwrap().data {
+                EventData::Scalar { tag, .. } => tag,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tags, ["tag:example.com,2000:int", crate::INT_TAG]);
+    }
+
+    #[test]
+    fn require_explicit_document_start() {
+        let count = |input: &str| {
+            let mut parser = Parser::from_str(input);
+            parser.set_require_explicit_document_start(true);
+            parser.count_documents()
+        };
+        assert_eq!(count("--- a\n--- b\n").unwrap(), 2);
+        assert_eq!(count("%YAML 1.2\n--- a\n...\n").unwrap(), 1);
+        assert_eq!(count("").unwrap(), 0);
+        assert_eq!(count("# comment\n").unwrap(), 0);
+        let err = count("a: 1\n--- b\n").unwrap_err();
+        assert_eq!(err.problem(), "did not find expected <document start>");
+        assert_eq!(err.problem_mark().unwrap().line, 0);
+        assert!(count("--- a\n...\nb\n").is_err());
+    }
+
+    #[test]
+    fn stats() {
+        let mut parser = Parser::from_str("a:\n  - [b, {c: d}]\n");
+        assert_eq!(parser.stats(), ParserStats::default());
+        let mut max_open = 0;
+        while let Some(event) = parser.next() {
+            event.unwrap();
+            let stats = parser.stats();
+            max_open = max_open.max(stats.open_collections);
+        }
+        let stats = parser.stats();
+        assert_eq!(max_open, 4);
+        assert_eq!(stats.open_collections, 0);
+        assert_eq!(stats.flow_level, 0);
+        assert_eq!(stats.indent, -1);
+        assert_eq!(stats.tokens_parsed, 20);
+    }
+
+    #[test]
+    fn reject_stray_bom() {
+        let inputs = ["--- a\n\u{feff}--- b\n", "key: va\u{feff}lue\n"];
+        for input in inputs {
+            let mut parser = Parser::from_str(input);
+            assert!(parser.count_documents().is_ok());
+        }
+
+        let mut parser = Parser::from_str(inputs[0]);
+        parser.set_reject_stray_bom(true);
+        let err = parser.count_documents().unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Scanner);
+        assert_eq!(
+            err.problem(),
+            "found a byte order mark after the start of the stream"
+        );
+        let mark = err.problem_mark().unwrap();
+        assert_eq!((mark.index, mark.line, mark.column), (6, 1, 0));
+
+        let mut parser = Parser::from_str(inputs[1]);
+        parser.set_reject_stray_bom(true);
+        let mark = parser
+            .count_documents()
+            .unwrap_err()
+            .problem_mark()
+            .unwrap();
+        assert_eq!((mark.index, mark.line, mark.column), (7, 0, 7));
+
+        let mut input: &[u8] = b"\xef\xbb\xbf- a\r\n- b\n";
+        let mut parser = Parser::new();
+        parser.set_input_string(&mut input);
+        parser.set_reject_stray_bom(true);
+        assert_eq!(parser.count_documents().unwrap(), 1);
+
+        let mut parser = Parser::from_str("\u{feff}- a\n");
+        parser.set_encoding(crate::Encoding::Utf8);
+        parser.set_reject_stray_bom(true);
+        assert_eq!(parser.count_documents().unwrap(), 1);
+    }
+
+    #[test]
+    fn tag_empty_scalars() {
+        let tags = |tag_empty_scalars| {
+            let mut parser = Parser::from_str("a:\nb: ''\nc: !!str\n?\n: d\n");
+            parser.set_tag_empty_scalars(tag_empty_scalars);
+            let mut tags = Vec::new();
+            loop {
+                match parser.parse().unwrap().data {
+                    EventData::Scalar { tag, .. } => tags.push(tag),
+                    EventData::StreamEnd => return tags,
+                    _ => {}
+                }
+            }
+        };
+
+        let null = Some(String::from(NULL_TAG));
+        let str = Some(String::from("tag:yaml.org,2002:str"));
+        assert_eq!(
+            tags(false),
+            [None, None, None, None, None, str.clone(), None, None]
+        );
+        assert_eq!(
+            tags(true),
+            [None, null.clone(), None, None, None, str, null, None]
+        );
+    }
+
+    #[test]
+    fn parse_all() {
+        let scalars = |input: &[u8]| -> Vec<String> {
+            super::parse_all(input)
+                .unwrap()
+                .into_iter()
+                .filter_map(|event| match event.data {
+                    EventData::Scalar { value, .. } => Some(value),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(scalars(b">\n  a\n  b"), ["a b"]);
+        assert_eq!(scalars(b"[!t , a]"), ["", "a"]);
+        assert_eq!(scalars(b"[!t, a]"), ["", "a"]);
+
+        let mut utf16 = vec![0xFF, 0xFE];
+        for unit in "a: [b, \u{1F600}]\n".encode_utf16() {
+            utf16.extend(unit.to_le_bytes());
+        }
+        assert_eq!(scalars(&utf16), ["a", "b", "\u{1F600}"]);
+
+        let err = super::parse_all(b"\"a\\").unwrap_err();
+        assert_eq!(err.problem(), "found unknown escape character");
+        let err = super::parse_all(&[0xFF, 0xFE, b'a', 0, 0x00, 0xDC, b'b', 0]).unwrap_err();
+        assert_eq!(err.problem(), "invalid UTF-16");
+    }
+
+    #[test]
+    fn initial_position() {
+        let mut parser = Parser::from_str("a: 1\nb: [x, y]\n");
+        parser.set_initial_position(10, 4, 100);
+        let marks: Vec<_> = parser
+            .filter_map(|event| match event.unwrap() {
+                Event {
+                    data: EventData::Scalar { value, .. },
+                    start_mark,
+                    ..
+                } => Some((value, start_mark.index, start_mark.line, start_mark.column)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            marks,
+            [
+                (String::from("a"), 100, 10, 4),
+                (String::from("1"), 103, 10, 7),
+                (String::from("b"), 105, 11, 0),
+                (String::from("x"), 109, 11, 4),
+                (String::from("y"), 112, 11, 7),
+            ]
+        );
+
+        let mut parser = Parser::from_str("a: 1\nb: [x\n");
+        parser.set_initial_position(10, 4, 100);
+        let err = parser.count_documents().unwrap_err();
+        let mark = err.problem_mark().unwrap();
+        assert_eq!((mark.index, mark.line, mark.column), (111, 12, 0));
+        let mark = err.context_mark().unwrap();
+        assert_eq!((mark.index, mark.line, mark.column), (108, 11, 3));
+
+        let mut parser = Parser::from_str("x: 1\n");
+        parser.set_initial_position(3, 2, 20);
+        let doc = Document::load(&mut parser).unwrap();
+        assert_eq!(doc.nodes[0].start_mark.line, 3);
+        assert_eq!(doc.nodes[0].start_mark.column, 2);
+
+        // The stream end produced again after the end is at the end of the
+        // input too.
+        let mut parser = Parser::from_str("a\n");
+        parser.set_initial_position(3, 2, 20);
+        assert_eq!(parser.count_documents().unwrap(), 1);
+        let mark = parser.parse().unwrap().start_mark;
+        assert_eq!((mark.index, mark.line, mark.column), (22, 4, 0));
+
+        // A new input starts at the default position.
+        parser.reset_input();
+        let mut input = &b"x\n"[..];
+        parser.set_input_string(&mut input);
+        let event = parser.nth(2).unwrap().unwrap();
+        assert_eq!(event.start_mark, Mark::default());
+    }
+
+    #[test]
+    fn property_marks() {
+        let position = |mark: Option<Mark>| mark.map(|mark| (mark.line, mark.column));
+        let property_marks = |event: Event| match event.data {
+            EventData::Scalar {
+                anchor_mark,
+                tag_mark,
+                value_mark,
+                ..
+            }
+            | EventData::SequenceStart {
+                anchor_mark,
+                tag_mark,
+                value_mark,
+                ..
+            }
+            | EventData::MappingStart {
+                anchor_mark,
+                tag_mark,
+                value_mark,
+                ..
+            } => Some((anchor_mark, tag_mark, value_mark)),
+            _ => None,
+        };
+        let marks: Vec<_> =
+            Parser::from_str("- &a !t  value\n- !!map &b\n  x: y\n- &c\n- [k: v]\n")
+                .map(Result::unwrap)
+                .filter_map(property_marks)
+                .map(|(anchor_mark, tag_mark, value_mark)| {
+                    (
+                        position(anchor_mark),
+                        position(tag_mark),
+                        position(value_mark),
+                    )
+                })
+                .collect();
+        assert_eq!(
+            marks,
+            [
+                (None, None, Some((0, 0))),
+                (Some((0, 2)), Some((0, 5)), Some((0, 9))),
+                (Some((1, 8)), Some((1, 2)), Some((2, 2))),
+                (None, None, Some((2, 2))),
+                (None, None, Some((2, 5))),
+                (Some((3, 2)), None, Some((3, 4))),
+                (None, None, Some((4, 2))),
+                (None, None, Some((4, 3))),
+                (None, None, Some((4, 3))),
+                (None, None, Some((4, 6))),
+            ]
+        );
+
+        let mut parser = Parser::from_str("&a b");
+        parser.set_initial_position(5, 1, 50);
+        let (anchor_mark, _, value_mark) = property_marks(parser.nth(2).unwrap().unwrap()).unwrap();
+        let (anchor_mark, value_mark) = (anchor_mark.unwrap(), value_mark.unwrap());
+        assert_eq!((anchor_mark.index, anchor_mark.column), (50, 1));
+        assert_eq!((value_mark.index, value_mark.column), (53, 4));
+    }
+
+    #[test]
+    fn reset_input() {
+        let [mut first, mut second, mut third, mut long] = [
+            "- a\n- b\n",
+            "x\n--- y\n",
+            "- !!int 1\n",
+            "[aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa]",
+        ]
+        .map(str::as_bytes);
+        let mut parser = Parser::new();
+        parser.set_default_tag_directives(false);
+        parser.set_max_input_len(16);
+
+        parser.set_input_string(&mut first);
+        assert_eq!(parser.count_documents().unwrap(), 1);
+        parser.reset_input();
+        parser.set_input_string(&mut second);
+        assert_eq!(parser.count_documents().unwrap(), 2);
+        parser.reset_input();
+        parser.set_input_string(&mut third);
+        let err = parser.count_documents().unwrap_err();
+        assert_eq!(err.problem(), "found undefined tag handle");
+
+        parser.reset_input();
+        parser.set_input_string(&mut long);
+        let err = parser.count_documents().unwrap_err();
+        assert_eq!(err.problem(), "input exceeds the maximum length");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn validate_utf8() {
+        let scalars = |input: &[u8], validate: bool, capacity: usize| {
+            let mut reader = std::io::BufReader::with_capacity(capacity, input);
+            let mut parser = Parser::new();
+            parser.set_validate_utf8(validate);
+            parser.set_input(&mut reader);
+            let mut values = Vec::new();
+            for event in parser {
+                if let EventData::Scalar { value, .. } = event?.data {
+                    values.push(value);
+                }
+            }
+            Ok::<_, Error>(values)
+        };
+        for capacity in [1, 2, 64] {
+            let err = scalars(b"a: caf\xc3", true, capacity).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Reader error: invalid UTF-8 byte sequence (offset 6, value 195)"
+            );
+            let err = scalars(b"a: caf\xc3x", true, capacity).unwrap_err();
+            assert_eq!(err.problem(), "invalid UTF-8 byte sequence");
+            let err = scalars(b"a: \xed\xa0\x80", true, capacity).unwrap_err();
+            assert_eq!(err.problem(), "invalid UTF-8 byte sequence");
+            let values = scalars(b"a: caf\xc3 \xffx\xe2\x82", false, capacity).unwrap();
+            assert_eq!(values, ["a", "caf\u{fffd} \u{fffd}x\u{fffd}"]);
+        }
+    }
+
+    #[test]
+    fn detected_indent() {
+        let detect = |input: &str| {
+            let mut parser = Parser::from_str(input);
+            parser.count_documents().unwrap();
+            parser.detected_indent()
+        };
+        assert_eq!(detect("a: 1\n"), None);
+        assert_eq!(detect("a:\n- 1\n- 2\n"), None);
+        assert_eq!(
+            detect("a:\n    b:\n        c: 1\n    d:\n      - 1\n"),
+            Some(4)
+        );
+        assert_eq!(detect("- - a\n  - b:\n      c\n- d: {e: [f]}\n"), Some(2));
+        assert_eq!(detect("a:\n  b: 1\nc:\n   d: 1\n"), Some(2));
+    }
+
+    fn feed_in_pieces(input: &[u8], size: usize) -> Result<Vec<Event>> {
+        let mut parser = Parser::new();
+        let mut events = Vec::new();
+        for piece in input.chunks(size) {
+            parser.feed(piece);
+            while let Some(event) = parser.try_parse()? {
+                events.push(event);
+            }
+        }
+        parser.end_input();
+        while let Some(event) = parser.try_parse()? {
+            let end = matches!(event.data, EventData::StreamEnd);
+            events.push(event);
+            if end {
+                break;
+            }
+        }
+        Ok(events)
+    }
+
+    #[test]
+    fn feed() {
+        let input = "%TAG !e! tag:example.com,2000:\n--- !e!map\nkey: &a \"caf\u{e9}\"\nlist: [*a, {x: y}]\ntext: |\n  line \u{1f600}\n\n...\n# end\n";
+        let expected = format!("{:?}", super::parse_all(input.as_bytes()).unwrap());
+        for size in [1, 2, 3, 7, 1000] {
+            let events = feed_in_pieces(input.as_bytes(), size).unwrap();
+            assert_eq!(format!("{events:?}"), expected);
+        }
+
+        let utf16: Vec<u8> = "\u{feff}- a\n- \u{1f600}\n"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let expected = format!("{:?}", super::parse_all(&utf16).unwrap());
+        let events = feed_in_pieces(&utf16, 1).unwrap();
+        assert_eq!(format!("{events:?}"), expected);
+
+        let mut parser = Parser::new();
+        assert!(parser.try_parse().unwrap().is_none());
+        parser.feed(b"- a\n- b");
+        let mut events = Vec::new();
+        while let Some(event) = parser.try_parse().unwrap() {
+            events.push(event);
+        }
+        // The scalar `b` may still continue.
+        assert_eq!(events.len(), 4);
+        parser.end_input();
+        while let Some(event) = parser.try_parse().unwrap() {
+            let end = matches!(event.data, EventData::StreamEnd);
+            events.push(event);
+            if end {
+                break;
+            }
+        }
+        assert_eq!(
+            format!("{events:?}"),
+            format!("{:?}", super::parse_all(b"- a\n- b").unwrap())
+        );
+
+        let err = feed_in_pieces(b"[a, b", 1).unwrap_err();
+        assert_eq!(err.problem(), "did not find expected ',' or ']'");
+        let err = feed_in_pieces(b"a: \xff", 2).unwrap_err();
+        assert_eq!(err.problem(), "invalid UTF-8 byte sequence");
+    }
+
+    #[test]
+    fn feed_long_scalar() {
+        let mut input = b"key: ".to_vec();
+        input.resize(1 << 16, b'x');
+        input.extend_from_slice(b" and more words\nnext: y\n");
+        let mut parser = Parser::new();
+        let mut events = Vec::new();
+        for piece in input.chunks(8) {
+            parser.feed(piece);
+            while let Some(event) = parser.try_parse().unwrap() {
+                events.push(event);
+            }
+            // Rescanning the scalar for each piece would take quadratic time.
+            if piece.iter().all(|&byte| byte == b'x') {
+                assert!(!parser.scanner.push_input().ready());
+            }
+        }
+        parser.end_input();
+        while let Some(event) = parser.try_parse().unwrap() {
+            let end = matches!(event.data, EventData::StreamEnd);
+            events.push(event);
+            if end {
+                break;
+            }
+        }
+        assert_eq!(
+            format!("{events:?}"),
+            format!("{:?}", super::parse_all(&input).unwrap())
+        );
+    }
+
+    #[test]
+    fn feed_complete_documents() {
+        let long = "x".repeat(100);
+        let pieces = [&long[..50], &long[50..], "\n...\n", "--- b\n...\n"];
+        let mut parser = Parser::new();
+        parser.feed(b"--- ");
+        let mut events = Vec::new();
+        let mut fed = String::from("--- ");
+        for piece in pieces {
+            parser.feed(piece.as_bytes());
+            fed.push_str(piece);
+            while let Some(event) = parser.try_parse().unwrap() {
+                events.push(event);
+            }
+            // The events are produced as soon as they would be from the whole
+            // input fed at once.
+            let mut expected = Vec::new();
+            let mut whole = Parser::new();
+            whole.feed(fed.as_bytes());
+            while let Some(event) = whole.try_parse().unwrap() {
+                expected.push(event);
+            }
+            assert_eq!(format!("{events:?}"), format!("{expected:?}"));
+        }
+        assert_eq!(events.len(), 7);
+    }
+
+    #[test]
+    fn parse_borrowed() {
+        let input =
+            "\u{feff}- plain caf\u{e9}\n- folded\n  plain\n- 'quoted'\n- {key: value} # c\n- a  \n";
+        let mut parser = Parser::from_str(input);
+        let mut values = Vec::new();
+        let mut events = Vec::new();
+        loop {
+            let (mut event, value) = parser.parse_borrowed().unwrap();
+            if let EventData::Scalar {
+                value: event_value, ..
+            } = &mut event.data
+            {
+                assert!(event_value.is_empty());
+                values.push((
+                    value.clone().into_owned(),
+                    matches!(value, Cow::Borrowed(_)),
+                ));
+                *event_value = value.into_owned();
+            }
+            let end = matches!(event.data, EventData::StreamEnd);
+            events.push(event);
+            if end {
+                break;
+            }
+        }
+        let borrowed = |value: &str, borrowed| (String::from(value), borrowed);
+        assert_eq!(
+            values,
+            [
+                borrowed("plain caf\u{e9}", true),
+                borrowed("folded plain", false),
+                borrowed("quoted", false),
+                borrowed("key", true),
+                borrowed("value", true),
+                borrowed("a", true),
+            ]
+        );
+        let expected = format!("{:?}", super::parse_all(input.as_bytes()).unwrap());
+        assert_eq!(format!("{events:?}"), expected);
+        let mut parser = Parser::from_str(input);
+        let events: Vec<Event> = core::iter::from_fn(|| Some(parser.parse().unwrap()))
+            .take(events.len())
+            .collect();
+        assert_eq!(format!("{events:?}"), expected);
+    }
 }
@@ -1,23 +1,229 @@
-use std::io::BufRead;
+use alloc::{collections::VecDeque, vec::Vec};
 
-use alloc::collections::VecDeque;
+use crate::{chars::is_flow_indicator, scanner::Scanner, Encoding, Error, Result};
 
-use crate::{scanner::Scanner, Encoding, Error, Result};
-
-const BOM_UTF8: [u8; 3] = [0xef, 0xbb, 0xbf];
+pub(crate) const BOM_UTF8: [u8; 3] = [0xef, 0xbb, 0xbf];
 const BOM_UTF16LE: [u8; 2] = [0xff, 0xfe];
 const BOM_UTF16BE: [u8; 2] = [0xfe, 0xff];
 
-fn yaml_parser_determine_encoding(reader: &mut dyn BufRead) -> Result<Option<Encoding>> {
+/// The operations the reader needs from the input stream.
+///
+/// With the `std` feature, this is implemented for every [`std::io::BufRead`].
+/// Otherwise, the input can only be read from memory.
+pub(crate) trait Source {
+    /// Return the buffered input, reading more if the buffer is empty. An
+    /// empty slice means that the end of the input was reached.
+    fn fill_buf(&mut self) -> Result<&[u8]>;
+
+    /// Mark `amount` bytes of the buffered input as read.
+    fn consume(&mut self, amount: usize);
+
+    /// Read exactly enough bytes to fill `buffer`. `offset` is the offset of
+    /// the first byte, to report when the input ends too early.
+    fn read_exact(&mut self, buffer: &mut [u8], offset: usize) -> Result<()>;
+}
+
+#[cfg(feature = "std")]
+impl<T: std::io::BufRead + ?Sized> Source for T {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        loop {
+            match std::io::BufRead::fill_buf(self) {
+                Ok([]) => return Ok(&[]),
+                Ok(_) => break,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        // The buffer is not empty, so this does not read from the input again.
+        Ok(std::io::BufRead::fill_buf(self)?)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        std::io::BufRead::consume(self, amount);
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8], _offset: usize) -> Result<()> {
+        Ok(std::io::Read::read_exact(self, buffer)?)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Source for &[u8] {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        Ok(self)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        *self = &self[amount..];
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8], offset: usize) -> Result<()> {
+        if self.len() < buffer.len() {
+            return Err(Error::reader("unexpected end of input", offset, -1));
+        }
+        let (read, rest) = self.split_at(buffer.len());
+        buffer.copy_from_slice(read);
+        *self = rest;
+        Ok(())
+    }
+}
+
+/// The problem of the reader error raised when the input fed to a parser with
+/// [`Parser::feed()`](crate::Parser::feed) runs out before its end is marked.
+pub(crate) const INCOMPLETE_INPUT: &str = "more input is needed";
+
+/// The problem of the reader error raised for invalid UTF-8, see
+/// [`Scanner::set_validate_utf8()`].
+const INVALID_UTF8: &str = "invalid UTF-8 byte sequence";
+
+/// The number of bytes of pushed input decoded at once, so that the scanner
+/// does not copy much undecoded input when it rewinds.
+const PUSH_CHUNK_SIZE: usize = 256;
+
+/// Input pushed to the parser in pieces, see
+/// [`Parser::feed()`](crate::Parser::feed).
+#[derive(Default)]
+pub(crate) struct PushInput {
+    /// The bytes fed and not yet dropped.
+    data: Vec<u8>,
+    /// The position of the next byte to read in `data`.
+    pub(crate) pos: usize,
+    /// Has every byte of the input been fed?
+    pub(crate) finished: bool,
+    /// The number of bytes dropped from the start of `data`.
+    dropped: usize,
+    /// The length `data` had when the plain scalar being scanned ran out of
+    /// input, while the bytes fed since only continue it.
+    scalar_end_from: Option<usize>,
+}
+
+impl PushInput {
+    pub(crate) fn extend(&mut self, input: &[u8]) {
+        assert!(!self.finished, "input was fed after its end");
+        self.data.extend_from_slice(input);
+    }
+
+    /// Drop the bytes that were read.
+    pub(crate) fn commit(&mut self) {
+        self.data.drain(..self.pos);
+        self.dropped += self.pos;
+        self.pos = 0;
+        self.scalar_end_from = None;
+    }
+
+    /// Wait for a byte that may end the plain scalar being scanned before
+    /// scanning it again, so that feeding a long scalar in small pieces does
+    /// not rescan it for each piece.
+    ///
+    /// This is only done when every byte fed was read and the last one is a
+    /// whole character.
+    pub(crate) fn wait_for_scalar_end(&mut self) {
+        if self.pos == self.data.len() && self.data.last().is_some_and(u8::is_ascii) {
+            self.scalar_end_from = Some(self.data.len());
+        }
+    }
+
+    /// Has input that may change the outcome of parsing been fed since it
+    /// needed more?
+    pub(crate) fn ready(&mut self) -> bool {
+        let Some(from) = self.scalar_end_from else {
+            return true;
+        };
+        let continued = self.data[from..]
+            .iter()
+            .all(|&byte| continues_plain_scalar(char::from(byte)));
+        if self.finished || !continued {
+            self.scalar_end_from = None;
+            return true;
+        }
+        self.scalar_end_from = Some(self.data.len());
+        false
+    }
+}
+
+/// Check if the character continues a plain scalar whatever follows it, when
+/// it comes after a character other than a line break.
+pub(crate) fn continues_plain_scalar(ch: char) -> bool {
+    ch == ' ' || ch.is_ascii_graphic() && !matches!(ch, ':' | '#' | '?') && !is_flow_indicator(ch)
+}
+
+impl Source for PushInput {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        let available = &self.data[self.pos..];
+        // Wait for enough bytes to tell the encoding of the stream.
+        let needed = if self.dropped + self.pos == 0 { 3 } else { 1 };
+        if available.len() < needed && !self.finished {
+            return Err(Error::reader(INCOMPLETE_INPUT, self.dropped + self.pos, -1));
+        }
+        Ok(&available[..available.len().min(PUSH_CHUNK_SIZE)])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos += amount;
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8], offset: usize) -> Result<()> {
+        let available = &self.data[self.pos..];
+        if available.len() < buffer.len() {
+            let problem = if self.finished {
+                "unexpected end of input"
+            } else {
+                INCOMPLETE_INPUT
+            };
+            return Err(Error::reader(problem, offset, -1));
+        }
+        buffer.copy_from_slice(&available[..buffer.len()]);
+        self.pos += buffer.len();
+        Ok(())
+    }
+}
+
+/// Determine the encoding of the stream from its first bytes.
+///
+/// The reader may buffer a single byte at a time, and more bytes cannot be
+/// looked at without consuming them. The first byte is then consumed to look
+/// at the second one, and the first character is decoded into `out`.
+fn yaml_parser_determine_encoding(
+    reader: &mut dyn Source,
+    out: &mut VecDeque<char>,
+    offset: &mut usize,
+    validate: bool,
+) -> Result<Option<Encoding>> {
     let initial_bytes = reader.fill_buf()?;
     if initial_bytes.is_empty() {
         return Ok(None);
     }
+    if let [initial] = *initial_bytes {
+        if !matches!(initial, 0xef | 0xfe | 0xff) {
+            reader.consume(1);
+            let Some(second) = reader.fill_buf()?.first().copied() else {
+                push_char(out, char::from(initial), *offset)?;
+                *offset += 1;
+                return Ok(Some(detect_encoding(&[initial])));
+            };
+            let encoding = detect_encoding(&[initial, second]);
+            if encoding == Encoding::Utf8 {
+                if initial.is_ascii() {
+                    push_char(out, char::from(initial), *offset)?;
+                    *offset += 1;
+                } else {
+                    read_utf8_char_unbuffered(reader, out, initial, offset, validate)?;
+                }
+            } else {
+                // One of the two bytes is zero, so they encode an ASCII or
+                // Latin-1 character rather than a surrogate.
+                reader.consume(1);
+                push_char(out, char::from(initial | second), *offset)?;
+                *offset += 2;
+            }
+            return Ok(Some(encoding));
+        }
+    }
 
     match initial_bytes[0] {
         0xef => {
             let mut bom = [0; 3];
-            reader.read_exact(&mut bom)?;
+            reader.read_exact(&mut bom, 0)?;
             if bom == BOM_UTF8 {
                 Ok(Some(Encoding::Utf8))
             } else {
@@ -30,7 +236,7 @@ fn yaml_parser_determine_encoding(reader: &mut dyn BufRead) -> Result<Option<Enc
         }
         0xff | 0xfe => {
             let mut bom = [0; 2];
-            reader.read_exact(&mut bom)?;
+            reader.read_exact(&mut bom, 0)?;
             if bom == BOM_UTF16LE {
                 Ok(Some(Encoding::Utf16Le))
             } else if bom == BOM_UTF16BE {
@@ -43,7 +249,23 @@ fn yaml_parser_determine_encoding(reader: &mut dyn BufRead) -> Result<Option<Enc
                 ))
             }
         }
-        _ => Ok(Some(Encoding::Utf8)),
+        _ => Ok(Some(detect_encoding(initial_bytes))),
+    }
+}
+
+/// Detect the encoding of a YAML stream from its first bytes.
+///
+/// A byte order mark selects UTF-8, UTF-16LE or UTF-16BE. Without one, the
+/// stream is read as UTF-16 if exactly one of its first two bytes is zero,
+/// since a YAML stream starts with an ASCII character, and as UTF-8 otherwise.
+/// An empty prefix gives [`Encoding::Any`].
+pub fn detect_encoding(prefix: &[u8]) -> Encoding {
+    match prefix {
+        [] => Encoding::Any,
+        [0xff, 0xfe, ..] | [1..=0xff, 0, ..] => Encoding::Utf16Le,
+        [0xfe, 0xff, ..] | [0, 1..=0xff, ..] => Encoding::Utf16Be,
+        // Including the UTF-8 byte order mark.
+        _ => Encoding::Utf8,
     }
 }
 
@@ -51,18 +273,15 @@ fn yaml_parser_determine_encoding(reader: &mut dyn BufRead) -> Result<Option<Enc
 // a string slice from a stream of UTF-8 bytes.
 #[allow(unsafe_code)]
 fn read_utf8_buffered(
-    reader: &mut dyn BufRead,
+    reader: &mut dyn Source,
     out: &mut VecDeque<char>,
     offset: &mut usize,
+    validate: bool,
 ) -> Result<bool> {
-    let available = loop {
-        match reader.fill_buf() {
-            Ok([]) => return Ok(false),
-            Ok(available) => break available,
-            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
-            Err(err) => return Err(err.into()),
-        }
-    };
+    let available = reader.fill_buf()?;
+    if available.is_empty() {
+        return Ok(false);
+    }
 
     match core::str::from_utf8(available) {
         Ok(valid) => {
@@ -90,11 +309,17 @@ fn read_utf8_buffered(
             }
 
             match err.error_len() {
-                Some(_invalid_len) => Err(Error::reader(
-                    "invalid UTF-8",
+                Some(_) if validate => Err(Error::reader(
+                    INVALID_UTF8,
                     *offset,
                     available[valid_bytes] as _,
                 )),
+                Some(invalid_len) => {
+                    push_char(out, char::REPLACEMENT_CHARACTER, *offset)?;
+                    *offset += invalid_len;
+                    reader.consume(valid_bytes + invalid_len);
+                    Ok(true)
+                }
                 None => {
                     if valid_bytes != 0 {
                         // Some valid UTF-8 characters were present, and the
@@ -105,12 +330,9 @@ fn read_utf8_buffered(
                     } else {
                         // The beginning of the buffer was an incomplete UTF-8
                         // sequence. Read the whole character unbuffered.
-                        //
-                        // This will return `UnexpectedEof` if the sequence
-                        // cannot be completed. Note that `read_exact()` handles
-                        // interrupt automatically.
                         let initial = available[0];
-                        read_utf8_char_unbuffered(reader, out, initial, offset)?;
+                        reader.consume(1);
+                        read_utf8_char_unbuffered(reader, out, initial, offset, validate)?;
                         Ok(true)
                     }
                 }
@@ -119,43 +341,55 @@ fn read_utf8_buffered(
     }
 }
 
+/// Read the character starting with `initial`, which was already consumed.
 fn read_utf8_char_unbuffered(
-    reader: &mut dyn BufRead,
+    reader: &mut dyn Source,
     out: &mut VecDeque<char>,
     initial: u8,
     offset: &mut usize,
+    validate: bool,
 ) -> Result<()> {
     let width = utf8_char_width(initial);
-    let mut buffer = [0; 4];
-    reader.read_exact(&mut buffer[..width])?;
-    if let Ok(valid) = core::str::from_utf8(&buffer[..width]) {
+    let mut buffer = [initial, 0, 0, 0];
+    let mut read = 1;
+    // Read the continuation bytes one by one, stopping at the end of the
+    // input or at a byte that does not continue the sequence.
+    while read < width {
+        let available = reader.fill_buf()?;
+        match available.first() {
+            Some(byte) if byte & 0xC0 == 0x80 => {
+                buffer[read] = *byte;
+                reader.consume(1);
+                read += 1;
+            }
+            _ => break,
+        }
+    }
+    if let Ok(valid) = core::str::from_utf8(&buffer[..read]) {
         // We read a whole, valid character.
         let Some(ch) = valid.chars().next() else {
             unreachable!()
         };
         push_char(out, ch, *offset)?;
-        *offset += width;
-        Ok(())
+    } else if validate {
+        // The sequence is truncated or encodes an invalid code point.
+        return Err(Error::reader(INVALID_UTF8, *offset, buffer[0] as _));
     } else {
-        // Since we read the exact character width, the only
-        // possible error here is invalid Unicode.
-        Err(Error::reader("invalid UTF-8", *offset, buffer[0] as _))
+        push_char(out, char::REPLACEMENT_CHARACTER, *offset)?;
     }
+    *offset += read;
+    Ok(())
 }
 
 fn read_utf16_buffered<const BIG_ENDIAN: bool>(
-    reader: &mut dyn BufRead,
+    reader: &mut dyn Source,
     out: &mut VecDeque<char>,
     offset: &mut usize,
 ) -> Result<bool> {
-    let available = loop {
-        match reader.fill_buf() {
-            Ok([]) => return Ok(false),
-            Ok(available) => break available,
-            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
-            Err(err) => return Err(err.into()),
-        }
-    };
+    let available = reader.fill_buf()?;
+    if available.is_empty() {
+        return Ok(false);
+    }
 
     let chunks = available.chunks_exact(2).map(|chunk| {
         let [a, b] = chunk else { unreachable!() };
@@ -171,7 +405,7 @@ fn read_utf16_buffered<const BIG_ENDIAN: bool>(
         match ch {
             Ok(ch) => {
                 push_char(out, ch, *offset)?;
-                let n = ch.len_utf16();
+                let n = ch.len_utf16() * 2;
                 *offset += n;
                 used += n;
             }
@@ -189,22 +423,21 @@ fn read_utf16_buffered<const BIG_ENDIAN: bool>(
 
     if used != 0 {
         reader.consume(used);
-        *offset += used;
         Ok(true)
     } else {
-        debug_assert!(!available.is_empty() && available.len() < 2);
+        // The buffer holds a single byte or starts with a surrogate.
         read_utf16_char_unbuffered::<BIG_ENDIAN>(reader, out, offset)?;
         Ok(true)
     }
 }
 
 fn read_utf16_char_unbuffered<const BIG_ENDIAN: bool>(
-    reader: &mut dyn BufRead,
+    reader: &mut dyn Source,
     out: &mut VecDeque<char>,
     offset: &mut usize,
 ) -> Result<()> {
     let mut buffer = [0; 2];
-    reader.read_exact(&mut buffer)?;
+    reader.read_exact(&mut buffer, *offset)?;
     let first = if BIG_ENDIAN {
         u16::from_be_bytes(buffer)
     } else {
@@ -212,7 +445,7 @@ fn read_utf16_char_unbuffered<const BIG_ENDIAN: bool>(
     };
 
     if is_utf16_surrogate(first) {
-        reader.read_exact(&mut buffer)?;
+        reader.read_exact(&mut buffer, *offset)?;
         let second = if BIG_ENDIAN {
             u16::from_be_bytes(buffer)
         } else {
@@ -283,12 +516,21 @@ fn push_char(out: &mut VecDeque<char>, ch: char, offset: usize) -> Result<()> {
 }
 
 pub(crate) fn yaml_parser_update_buffer(parser: &mut Scanner, length: usize) -> Result<()> {
-    let reader = parser.read_handler.as_deref_mut().expect("no read handler");
+    let reader = parser
+        .read_handler
+        .as_mut()
+        .expect("no read handler")
+        .as_source();
     if parser.buffer.len() >= length {
         return Ok(());
     }
     if parser.encoding == Encoding::Any {
-        if let Some(encoding) = yaml_parser_determine_encoding(reader)? {
+        if let Some(encoding) = yaml_parser_determine_encoding(
+            reader,
+            &mut parser.buffer,
+            &mut parser.offset,
+            parser.validate_utf8,
+        )? {
             parser.encoding = encoding;
         } else {
             parser.eof = true;
@@ -303,7 +545,12 @@ pub(crate) fn yaml_parser_update_buffer(parser: &mut Scanner, length: usize) ->
 
         let not_eof = match parser.encoding {
             Encoding::Any => unreachable!(),
-            Encoding::Utf8 => read_utf8_buffered(reader, &mut parser.buffer, &mut parser.offset)?,
+            Encoding::Utf8 => read_utf8_buffered(
+                reader,
+                &mut parser.buffer,
+                &mut parser.offset,
+                parser.validate_utf8,
+            )?,
             Encoding::Utf16Le => {
                 read_utf16_buffered::<false>(reader, &mut parser.buffer, &mut parser.offset)?
             }
@@ -315,6 +562,16 @@ pub(crate) fn yaml_parser_update_buffer(parser: &mut Scanner, length: usize) ->
             parser.eof = true;
             return Ok(());
         }
+        if parser
+            .max_input_len
+            .is_some_and(|max_input_len| parser.offset > max_input_len)
+        {
+            return Err(Error::reader(
+                "input exceeds the maximum length",
+                parser.offset,
+                -1,
+            ));
+        }
     }
 
     if parser.offset >= (!0_usize).wrapping_div(2_usize) {
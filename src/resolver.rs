@@ -0,0 +1,154 @@
+//! Resolution of plain scalars according to the YAML 1.2 core schema.
+
+use alloc::{format, string::String};
+
+use crate::{BOOL_TAG, FLOAT_TAG, INT_TAG, NULL_TAG};
+
+/// The kind of node whose tag is being resolved.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum NodeKind {
+    /// A plain scalar.
+    PlainScalar,
+    /// A quoted or block scalar.
+    QuotedScalar,
+    /// A sequence.
+    Sequence,
+    /// A mapping.
+    Mapping,
+}
+
+/// Resolve the tag of a node without an explicit tag using the YAML 1.2 core
+/// schema.
+///
+/// Plain scalars that are nulls, booleans, integers or floating-point numbers
+/// are given the corresponding tag. Returns `None` for other nodes, which the
+/// loader then gives the default tag of their kind. This can be passed to
+/// [`Parser::set_tag_resolver()`](crate::Parser::set_tag_resolver), or called
+/// from a custom resolver.
+pub fn core_schema_tag(value: &str, tag: Option<&str>, kind: NodeKind) -> Option<String> {
+    if kind != NodeKind::PlainScalar || tag.is_some() {
+        return None;
+    }
+    plain_tag(value).map(String::from)
+}
+
+/// Get the core schema tag of a plain scalar without an explicit tag, or
+/// `None` if it is a string.
+pub(crate) fn plain_tag(value: &str) -> Option<&'static str> {
+    if is_null(value) {
+        Some(NULL_TAG)
+    } else if parse_bool(value).is_some() {
+        Some(BOOL_TAG)
+    } else if parse_int(value).is_some() {
+        Some(INT_TAG)
+    } else if parse_float(value).is_some() {
+        Some(FLOAT_TAG)
+    } else {
+        None
+    }
+}
+
+/// Check if the value is a null in the core schema.
+pub(crate) fn is_null(value: &str) -> bool {
+    matches!(value, "" | "~" | "null" | "Null" | "NULL")
+}
+
+/// Parse a boolean in the core schema.
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "True" | "TRUE" => Some(true),
+        "false" | "False" | "FALSE" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse an integer in the core schema, also accepting `_` separators
+/// between digits.
+///
+/// Returns `None` if the value is not an integer or does not fit in an
+/// `i64`.
+pub(crate) fn parse_int(value: &str) -> Option<i64> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        return parse_digits(hex, 16).and_then(|n| i64::try_from(n).ok());
+    }
+    if let Some(octal) = value.strip_prefix("0o") {
+        return parse_digits(octal, 8).and_then(|n| i64::try_from(n).ok());
+    }
+    let (negative, digits) = match value.as_bytes().first() {
+        Some(b'-') => (true, &value[1..]),
+        Some(b'+') => (false, &value[1..]),
+        _ => (false, value),
+    };
+    let magnitude = parse_digits(digits, 10)?;
+    if negative {
+        0_i64.checked_sub_unsigned(magnitude)
+    } else {
+        i64::try_from(magnitude).ok()
+    }
+}
+
+fn parse_digits(digits: &str, radix: u32) -> Option<u64> {
+    if digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') {
+        return None;
+    }
+    let mut result = 0_u64;
+    for ch in digits.chars().filter(|ch| *ch != '_') {
+        let digit = ch.to_digit(radix)?;
+        result = result
+            .checked_mul(u64::from(radix))?
+            .checked_add(u64::from(digit))?;
+    }
+    Some(result)
+}
+
+/// Parse a floating-point number in the core schema.
+///
+/// Note that integers also match, so [`parse_int()`] should be tried first.
+pub(crate) fn parse_float(value: &str) -> Option<f64> {
+    match value {
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => return Some(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => return Some(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => return Some(f64::NAN),
+        _ => {}
+    }
+
+    // [-+]? ( \. [0-9]+ | [0-9]+ ( \. [0-9]* )? ) ( [eE] [-+]? [0-9]+ )?
+    let number = value.strip_prefix(['-', '+']).unwrap_or(value);
+    let (mantissa, exponent) = match number.find(['e', 'E']) {
+        Some(index) => (&number[..index], Some(&number[index + 1..])),
+        None => (number, None),
+    };
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let is_digits = |digits: &str| digits.bytes().all(|ch| ch.is_ascii_digit());
+    let mantissa_ok = is_digits(integer)
+        && fraction.map_or(true, is_digits)
+        && (!integer.is_empty() || fraction.is_some_and(|fraction| !fraction.is_empty()));
+    let exponent_ok = exponent.map_or(true, |exponent| {
+        let digits = exponent.strip_prefix(['-', '+']).unwrap_or(exponent);
+        !digits.is_empty() && is_digits(digits)
+    });
+    if !mantissa_ok || !exponent_ok {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Format a floating-point number so that it resolves back to the same value
+/// in the core schema.
+///
+/// Infinities and NaN are written as `.inf`, `-.inf` and `.nan`, and finite
+/// numbers always have a fraction or an exponent so they are not read as
+/// integers.
+pub(crate) fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        String::from(".nan")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { ".inf" } else { "-.inf" })
+    } else {
+        format!("{value:?}")
+    }
+}
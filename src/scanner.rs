@@ -1,20 +1,49 @@
-use std::collections::VecDeque;
+use alloc::{collections::VecDeque, string::String, vec::Vec};
 
 use crate::macros::{is_blankz, is_break};
-use crate::reader::yaml_parser_update_buffer;
+use crate::reader::{
+    continues_plain_scalar, yaml_parser_update_buffer, PushInput, Source, BOM_UTF8,
+};
 use crate::{
     Encoding, Error, Mark, Result, ScalarStyle, SimpleKey, Token, TokenData, INPUT_BUFFER_SIZE,
 };
 
 const MAX_NUMBER_LENGTH: u64 = 9_u64;
 
+/// The source of the input stream.
+pub(crate) enum ReadHandler<'r> {
+    /// A slice owned by the scanner.
+    Slice(&'r [u8]),
+    /// A slice borrowed from the caller, which is advanced as it is read.
+    SliceRef(&'r mut dyn Source),
+    /// A reader borrowed from the caller.
+    #[cfg(feature = "std")]
+    Reader(&'r mut dyn std::io::BufRead),
+    /// Input fed to the scanner in pieces.
+    Push(PushInput),
+}
+
+impl ReadHandler<'_> {
+    pub(crate) fn as_source(&mut self) -> &mut dyn Source {
+        match self {
+            ReadHandler::Slice(slice) => slice,
+            ReadHandler::SliceRef(slice) => *slice,
+            #[cfg(feature = "std")]
+            ReadHandler::Reader(reader) => reader,
+            ReadHandler::Push(input) => input,
+        }
+    }
+}
+
 /// Given an input stream of bytes, produce a stream of [`Token`]s.
 ///
 /// This is used internally by the parser, and may also be used standalone as a
 /// replacement for the libyaml `yaml_parser_scan()` function.
 pub struct Scanner<'r> {
     /// Read handler.
-    pub(crate) read_handler: Option<&'r mut dyn std::io::BufRead>,
+    pub(crate) read_handler: Option<ReadHandler<'r>>,
+    /// The whole input, if it was set with [`Scanner::set_input_str()`].
+    pub(crate) source: Option<&'r str>,
     /// EOF flag
     pub(crate) eof: bool,
     /// The working buffer.
@@ -23,10 +52,23 @@ pub struct Scanner<'r> {
     pub(crate) buffer: VecDeque<char>,
     /// The input encoding.
     pub(crate) encoding: Encoding,
+    /// The input encoding set with [`Scanner::set_encoding()`], if any.
+    pub(crate) preset_encoding: Encoding,
     /// The offset of the current position (in bytes).
     pub(crate) offset: usize,
+    /// The maximum number of bytes to read from the input.
+    pub(crate) max_input_len: Option<usize>,
+    /// The maximum length of a scalar value in bytes.
+    pub(crate) max_scalar_len: Option<usize>,
+    /// Is a byte order mark after the start of the stream an error?
+    pub(crate) reject_stray_bom: bool,
+    /// Is invalid UTF-8 an error, rather than decoded as U+FFFD?
+    pub(crate) validate_utf8: bool,
     /// The mark of the current position.
     pub(crate) mark: Mark,
+    /// The position of the start of the input in the document containing it,
+    /// whose index and line the marks start from.
+    pub(crate) origin: Mark,
     /// Have we started to scan the input stream?
     pub(crate) stream_start_produced: bool,
     /// Have we reached the end of the input stream?
@@ -43,6 +85,9 @@ pub struct Scanner<'r> {
     pub(crate) indents: Vec<i32>,
     /// The current indentation level.
     pub(crate) indent: i32,
+    /// The number of times each indentation step, from one to nine columns
+    /// deeper than the enclosing block collection, was seen.
+    pub(crate) indent_steps: [usize; 10],
     /// May a simple key occur at the current position?
     pub(crate) simple_key_allowed: bool,
     /// The stack of simple keys.
@@ -53,11 +98,18 @@ impl<'r> Scanner<'r> {
     pub fn new() -> Scanner<'r> {
         Self {
             read_handler: None,
+            source: None,
             eof: false,
             buffer: VecDeque::with_capacity(INPUT_BUFFER_SIZE),
             encoding: Encoding::Any,
+            preset_encoding: Encoding::Any,
             offset: 0,
+            max_input_len: None,
+            max_scalar_len: None,
+            reject_stray_bom: false,
+            validate_utf8: true,
             mark: Mark::default(),
+            origin: Mark::default(),
             stream_start_produced: false,
             stream_end_produced: false,
             flow_level: 0,
@@ -66,6 +118,7 @@ impl<'r> Scanner<'r> {
             token_available: false,
             indents: Vec::with_capacity(16),
             indent: 0,
+            indent_steps: [0; 10],
             simple_key_allowed: false,
             simple_keys: Vec::with_capacity(16),
         }
@@ -74,27 +127,180 @@ impl<'r> Scanner<'r> {
     /// Set a string input.
     pub fn set_input_string(&mut self, input: &'r mut &[u8]) {
         assert!((self.read_handler).is_none());
-        self.read_handler = Some(input);
+        self.read_handler = Some(ReadHandler::SliceRef(input));
+    }
+
+    /// Set a borrowed string input.
+    ///
+    /// Unlike [`Scanner::set_input_string()`], this does not require a mutable
+    /// reference to the input.
+    pub fn set_input_str(&mut self, input: &'r str) {
+        assert!((self.read_handler).is_none());
+        self.read_handler = Some(ReadHandler::Slice(input.as_bytes()));
+        self.source = Some(input);
     }
 
     /// Set a generic input handler.
+    #[cfg(feature = "std")]
     pub fn set_input(&mut self, input: &'r mut dyn std::io::BufRead) {
         assert!((self.read_handler).is_none());
-        self.read_handler = Some(input);
+        self.read_handler = Some(ReadHandler::Reader(input));
+    }
+
+    /// Append bytes to the input, which is then fed in pieces.
+    ///
+    /// The input must not have been set otherwise. Scanning fails with a
+    /// reader error when the bytes fed so far run out, until the end of the
+    /// input is marked with [`Scanner::end_input()`].
+    pub fn feed(&mut self, input: &[u8]) {
+        self.push_input().extend(input);
+    }
+
+    /// Mark the end of the input fed with [`Scanner::feed()`].
+    pub fn end_input(&mut self) {
+        self.push_input().finished = true;
+    }
+
+    pub(crate) fn push_input(&mut self) -> &mut PushInput {
+        let read_handler = self
+            .read_handler
+            .get_or_insert_with(|| ReadHandler::Push(PushInput::default()));
+        match read_handler {
+            ReadHandler::Push(input) => input,
+            _ => panic!("the input was not fed"),
+        }
+    }
+
+    /// Copy the scanning state, without the input, so that the scanner can be
+    /// rewound to it with [`Scanner::restore()`].
+    pub(crate) fn checkpoint(&self) -> Scanner<'r> {
+        Scanner {
+            read_handler: None,
+            source: self.source,
+            eof: self.eof,
+            buffer: self.buffer.clone(),
+            encoding: self.encoding,
+            preset_encoding: self.preset_encoding,
+            offset: self.offset,
+            max_input_len: self.max_input_len,
+            max_scalar_len: self.max_scalar_len,
+            reject_stray_bom: self.reject_stray_bom,
+            validate_utf8: self.validate_utf8,
+            mark: self.mark,
+            origin: self.origin,
+            stream_start_produced: self.stream_start_produced,
+            stream_end_produced: self.stream_end_produced,
+            flow_level: self.flow_level,
+            tokens: self.tokens.clone(),
+            tokens_parsed: self.tokens_parsed,
+            token_available: self.token_available,
+            indents: self.indents.clone(),
+            indent: self.indent,
+            indent_steps: self.indent_steps,
+            simple_key_allowed: self.simple_key_allowed,
+            simple_keys: self.simple_keys.clone(),
+        }
+    }
+
+    /// Rewind to a state saved with [`Scanner::checkpoint()`], keeping the
+    /// input.
+    pub(crate) fn restore(&mut self, checkpoint: Scanner<'r>) {
+        let read_handler = self.read_handler.take();
+        *self = checkpoint;
+        self.read_handler = read_handler;
     }
 
     /// Set the source encoding.
     pub fn set_encoding(&mut self, encoding: Encoding) {
         assert!(self.encoding == Encoding::Any);
         self.encoding = encoding;
+        self.preset_encoding = encoding;
+    }
+
+    /// Set the maximum number of bytes to read from the input.
+    ///
+    /// Reading past the limit fails with a reader error. The input is read in
+    /// chunks, so up to one chunk of the input may be read past the limit
+    /// before the error is reported.
+    pub fn set_max_input_len(&mut self, max_input_len: usize) {
+        self.max_input_len = Some(max_input_len);
+    }
+
+    /// Set the maximum length of a scalar value, in bytes of its UTF-8
+    /// encoding.
+    ///
+    /// Scanning a longer scalar fails with a scanner error as soon as the
+    /// limit is exceeded, so that the value is never held in memory in full.
+    /// The blank lines and spaces after a scalar count towards the limit while
+    /// it is scanned, since they are part of the value if more content
+    /// follows.
+    pub fn set_max_scalar_len(&mut self, max_scalar_len: usize) {
+        self.max_scalar_len = Some(max_scalar_len);
+    }
+
+    /// Set if a byte order mark after the start of the stream is an error.
+    ///
+    /// By default, a byte order mark at the start of a line is skipped and
+    /// one anywhere else is read as part of the content, like libyaml does.
+    /// When enabled, any byte order mark other than the first character of
+    /// the stream fails with a scanner error pointing at it.
+    pub fn set_reject_stray_bom(&mut self, reject: bool) {
+        self.reject_stray_bom = reject;
+    }
+
+    /// Set if a UTF-8 input with an invalid byte sequence is an error.
+    ///
+    /// This is enabled by default: an invalid or truncated byte sequence
+    /// fails with an `invalid UTF-8 byte sequence` reader error at the offset
+    /// of its first byte, as soon as it is decoded. When disabled, each
+    /// invalid sequence is read as U+FFFD REPLACEMENT CHARACTER instead. This
+    /// has no effect on UTF-16 inputs.
+    pub fn set_validate_utf8(&mut self, validate: bool) {
+        self.validate_utf8 = validate;
     }
 
     fn cache(&mut self, length: usize) -> Result<()> {
         if self.buffer.len() >= length {
             Ok(())
         } else {
-            yaml_parser_update_buffer(self, length)
+            let start = self.buffer.len();
+            yaml_parser_update_buffer(self, length)?;
+            if self.reject_stray_bom {
+                self.check_stray_bom(start)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Fail if a byte order mark was decoded into the buffer after `start`.
+    fn check_stray_bom(&self, start: usize) -> Result<()> {
+        let Some(position) = self.buffer.range(start..).position(|&ch| ch == '\u{feff}') else {
+            return Ok(());
+        };
+        let mut mark = self.mark;
+        let mut chars = self.buffer.range(..start + position).peekable();
+        while let Some(&ch) = chars.next() {
+            mark.index += ch.len_utf8() as u64;
+            if ch == '\r' && chars.peek() == Some(&&'\n') {
+                chars.next();
+                mark.index += 1;
+            }
+            if is_break(ch) {
+                mark.column = 0;
+                mark.line += 1;
+            } else {
+                mark.column += 1;
+            }
         }
+        if mark.index == self.origin.index {
+            return Ok(());
+        }
+        Err(Error::scanner(
+            "while reading the stream",
+            self.mark,
+            "found a byte order mark after the start of the stream",
+            mark,
+        ))
     }
 
     /// Equivalent to the libyaml macro `SKIP`.
@@ -136,11 +342,12 @@ impl<'r> Scanner<'r> {
 
     /// Equivalent to the libyaml macro `READ_LINE`.
     fn read_line_break(&mut self, string: &mut String) {
+        // Like `SKIP_LINE`, this does nothing at the end of the input.
         let Some(front) = self.buffer.front().copied() else {
-            panic!("unexpected end of input");
+            return;
         };
 
-        if let Some('\r') = self.buffer.get(1).copied() {
+        if let ('\r', Some('\n')) = (front, self.buffer.get(1).copied()) {
             string.push('\n');
             self.buffer.drain(0..2);
             self.mark.index += 2;
@@ -178,18 +385,67 @@ impl<'r> Scanner<'r> {
         if !self.token_available {
             self.fetch_more_tokens()?;
         }
-        if let Some(token) = self.tokens.pop_front() {
+        if let Some(mut token) = self.tokens.pop_front() {
             self.token_available = false;
             self.tokens_parsed += 1;
             if let TokenData::StreamEnd = &token.data {
                 self.stream_end_produced = true;
             }
+            if let Some(text) = self.borrowed_value(&token) {
+                if let TokenData::Scalar { value, .. } = &mut token.data {
+                    *value = String::from(text);
+                }
+            }
             Ok(token)
         } else {
             unreachable!("no more tokens, but stream-end was not produced")
         }
     }
 
+    /// Scan the next token like [`Scanner::scan()`], together with the text of
+    /// the input that it spans.
+    ///
+    /// The text is only available when the input was set with
+    /// [`Scanner::set_input_str()`], which keeps the whole input, and is in
+    /// UTF-8. With other inputs, which are not kept once they are read, it is
+    /// `None`. Tokens that span no text, such as BLOCK-END, have an empty
+    /// text, and the text of a scalar includes its quotes or block scalar
+    /// header.
+    pub fn scan_with_source(&mut self) -> Result<(Token, Option<&'r str>)> {
+        let token = self.scan()?;
+        let source = self.source_text(token.start_mark, token.end_mark);
+        Ok((token, source))
+    }
+
+    /// The text of the input between two marks, if the input was set with
+    /// [`Scanner::set_input_str()`] and is in UTF-8.
+    pub(crate) fn source_text(&self, start_mark: Mark, end_mark: Mark) -> Option<&'r str> {
+        self.source
+            .filter(|_| self.encoding == Encoding::Utf8)
+            .and_then(|input| {
+                let offset = if input.as_bytes().starts_with(&BOM_UTF8) {
+                    BOM_UTF8.len()
+                } else {
+                    0
+                };
+                let start = offset + (start_mark.index - self.origin.index) as usize;
+                let end = offset + (end_mark.index - self.origin.index) as usize;
+                input.get(start..end)
+            })
+    }
+
+    /// The value of a plain scalar token that was left empty because it is
+    /// the text of the input it spans, see [`Scanner::scan_plain_scalar()`].
+    fn borrowed_value(&self, token: &Token) -> Option<&'r str> {
+        match &token.data {
+            TokenData::Scalar {
+                value,
+                style: ScalarStyle::Plain,
+            } if value.is_empty() => self.source_text(token.start_mark, token.end_mark),
+            _ => None,
+        }
+    }
+
     /// Equivalent of the libyaml `PEEK_TOKEN` macro, used by the parser.
     pub(crate) fn peek(&mut self) -> Result<&Token> {
         if self.token_available {
@@ -251,6 +507,47 @@ impl<'r> Scanner<'r> {
         Err(Error::scanner(context, context_mark, problem, self.mark))
     }
 
+    /// Check the length of the scalar being scanned, see
+    /// [`Scanner::set_max_scalar_len()`].
+    fn check_scalar_len(&self, context: &'static str, start_mark: Mark, len: usize) -> Result<()> {
+        if self
+            .max_scalar_len
+            .is_some_and(|max_scalar_len| len > max_scalar_len)
+        {
+            return Err(Error::scanner(
+                context,
+                start_mark,
+                "found a scalar longer than the maximum length",
+                self.mark,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Like [`Scanner::cache()`], in a plain scalar.
+    ///
+    /// When input fed in pieces runs out on a line that the buffered
+    /// characters only continue, the input waits for a character that may
+    /// end the scalar before it is scanned again, see
+    /// [`PushInput::wait_for_scalar_end()`].
+    fn cache_plain(&mut self, length: usize, leading_blanks: bool, indent: i32) -> Result<()> {
+        let result = self.cache(length);
+        if result.as_ref().is_err_and(Error::is_incomplete)
+            && !leading_blanks
+            && self.mark.column != 0
+            && (self.flow_level != 0 || self.mark.column as i32 >= indent)
+            && self.encoding == Encoding::Utf8
+            && self.max_scalar_len.is_none()
+            && self.max_input_len.is_none()
+            && self.buffer.iter().all(|&ch| continues_plain_scalar(ch))
+        {
+            if let Some(ReadHandler::Push(input)) = &mut self.read_handler {
+                input.wait_for_scalar_end();
+            }
+        }
+        result
+    }
+
     pub(crate) fn fetch_more_tokens(&mut self) -> Result<()> {
         let mut need_more_tokens;
         loop {
@@ -469,6 +766,12 @@ impl<'r> Scanner<'r> {
             return Ok(());
         }
         if self.indent < column as i32 {
+            if self.indent >= 0 {
+                let step = column as usize - self.indent as usize;
+                if let Some(count) = self.indent_steps.get_mut(step) {
+                    *count += 1;
+                }
+            }
             self.indents.push(self.indent);
             assert!(column <= i32::MAX as i64, "integer overflow");
             self.indent = column as i32;
@@ -1075,15 +1378,14 @@ impl<'r> Scanner<'r> {
         }
 
         self.cache(1)?;
-        if !IS_BLANKZ!(self.buffer) {
-            if self.flow_level == 0 || !CHECK!(self.buffer, ',') {
-                return self.set_scanner_error(
-                    "while scanning a tag",
-                    start_mark,
-                    "did not find expected whitespace or line break",
-                );
-            }
-            panic!("TODO: What is expected here?");
+        // In a flow collection, a tag may be directly followed by `,`, as in
+        // `[!tag, a]`, which tags an empty node.
+        if !IS_BLANKZ!(self.buffer) && (self.flow_level == 0 || !CHECK!(self.buffer, ',')) {
+            return self.set_scanner_error(
+                "while scanning a tag",
+                start_mark,
+                "did not find expected whitespace or line break",
+            );
         }
 
         let end_mark: Mark = self.mark;
@@ -1382,6 +1684,7 @@ impl<'r> Scanner<'r> {
             leading_blank = IS_BLANK!(self.buffer) as i32;
             while !IS_BREAKZ!(self.buffer) {
                 self.read_char(&mut string);
+                self.check_scalar_len("while scanning a block scalar", start_mark, string.len())?;
                 self.cache(1)?;
             }
             self.cache(2)?;
@@ -1401,6 +1704,7 @@ impl<'r> Scanner<'r> {
         if chomping == 1 {
             string.push_str(&trailing_breaks);
         }
+        self.check_scalar_len("while scanning a block scalar", start_mark, string.len())?;
 
         Ok(Token {
             data: TokenData::Scalar {
@@ -1446,6 +1750,7 @@ impl<'r> Scanner<'r> {
             }
             self.cache(2)?;
             self.read_line_break(breaks);
+            self.check_scalar_len("while scanning a block scalar", start_mark, breaks.len())?;
             *end_mark = self.mark;
         }
         if *indent == 0 {
@@ -1512,7 +1817,9 @@ impl<'r> Scanner<'r> {
                         break;
                     } else if !single && CHECK!(self.buffer, '\\') {
                         let mut code_length = 0usize;
-                        match self.buffer.get(1).copied().unwrap() {
+                        // A backslash at the end of the input is an unknown
+                        // escape, as it is followed by NUL in libyaml.
+                        match self.buffer.get(1).copied().unwrap_or('\0') {
                             '0' => {
                                 string.push('\0');
                             }
@@ -1630,6 +1937,7 @@ impl<'r> Scanner<'r> {
                         self.read_char(&mut string);
                     }
                 }
+                self.check_scalar_len("while scanning a quoted scalar", start_mark, string.len())?;
                 self.cache(2)?;
             }
             self.cache(1)?;
@@ -1654,6 +1962,11 @@ impl<'r> Scanner<'r> {
                         leading_blanks = true;
                     }
                 }
+                self.check_scalar_len(
+                    "while scanning a quoted scalar",
+                    start_mark,
+                    string.len() + whitespaces.len() + trailing_breaks.len(),
+                )?;
                 self.cache(1)?;
             }
             if leading_blanks {
@@ -1693,8 +2006,15 @@ impl<'r> Scanner<'r> {
         })
     }
 
+    /// Scan a plain scalar.
+    ///
+    /// When the input is kept by [`Scanner::set_input_str()`], the value of a
+    /// plain scalar on a single line is the text of the input it spans, so it
+    /// is not copied and the value of the token is left empty until it is
+    /// taken with [`Scanner::borrowed_value()`].
     fn scan_plain_scalar(&mut self) -> Result<Token> {
         let mut end_mark: Mark;
+        let mut borrowed = self.source.is_some() && self.encoding == Encoding::Utf8;
         let mut string = String::new();
         let mut leading_break = String::new();
         let mut trailing_breaks = String::new();
@@ -1704,7 +2024,7 @@ impl<'r> Scanner<'r> {
         end_mark = self.mark;
         let start_mark: Mark = end_mark;
         loop {
-            self.cache(4)?;
+            self.cache_plain(4, leading_blanks, indent)?;
             if self.mark.column == 0
                 && (CHECK_AT!(self.buffer, '-', 0)
                     && CHECK_AT!(self.buffer, '-', 1)
@@ -1746,6 +2066,10 @@ impl<'r> Scanner<'r> {
                 {
                     break;
                 }
+                if leading_blanks && borrowed {
+                    string.push_str(self.source_text(start_mark, end_mark).unwrap_or_default());
+                    borrowed = false;
+                }
                 if leading_blanks || !whitespaces.is_empty() {
                     if leading_blanks {
                         if leading_break.starts_with('\n') {
@@ -1768,14 +2092,24 @@ impl<'r> Scanner<'r> {
                         whitespaces.clear();
                     }
                 }
-                self.read_char(&mut string);
+                if borrowed {
+                    self.skip_char();
+                } else {
+                    self.read_char(&mut string);
+                }
                 end_mark = self.mark;
-                self.cache(2)?;
+                let len = if borrowed {
+                    (end_mark.index - start_mark.index) as usize
+                } else {
+                    string.len()
+                };
+                self.check_scalar_len("while scanning a plain scalar", start_mark, len)?;
+                self.cache_plain(2, leading_blanks, indent)?;
             }
             if !(IS_BLANK!(self.buffer) || IS_BREAK!(self.buffer)) {
                 break;
             }
-            self.cache(1)?;
+            self.cache_plain(1, leading_blanks, indent)?;
 
             while IS_BLANK!(self.buffer) || IS_BREAK!(self.buffer) {
                 if IS_BLANK!(self.buffer) {
@@ -1786,7 +2120,7 @@ impl<'r> Scanner<'r> {
                             start_mark,
                             "found a tab character that violates indentation",
                         );
-                    } else if !leading_blanks {
+                    } else if !leading_blanks && !borrowed {
                         self.read_char(&mut whitespaces);
                     } else {
                         self.skip_char();
@@ -1802,7 +2136,12 @@ impl<'r> Scanner<'r> {
                         leading_blanks = true;
                     }
                 }
-                self.cache(1)?;
+                self.check_scalar_len(
+                    "while scanning a plain scalar",
+                    start_mark,
+                    string.len() + whitespaces.len() + trailing_breaks.len(),
+                )?;
+                self.cache_plain(1, leading_blanks, indent)?;
             }
             if self.flow_level == 0 && (self.mark.column as i32) < indent {
                 break;
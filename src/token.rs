@@ -1,7 +1,9 @@
+use alloc::string::String;
+
 use crate::{Encoding, Mark, ScalarStyle};
 
 /// The token structure.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 #[non_exhaustive]
 pub struct Token {
     /// The token type.
@@ -12,7 +14,7 @@ pub struct Token {
     pub end_mark: Mark,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenData {
     /// A STREAM-START token.
     StreamStart {
@@ -0,0 +1,67 @@
+//! Checks that parsing malformed node properties fails cleanly, and frees
+//! everything that was allocated on the way.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+use libyaml_safer::{Document, Parser};
+
+struct CountingAllocator;
+
+static LIVE_BYTES: AtomicIsize = AtomicIsize::new(0);
+
+#[allow(unsafe_code)]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size() as isize, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const MALFORMED: &[&str] = &[
+    "&a &b x\n",
+    "!t !u x\n",
+    "&a !t &b x\n",
+    "!e!x y\n",
+    "- !e!x &a y\n",
+    "&a !t [x, *b\n",
+    "!<tag:example.com,2000:x\n",
+    "key: &a !t [x, &b !u {y: *c\n",
+    "- &a\n  !t\n    - &b !!str &c\n",
+    "%TAG !e! tag:example.com,2000:\n%TAG !e! tag:example.com,2000:\n--- !e!x y\n",
+    "? &a !t\n: &b !u\n  &c !v x: y\n z: &d\n",
+    "[&a !t x, !u &b : y, &c\n",
+];
+
+#[test]
+fn malformed_properties_do_not_leak() {
+    for input in MALFORMED {
+        let before = LIVE_BYTES.load(Ordering::SeqCst);
+        {
+            let mut parser = Parser::from_str(input);
+            assert!(
+                parser.by_ref().collect::<Result<Vec<_>, _>>().is_err(),
+                "{input:?} should fail to parse"
+            );
+        }
+        {
+            let mut parser = Parser::from_str(input);
+            while let Ok(mut document) = Document::load(&mut parser) {
+                assert!(
+                    document.get_root_node().is_some(),
+                    "{input:?} should fail to load"
+                );
+            }
+        }
+        let after = LIVE_BYTES.load(Ordering::SeqCst);
+        assert_eq!(before, after, "{input:?} leaked {} bytes", after - before);
+    }
+}